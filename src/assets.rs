@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf};
+use std::sync::OnceLock;
+use raylib::prelude::*;
+use raylib::core::audio::{Sound,Wave};
+use raylib::ffi;
+
+pub const ASSETS_ENV:&str = "BOMBER_MAN_ASSETS";
+const ASSETS_DIR:&str = "assets";
+
+// Every asset the game ships with, by its path inside the assets directory. A file of the same
+// name in the directory wins, so these only fill in what's missing.
+const BUILT_IN:[(&str,&[u8]);11] = [
+    ("spritesheet.png",include_bytes!("../assets/spritesheet.png")),
+    ("spritesheet.json",include_bytes!("../assets/spritesheet.json")),
+    ("sounds/exp.ogg",include_bytes!("../assets/sounds/exp.ogg")),
+    ("sounds/bonus.ogg",include_bytes!("../assets/sounds/bonus.ogg")),
+    ("sounds/upgrade.ogg",include_bytes!("../assets/sounds/upgrade.ogg")),
+    ("sounds/gameover.ogg",include_bytes!("../assets/sounds/gameover.ogg")),
+    ("sounds/win.ogg",include_bytes!("../assets/sounds/win.ogg")),
+    ("sounds/burning.ogg",include_bytes!("../assets/sounds/burning.ogg")),
+    ("sounds/punch.ogg",include_bytes!("../assets/sounds/punch.ogg")),
+    ("maps/crossroads.map",include_bytes!("../assets/maps/crossroads.map")),
+    ("maps/fortress.map",include_bytes!("../assets/maps/fortress.map")),
+];
+
+static DIR:OnceLock<Option<PathBuf>> = OnceLock::new();
+
+// $BOMBER_MAN_ASSETS, then `assets` next to the executable, then in the working directory.
+// None leaves only the built-in files.
+fn find_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(ASSETS_ENV) {
+        let dir = PathBuf::from(dir);
+        if dir.is_dir() {
+            return Some(dir);
+        }
+        eprintln!("{} `{}` is not a directory, ignoring it",ASSETS_ENV,dir.display());
+    }
+    let beside_exe = env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.join(ASSETS_DIR)));
+    beside_exe.into_iter().chain([PathBuf::from(ASSETS_DIR)]).find(|dir| dir.is_dir())
+}
+
+// The directory assets are read from, found on first use unless `use_dir` set one.
+pub fn dir() -> Option<&'static Path> {
+    DIR.get_or_init(find_dir).as_deref()
+}
+
+// For `--assets <dir>`, has to come before the first asset is read.
+pub fn use_dir(dir:PathBuf) -> io::Result<()> {
+    if !dir.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: not a directory", dir.display())));
+    }
+    if DIR.set(Some(dir)).is_err() {
+        eprintln!("the assets directory is already in use, ignoring `--assets`");
+    }
+    Ok(())
+}
+
+// An asset from the assets directory, or the built-in copy when the directory doesn't have it.
+pub fn read(name:&str) -> io::Result<Cow<'static,[u8]>> {
+    read_from(dir(), name)
+}
+
+fn read_from(dir:Option<&Path>,name:&str) -> io::Result<Cow<'static,[u8]>> {
+    if let Some(path) = dir.map(|dir| dir.join(name)).filter(|path| path.is_file()) {
+        return fs::read(&path).map(Cow::Owned).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)));
+    }
+    match BUILT_IN.iter().find(|(built_in,_)| *built_in == name) {
+        Some((_,bytes)) => Ok(Cow::Borrowed(*bytes)),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: no such asset", name))),
+    }
+}
+
+pub fn read_text(name:&str) -> io::Result<String> {
+    let bytes = read(name)?;
+    String::from_utf8(bytes.into_owned()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{}: not UTF-8 text", name)))
+}
+
+// Dotted extension raylib picks the decoder by.
+fn file_type(name:&str) -> String {
+    Path::new(name).extension().map_or(String::new(), |ext| format!(".{}", ext.to_string_lossy()))
+}
+
+pub fn load_texture(rl:&mut RaylibHandle,thread:&RaylibThread,name:&str) -> Result<Texture2D,String> {
+    let bytes = read(name).map_err(|err| err.to_string())?.into_owned();
+    let image = Image::load_image_from_mem(&file_type(name), &bytes, bytes.len() as i32).map_err(|err| format!("{}: {}", name, err))?;
+    rl.load_texture_from_image(thread, &image).map_err(|err| format!("{}: {}", name, err))
+}
+
+// A sound effect, or None with a warning when there's no audio device or the file won't decode,
+// so the game plays on without it.
+pub fn load_sound(audio:&RaylibAudio,name:&str) -> Option<Sound> {
+    if !audio.is_audio_device_ready() {
+        return None;
+    }
+    let bytes = match read(name) {
+        Ok(bytes) => bytes,
+        Err(err) => {eprintln!("no sound: {}",err); return None}
+    };
+    let file_type = CString::new(file_type(name)).unwrap_or_default();
+    // raylib-rs has no safe wrapper for decoding a sound from memory.
+    let wave = unsafe { ffi::LoadWaveFromMemory(file_type.as_ptr(), bytes.as_ptr(), bytes.len() as i32) };
+    if wave.data.is_null() {
+        eprintln!("no sound: {}: not an audio file raylib can decode",name);
+        return None;
+    }
+    let wave = unsafe { Wave::from_raw(wave) };
+    match Sound::load_sound_from_wave(&wave) {
+        Ok(sound) => Some(sound),
+        Err(err) => {eprintln!("no sound: {}: {}",name,err); None}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET:&str = "spritesheet.json";
+
+    fn built_in(name:&str) -> &'static [u8] {
+        BUILT_IN.iter().find(|(built_in,_)| *built_in == name).unwrap().1
+    }
+
+    #[test]
+    fn without_the_file_the_built_in_one_is_read(){
+        let missing = env::temp_dir().join(format!("bomber_man_no_assets_{}", std::process::id()));
+        assert_eq!(read_from(Some(&missing), SHEET).unwrap(), built_in(SHEET));
+        assert_eq!(read_from(None, SHEET).unwrap(), built_in(SHEET));
+        assert_eq!(read_from(None, "nope.png").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn a_file_in_the_directory_wins(){
+        let dir = env::temp_dir().join(format!("bomber_man_assets_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(SHEET), "{}").unwrap();
+        let (sheet,sound) = (read_from(Some(&dir), SHEET), read_from(Some(&dir), "sounds/exp.ogg"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sheet.unwrap(), &b"{}"[..]);
+        assert_eq!(sound.unwrap(), built_in("sounds/exp.ogg"), "the rest still come built in");
+    }
+}
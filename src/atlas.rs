@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::OnceLock;
+use raylib::prelude::*;
+use serde::Deserialize;
+use crate::assets;
+use crate::grid::TILE_SIZE;
+use crate::objects::MAX_WALL_FRAMES;
+use crate::player::MAX_PLAYER_FRAME;
+
+// The manifest shipped with the game, used until one is loaded from disk and by runs without a window.
+const BUILT_IN:&str = include_str!("../assets/spritesheet.json");
+const FLOOR_FRAMES:usize = 5; // Plain floor, then shadows cast by a wall above, left, both and the corner.
+
+static ATLAS:OnceLock<Atlas> = OnceLock::new();
+
+// How the game steps through a clip.
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum Kind {
+    Exact(usize), // Picked by a frame number the simulation keeps, so the count is fixed and so is the timing.
+    Any, // Picked or rolled by the game, any number of frames and no timing.
+    Loop, // Looped on its own at `duration` seconds a frame.
+}
+
+// Every clip the game draws, a manifest has exactly these.
+const CLIPS:&[(&str,Kind)] = &[
+    ("wall",Kind::Exact(MAX_WALL_FRAMES)),
+    ("block",Kind::Any),
+    ("floor",Kind::Exact(FLOOR_FRAMES)),
+    ("shadow",Kind::Any),
+    ("grass",Kind::Any),
+    ("player_down",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("player_left",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("player_up",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("player_right",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("player_death",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("player_spawn",Kind::Exact(MAX_PLAYER_FRAME)),
+    ("bomb",Kind::Any),
+    ("black_bomb",Kind::Any),
+    ("blue_bomb",Kind::Any),
+    ("purple_bomb",Kind::Any),
+    ("red_bomb",Kind::Any),
+    ("explosion",Kind::Loop),
+    ("flame_left_end",Kind::Loop),
+    ("flame_left_mid",Kind::Loop),
+    ("flame_right_mid",Kind::Loop),
+    ("flame_right_end",Kind::Loop),
+    ("flame_top_end",Kind::Loop),
+    ("flame_top_mid",Kind::Loop),
+    ("flame_down_mid",Kind::Loop),
+    ("flame_down_end",Kind::Loop),
+    ("orc_left",Kind::Loop),
+    ("orc_down",Kind::Loop),
+    ("orc_right",Kind::Loop),
+    ("orc_up",Kind::Loop),
+    ("skull_left",Kind::Loop),
+    ("skull_down",Kind::Loop),
+    ("skull_right",Kind::Loop),
+    ("skull_up",Kind::Loop),
+    ("heart",Kind::Loop),
+    ("gold_coin",Kind::Loop),
+    ("silver_coin",Kind::Loop),
+    ("diamond",Kind::Loop),
+    ("cash",Kind::Loop),
+    ("countdown",Kind::Any),
+    ("countdown_flash",Kind::Any),
+    ("game_over",Kind::Loop),
+    ("paused",Kind::Loop),
+];
+
+// Manifest layout, `size` defaults to one tile and only looping clips carry a `duration`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    clips:HashMap<String,ClipDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClipDef {
+    #[serde(default = "tile")]
+    size:[f32;2],
+    frames:Vec<[f32;2]>,
+    duration:Option<f32>,
+}
+
+fn tile() -> [f32;2] {
+    [TILE_SIZE,TILE_SIZE]
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Clip {
+    pub frames:Vec<Rectangle>, // Never empty.
+    pub duration:f32, // Seconds a frame, 0 for clips the game times itself.
+}
+
+// Named clips on the sprite sheet, read from the manifest next to it.
+#[derive(Clone,PartialEq,Debug)]
+pub struct Atlas {
+    name:String,
+    clips:HashMap<String,Clip>,
+}
+
+// The atlas in use, the built-in one until `use_atlas` puts another in.
+pub fn atlas() -> &'static Atlas {
+    ATLAS.get_or_init(|| Atlas::parse("built-in atlas", BUILT_IN).expect("the built-in atlas is valid"))
+}
+
+// Has to come before anything is made from the sheet, later calls are reported and ignored.
+pub fn use_atlas(atlas:Atlas){
+    if let Err(atlas) = ATLAS.set(atlas) {
+        eprintln!("{}: the sprite atlas is already in use, ignoring it",atlas.name);
+    }
+}
+
+impl Atlas {
+    pub fn load(name:&str) -> io::Result<Self> {
+        let text = assets::read_text(name)?;
+        Atlas::parse(name, &text)
+    }
+
+    // Errors read `name: clip `x`: what`, or `name: what` for the manifest as a whole.
+    pub fn parse(name:&str,text:&str) -> io::Result<Self> {
+        let fail = |msg:String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, msg));
+        let manifest:Manifest = serde_json::from_str(text).map_err(|err| fail(err.to_string()))?;
+        if let Some(clip) = manifest.clips.keys().find(|clip| !CLIPS.iter().any(|(name,_)| name == clip)) {
+            return Err(fail(format!("unknown clip `{}`", clip)));
+        }
+        let mut clips = HashMap::new();
+        for &(clip,kind) in CLIPS {
+            let def = manifest.clips.get(clip).ok_or_else(|| fail(format!("missing clip `{}`", clip)))?;
+            let invalid = |msg:String| fail(format!("clip `{}`: {}", clip, msg));
+            if def.frames.is_empty() {
+                return Err(invalid("no frames".to_string()));
+            }
+            if def.size[0] <= 0_f32 || def.size[1] <= 0_f32 {
+                return Err(invalid(format!("bad size {}x{}", def.size[0], def.size[1])));
+            }
+            if def.frames.iter().any(|&[x,y]| x < 0_f32 || y < 0_f32) {
+                return Err(invalid("frames start at 0,0".to_string()));
+            }
+            if let Kind::Exact(count) = kind {
+                if def.frames.len() != count {
+                    return Err(invalid(format!("needs exactly {} frames, found {}", count, def.frames.len())));
+                }
+            }
+            let duration = match (kind,def.duration) {
+                (Kind::Loop,Some(duration)) if duration > 0_f32 => duration,
+                (Kind::Loop,_) => return Err(invalid("needs a positive `duration` in seconds a frame".to_string())),
+                (_,Some(_)) => return Err(invalid("the game times this clip, drop its `duration`".to_string())),
+                (_,None) => 0_f32,
+            };
+            let [width,height] = def.size;
+            let frames = def.frames.iter().map(|&[x,y]| Rectangle::new(x, y, width, height)).collect();
+            clips.insert(clip.to_string(), Clip { frames, duration });
+        }
+        Ok(Self { name: name.to_string(), clips })
+    }
+
+    // Every frame has to lie on the sheet, checked once the texture is loaded.
+    pub fn check_sheet(&self,width:i32,height:i32) -> io::Result<()> {
+        for &(clip,_) in CLIPS {
+            for (n,rec) in self.clips[clip].frames.iter().enumerate() {
+                if rec.x + rec.width > width as f32 || rec.y + rec.height > height as f32 {
+                    let msg = format!("{}: clip `{}`: frame {} at {},{} runs off the {}x{} sheet", self.name, clip, n+1, rec.x, rec.y, width, height);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clip(&self,name:&str) -> &Clip {
+        &self.clips[name]
+    }
+
+    // Frame `n` of a clip, wrapping past the end.
+    pub fn frame(&self,name:&str,n:usize) -> Rectangle {
+        let frames = &self.clip(name).frames;
+        frames[n % frames.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json,Value};
+
+    // The built-in manifest with one clip swapped for `def`, or dropped for Null.
+    fn with_clip(clip:&str,def:Value) -> String {
+        let mut manifest:Value = serde_json::from_str(BUILT_IN).unwrap();
+        let clips = manifest["clips"].as_object_mut().unwrap();
+        match def {
+            Value::Null => {clips.remove(clip);}
+            def => {clips.insert(clip.to_string(), def);}
+        }
+        manifest.to_string()
+    }
+
+    fn error(text:&str) -> String {
+        Atlas::parse("test", text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn built_in_manifest_parses(){
+        let atlas = Atlas::parse("built-in atlas", BUILT_IN).unwrap();
+        for &(clip,kind) in CLIPS {
+            let frames = atlas.clip(clip).frames.len();
+            match kind {
+                Kind::Exact(count) => assert_eq!(frames, count, "{}", clip),
+                Kind::Any => assert!(frames > 0, "{}", clip),
+                Kind::Loop => assert!(atlas.clip(clip).duration > 0_f32, "{}", clip),
+            }
+        }
+        assert_eq!(atlas.frame("shadow", 0), Rectangle::new(80_f32, 32_f32, TILE_SIZE, TILE_SIZE));
+    }
+
+    #[test]
+    fn frames_wrap_past_the_end(){
+        let atlas = Atlas::parse("test", BUILT_IN).unwrap();
+        assert_eq!(atlas.frame("wall", MAX_WALL_FRAMES + 1), atlas.frame("wall", 1));
+    }
+
+    #[test]
+    fn rejects_unknown_clips(){
+        assert_eq!(error(&with_clip("ghost", json!({"frames": [[0, 0]]}))), "test: unknown clip `ghost`");
+    }
+
+    #[test]
+    fn rejects_missing_clips(){
+        assert_eq!(error(&with_clip("shadow", Value::Null)), "test: missing clip `shadow`");
+    }
+
+    #[test]
+    fn rejects_unknown_fields(){
+        assert!(error(&with_clip("shadow", json!({"frames": [[80, 32]], "speed": 1}))).starts_with("test: unknown field `speed`"));
+    }
+
+    #[test]
+    fn rejects_a_clip_without_frames(){
+        assert_eq!(error(&with_clip("block", json!({"frames": []}))), "test: clip `block`: no frames");
+    }
+
+    #[test]
+    fn rejects_a_non_positive_size(){
+        assert_eq!(error(&with_clip("block", json!({"size": [0, 16], "frames": [[0, 16]]}))), "test: clip `block`: bad size 0x16");
+        assert_eq!(error(&with_clip("block", json!({"size": [16, -1], "frames": [[0, 16]]}))), "test: clip `block`: bad size 16x-1");
+    }
+
+    #[test]
+    fn rejects_negative_frames(){
+        assert_eq!(error(&with_clip("block", json!({"frames": [[0, 16], [-16, 16]]}))), "test: clip `block`: frames start at 0,0");
+    }
+
+    #[test]
+    fn rejects_the_wrong_frame_count_for_exact_clips(){
+        let msg = format!("test: clip `wall`: needs exactly {} frames, found 1", MAX_WALL_FRAMES);
+        assert_eq!(error(&with_clip("wall", json!({"frames": [[0, 0]]}))), msg);
+    }
+
+    #[test]
+    fn rejects_a_duration_on_clips_the_game_times(){
+        let msg = "test: clip `block`: the game times this clip, drop its `duration`";
+        assert_eq!(error(&with_clip("block", json!({"frames": [[0, 16]], "duration": 0.1}))), msg);
+    }
+
+    #[test]
+    fn looping_clips_need_a_positive_duration(){
+        let msg = "test: clip `heart`: needs a positive `duration` in seconds a frame";
+        assert_eq!(error(&with_clip("heart", json!({"frames": [[0, 0]]}))), msg);
+        assert_eq!(error(&with_clip("heart", json!({"frames": [[0, 0]], "duration": 0}))), msg);
+    }
+
+    #[test]
+    fn frames_have_to_lie_on_the_sheet(){
+        let atlas = Atlas::parse("test", &with_clip("shadow", json!({"frames": [[80, 32], [400, 32]]}))).unwrap();
+        assert!(atlas.check_sheet(416, 400).is_ok());
+        let err = atlas.check_sheet(400, 400).unwrap_err().to_string();
+        assert_eq!(err, "test: clip `shadow`: frame 2 at 400,32 runs off the 400x400 sheet");
+    }
+}
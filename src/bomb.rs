@@ -1,42 +1,25 @@
 use raylib::prelude::*;
-use crate::grid::{TILE_SIZE, MAX_FRAME,ANIM_DURATION,O,FRAMES,SCALED_TILE};
+use crate::atlas::atlas;
+use crate::grid::{O,TILE_SIZE};
 use crate::{impl_exp,impl_set_position,impl_static_draw};
 use crate::objects::{State};
+use crate::cell::*;
+use serde::{Serialize,Deserialize};
+use crate::save::RectangleDef;
 
 pub const BOMB_POWER_LEVEL:[usize;3] = [2,7,10];
 pub const MAX_BOMB_POWER:usize = BOMB_POWER_LEVEL[BOMB_POWER_LEVEL.len()-1];
-const EXP_TIME:f32 = 7_f32;
-
-const BOMB_Y:f32 = 96_f32;
-const F_TOP_END_Y:f32 = 320_f32;
-const F_TOP_MID_Y:f32 = 336_f32;
-const F_DOWN_MID_Y:f32 = 368_f32;  
-const F_DOWN_END_Y:f32 = 384_f32;
-const EXP_FLAME_LR_Y:f32 = 352_f32;
-
-pub const EXPLOSION:i8 = -1;
-pub const FLAME_MID_LEFT:i8 = -2; 
-pub const FLAME_MID_RIGHT:i8 = -3;
-pub const FLAME_MID_TOP:i8 = -4;
-pub const FLAME_MID_DOWN:i8 = -5;
-pub const FLAME_END_LEFT:i8 = -6;
-pub const FLAME_END_RIGHT:i8 = -7;
-pub const FLAME_END_TOP:i8 = -8;
-pub const FLAME_END_DOWN:i8 = -9;
-
-const EXP_TD_FRAMES:[f32;4] = [32_f32,112_f32,192_f32,272_f32];
-const F_LEFT_END_FRAMES:[f32;4] = [0_f32,80_f32,160_f32,240_f32];
-const F_RIGHT_END_FRAMES:[f32;4] = [64_f32,144_f32,224_f32,304_f32];
-const F_LEFT_MID_FRAMES:[f32;4] = [16_f32,96_f32,176_f32,256_f32];
-const F_RIGHT_MID_FRAMES:[f32;4] = [48_f32,128_f32,208_f32,288_f32];
+pub const EXP_TIME:f32 = 7_f32;
 
 // ADD A CENTER FLAME OBJ FOR CHAIN EXPLOSION
 
 macro_rules! flame_obj {
     ($name:ident) => {
-      #[derive(PartialEq,Clone,Debug,Copy)]
+      #[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
        pub struct $name {
+         #[serde(with = "RectangleDef")]
          pub rec:Rectangle,
+         #[serde(with = "RectangleDef")]
          pub rec2:Rectangle,
          pub frames:usize,
          pub time:f32,
@@ -46,14 +29,14 @@ macro_rules! flame_obj {
 }
 
 macro_rules! impl_new {
-    ($name:ident,$frames_arr:expr,$y:expr) => {
+    ($name:ident,$clip:literal) => {
         impl $name {
             pub fn new() -> Self{
                 let frames = 0;
                 let time = 0_f32;
                 let state = State::IDEAL;
-                let rec = Rectangle::new($frames_arr[frames],$y,TILE_SIZE,TILE_SIZE);
-                let rec2 = Rectangle::new(O,O,SCALED_TILE,SCALED_TILE);
+                let rec = atlas().frame($clip, frames);
+                let rec2 = Rectangle::new(O,O,TILE_SIZE,TILE_SIZE);
                 Self{rec,rec2,frames,time,state}
             }
         }
@@ -61,27 +44,28 @@ macro_rules! impl_new {
 }
 
 macro_rules! impl_draw_anim {
-    ($name:ident,$fn_name:ident,$fn_name1:ident,$frames:ident,$rec:ident,$rec2:ident,$time:ident,$frame_arr:expr,$check:expr) => {
+    ($name:ident,$fn_name:ident,$fn_name1:ident,$frames:ident,$rec:ident,$rec2:ident,$time:ident,$clip:literal,$check:expr) => {
         
 impl $name {
 
-    pub fn $fn_name(&mut self,texture:&Texture2D,d:&mut RaylibDrawHandle,i:usize,j:usize,cell:i8){
-        if cell == $check {
-        self.$rec2.x = i as f32 * SCALED_TILE;
-        self.$rec2.y = j as f32 * SCALED_TILE;
+    pub fn $fn_name(&mut self,texture:&Texture2D,d:&mut impl RaylibDraw,i:usize,j:usize,flame:Flame){
+        if flame == $check {
+        self.$rec2.x = i as f32 * TILE_SIZE;
+        self.$rec2.y = j as f32 * TILE_SIZE;
         d.draw_texture_pro(texture, self.$rec, self.$rec2, Vector2::default(),O, Color::WHITE);  
         }
     }
 
-    pub fn $fn_name1(&mut self,cell:i8,frame_time:f32){
-      if cell == $check {
-        if self.$time > ANIM_DURATION {
+    pub fn $fn_name1(&mut self,flame:Flame,frame_time:f32){
+      if flame == $check {
+        let clip = atlas().clip($clip);
+        if self.$time > clip.duration {
             self.$time = 0_f32;
             self.$frames += 1;
         }
         self.$time += frame_time;
-        self.$frames %= $frame_arr.len();
-        self.$rec.x = $frame_arr[self.$frames];
+        self.$frames %= clip.frames.len();
+        self.$rec = clip.frames[self.$frames];
       }
     }
   }
@@ -99,36 +83,40 @@ flame_obj!(FlameRightMid);
 flame_obj!(FlameTopMid);
 flame_obj!(FlameDownMid);
 
-impl_new!(FlameLeftEnd,F_LEFT_END_FRAMES,EXP_FLAME_LR_Y);
-impl_new!(FlameRightEnd,F_RIGHT_END_FRAMES,EXP_FLAME_LR_Y);
-impl_new!(FlameTopEnd,EXP_TD_FRAMES,F_TOP_END_Y);
-impl_new!(FlameDownEnd,EXP_TD_FRAMES,F_DOWN_END_Y);
+impl_new!(FlameLeftEnd,"flame_left_end");
+impl_new!(FlameRightEnd,"flame_right_end");
+impl_new!(FlameTopEnd,"flame_top_end");
+impl_new!(FlameDownEnd,"flame_down_end");
 
-impl_new!(FlameLeftMid,F_LEFT_MID_FRAMES,EXP_FLAME_LR_Y);
-impl_new!(FlameRightMid,F_RIGHT_MID_FRAMES,EXP_FLAME_LR_Y);
-impl_new!(FlameTopMid,EXP_TD_FRAMES,F_TOP_MID_Y);
-impl_new!(FlameDownMid,EXP_TD_FRAMES,F_DOWN_MID_Y);
+impl_new!(FlameLeftMid,"flame_left_mid");
+impl_new!(FlameRightMid,"flame_right_mid");
+impl_new!(FlameTopMid,"flame_top_mid");
+impl_new!(FlameDownMid,"flame_down_mid");
 
-impl_draw_anim!(FlameLeftEnd,draw,anim,frames,rec,rec2,time,F_LEFT_END_FRAMES,FLAME_END_LEFT);
-impl_draw_anim!(FlameRightEnd,draw,anim,frames,rec,rec2,time,F_RIGHT_END_FRAMES,FLAME_END_RIGHT);
-impl_draw_anim!(FlameTopEnd,draw,anim,frames,rec,rec2,time,EXP_TD_FRAMES,FLAME_END_TOP);
-impl_draw_anim!(FlameDownEnd,draw,anim,frames,rec,rec2,time,EXP_TD_FRAMES,FLAME_END_DOWN);
+impl_draw_anim!(FlameLeftEnd,draw,anim,frames,rec,rec2,time,"flame_left_end",Flame::EndLeft);
+impl_draw_anim!(FlameRightEnd,draw,anim,frames,rec,rec2,time,"flame_right_end",Flame::EndRight);
+impl_draw_anim!(FlameTopEnd,draw,anim,frames,rec,rec2,time,"flame_top_end",Flame::EndTop);
+impl_draw_anim!(FlameDownEnd,draw,anim,frames,rec,rec2,time,"flame_down_end",Flame::EndDown);
 
-impl_draw_anim!(FlameLeftMid,draw,anim,frames,rec,rec2,time,F_LEFT_MID_FRAMES,FLAME_MID_LEFT);
-impl_draw_anim!(FlameRightMid,draw,anim,frames,rec,rec2,time,F_RIGHT_MID_FRAMES,FLAME_MID_RIGHT);
-impl_draw_anim!(FlameTopMid,draw,anim,frames,rec,rec2,time,EXP_TD_FRAMES,FLAME_MID_TOP);
-impl_draw_anim!(FlameDownMid,draw,anim,frames,rec,rec2,time,EXP_TD_FRAMES,FLAME_MID_DOWN);
+impl_draw_anim!(FlameLeftMid,draw,anim,frames,rec,rec2,time,"flame_left_mid",Flame::MidLeft);
+impl_draw_anim!(FlameRightMid,draw,anim,frames,rec,rec2,time,"flame_right_mid",Flame::MidRight);
+impl_draw_anim!(FlameTopMid,draw,anim,frames,rec,rec2,time,"flame_top_mid",Flame::MidTop);
+impl_draw_anim!(FlameDownMid,draw,anim,frames,rec,rec2,time,"flame_down_mid",Flame::MidDown);
 
-impl_set_position!(Bomb,set_position,rec2,SCALED_TILE);
+impl_set_position!(Bomb,set_position,rec2,TILE_SIZE);
 impl_static_draw!(Bomb);
-impl_draw_anim!(Bomb,draw_exp,anim_exp,exp_frames,exp_rec,exp_rec2,exp_time,EXP_TD_FRAMES,EXPLOSION);
+impl_draw_anim!(Bomb,draw_exp,anim_exp,exp_frames,exp_rec,exp_rec2,exp_time,"explosion",Flame::Explosion);
 impl_exp!(Bomb,chain_exp);
 
-#[derive(PartialEq,Clone,Debug,Copy)]
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
 pub struct Bomb {
+    #[serde(with = "RectangleDef")]
     pub rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub exp_rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub exp_rec:Rectangle,
     pub exp_frames:usize,
     pub frames:usize,
@@ -136,23 +124,25 @@ pub struct Bomb {
     pub exp_time:f32,
     pub exploading_time:f32,
     pub power:usize,
+    pub owner:usize, // Slot of the player who planted it.
     pub state:State,
 }
 
 impl Bomb {
     pub fn new() -> Self{
-        let rec2 = Rectangle::new(O,O,SCALED_TILE,SCALED_TILE);
-        let rec = Rectangle::new(O,BOMB_Y,TILE_SIZE,TILE_SIZE);
+        let rec2 = Rectangle::new(O,O,TILE_SIZE,TILE_SIZE);
+        let rec = atlas().frame("bomb", 0);
         let frames = 0;
         let time = 0_f32;
         let exp_time = 0_f32;
         let exploading_time = EXP_TIME;
         let exp_frames = 0;
-        let exp_rec2 = Rectangle::new(O,O,SCALED_TILE,SCALED_TILE);
-        let exp_rec = Rectangle::new(EXP_TD_FRAMES[exp_frames],EXP_FLAME_LR_Y,TILE_SIZE,TILE_SIZE);
+        let exp_rec2 = Rectangle::new(O,O,TILE_SIZE,TILE_SIZE);
+        let exp_rec = atlas().frame("explosion", exp_frames);
         let power = 1;
+        let owner = 0;
         let state = State::IDEAL;
-        Self { rec2,exp_rec2, rec,exp_rec, exp_frames, frames, time,exp_time,exploading_time,power,state}
+        Self { rec2,exp_rec2, rec,exp_rec, exp_frames, frames, time,exp_time,exploading_time,power,owner,state}
     }
   
     pub fn explode(&mut self){
@@ -163,30 +153,24 @@ impl Bomb {
      }  
     }
 
-    pub fn play_sound(&self,audio:&mut RaylibAudio,exp_sound:&Sound){
-       if self.state == State::EXPLOADING {
-         audio.play_sound(exp_sound);
-       }
-    }
-
     pub fn detonating(&mut self,frame_time:f32){
       self.exploading_time -= frame_time;
     }
 
     fn animate(&mut self,frame_time:f32){
         if self.state == State::IDEAL{
-        if self.time > (EXP_TIME/MAX_FRAME as f32){
+        let fuse = atlas().clip("bomb"); // Spread over the time it takes to go off.
+        if self.time > (EXP_TIME/fuse.frames.len() as f32){
             self.time = 0_f32;
             self.frames += 1;
         }
         self.time += frame_time;
-        self.rec.x = FRAMES[self.frames];
-        self.frames = self.frames  % MAX_FRAME;
+        self.frames %= fuse.frames.len();
+        self.rec = fuse.frames[self.frames];
      }
     }
 
-    pub fn update(&mut self,frame_time:f32,audio:&mut RaylibAudio,sound:&Sound){
-        self.play_sound(audio, sound);
+    pub fn update(&mut self,frame_time:f32){
         self.animate(frame_time);
         self.detonating(frame_time);
         self.explode();
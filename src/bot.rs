@@ -0,0 +1,425 @@
+use crate::sim::*;
+use crate::grid::*;
+use crate::player::*;
+use crate::path::*;
+use crate::cell::*;
+use crate::bomb::EXP_TIME;
+use serde::{Serialize,Deserialize};
+
+// CPU players for battles. A bot looks at the same Sim a human looks at and answers with an
+// Input each tick, so it plays by the player's rules and its games record and replay like any other.
+
+const TILE_TIME:f32 = TILE_SIZE / SPEED; // Seconds to walk one tile.
+const WANDER_RANGE:u32 = 4; // With nothing to do a bot strolls to a safe tile this close.
+const PLANT_SLACK:f32 = 1_f32; // Extra seconds a way out must leave before a bomb is planted, walking up and lining up take time.
+const TIE_RANGE:u32 = 1; // Tiles this much farther than the nearest choice are as good.
+const MATCH_TIME:f32 = 300_f32; // Headless matches still going after this are a draw.
+
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+pub const DIFFICULTIES:[Difficulty;3] = [Difficulty::Easy,Difficulty::Normal,Difficulty::Hard];
+
+struct Skill {
+    think_ticks:usize, // Ticks between plans, how long it takes to notice an opening.
+    margin:f32, // Seconds it wants between crossing a tile and the flame reaching it.
+    hunt:bool, // Goes after opponents once there is nothing left to dig.
+    hunt_first:bool, // ...even while walls are left.
+    trap:bool, // Looks for spots where the blast leaves an opponent nowhere to go.
+    best_bomb:bool, // Switches to the strongest bomb in stock.
+    loot_range:u32, // Farthest item it walks over for, in tiles.
+    idle_chance:u64, // One in this many plans it just stands there, 0 for never.
+}
+
+// Indexed by Difficulty.
+const SKILLS:[Skill;3] = [
+    Skill { think_ticks: 40, margin: 0.2, hunt: false, hunt_first: false, trap: false, best_bomb: false, loot_range: 4, idle_chance: 3 },
+    Skill { think_ticks: 12, margin: 0.4, hunt: true, hunt_first: false, trap: false, best_bomb: true, loot_range: 10, idle_chance: 0 },
+    Skill { think_ticks: 3, margin: 0.5, hunt: true, hunt_first: true, trap: true, best_bomb: true, loot_range: 20, idle_chance: 0 },
+];
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name:&str) -> Option<Self> {
+        DIFFICULTIES.iter().copied().find(|difficulty| difficulty.name() == name)
+    }
+
+    fn skill(&self) -> &'static Skill {
+        &SKILLS[*self as usize]
+    }
+}
+
+// Tiles a bot can walk to without being caught by a flame on the way.
+fn reach(cells:&[Vec<Cell>],danger:&DangerMap,from:Position,margin:f32) -> FloodFill {
+    FloodFill::search(cells, from, |(i,j),steps| bomber_cost(&cells[i][j]).is_some() && danger[i][j] > steps as f32 * TILE_TIME + margin)
+}
+
+// Whether a bomb of `power` on `from` puts flame on `to`, held back by what holds back a real blast.
+fn in_blast(grid:&Grid,from:Position,power:usize,to:Position) -> bool {
+    if from.0 != to.0 && from.1 != to.1 {
+        return false;
+    }
+    let steps = from.0.abs_diff(to.0) + from.1.abs_diff(to.1);
+    let (di,dj) = (to.0 as isize - from.0 as isize,to.1 as isize - from.1 as isize);
+    steps <= power && (1..steps).all(|k| {
+        let i = (from.0 as isize + di.signum() * k as isize) as usize;
+        let j = (from.1 as isize + dj.signum() * k as isize) as usize;
+        !grid.stops_blast(i, j)
+    })
+}
+
+// The danger map as it would be with one more bomb on `at`.
+fn with_bomb(grid:&Grid,danger:&DangerMap,at:Position,power:usize) -> DangerMap {
+    let mut danger = danger.clone();
+    let fuse = danger[at.0][at.1].min(EXP_TIME);
+    for (i,rows) in danger.iter_mut().enumerate() {
+        for (j,time) in rows.iter_mut().enumerate() {
+            if in_blast(grid, at, power, (i,j)) {
+                *time = time.min(fuse);
+            }
+        }
+    }
+    danger
+}
+
+fn is_safe(danger:&DangerMap,(i,j):Position) -> bool {
+    danger[i][j].is_infinite()
+}
+
+fn next_to_wall(cells:&[Vec<Cell>],(i,j):Position) -> bool {
+    [(i-1,j),(i+1,j),(i,j-1),(i,j+1)].iter().any(|&(r,c)| cells[r][c].terrain == Terrain::Wall)
+}
+
+pub struct Bot {
+    pub slot:usize,
+    pub difficulty:Difficulty,
+    path:Vec<Position>, // Tiles still to walk.
+    goal:Option<Position>, // Where the last plan led, kept while it stays among the best choices.
+    plant:bool, // Drop a bomb once the path is walked.
+    wait:usize, // Ticks until the next plan.
+    rand:u64,
+}
+
+impl Bot {
+    pub fn new(slot:usize,difficulty:Difficulty,seed:u64) -> Self {
+        let path = vec![];
+        let goal = None;
+        let plant = false;
+        let wait = 0;
+        let rand = (seed ^ (slot as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15)) | 1;
+        Self { slot, difficulty, path, goal, plant, wait, rand }
+    }
+
+    fn next_rand(&mut self) -> u64 { // xorshift64
+        self.rand ^= self.rand << 13;
+        self.rand ^= self.rand >> 7;
+        self.rand ^= self.rand << 17;
+        self.rand
+    }
+
+    // This tick's input for the bot's player.
+    pub fn think(&mut self,sim:&Sim) -> Input {
+        let player = &sim.players[self.slot];
+        if player.state != State2::ALIVE {
+            self.path.clear();
+            self.plant = false;
+            return Input::default();
+        }
+        let skill = self.difficulty.skill();
+        let here = player.get_position();
+        let danger = sim.grid.danger_map();
+        let goal = self.path.last().copied().unwrap_or(here);
+        if self.wait == 0 || !is_safe(&danger, goal) { // A plan that ends in a blast is dropped at once.
+            self.plan(sim, here, &danger);
+            self.wait = skill.think_ticks;
+        }else{
+            self.wait -= 1;
+        }
+        let mut input = Input { cycle: skill.best_bomb && player.weapon != player.strongest_weapon(), ..Input::default() };
+        while let Some(&next) = self.path.first() {
+            match player.steer(next) {
+                Some(steer) => return Input { cycle: input.cycle, ..steer },
+                None => {self.path.remove(0);}
+            }
+        }
+        if let Some(steer) = player.steer(here) { // Stand square on the tile, half on the next one a flame there still hits.
+            return Input { cycle: input.cycle, ..steer };
+        }
+        if self.plant && player.bomb_ready(&sim.grid) {
+            self.plant = false;
+            input.bomb = true;
+        }
+        input
+    }
+
+    // The path keeps the tile it starts on when the first step would cut a corner, the
+    // player then walks back onto it to line up.
+    fn walk(&mut self,player:&Player,reach:&FloodFill,to:Position){
+        self.goal = Some(to);
+        self.path = reach.path_to(to).unwrap_or_default();
+        if self.path.len() > 1 && player.lined_up(self.path[0], self.path[1]) {
+            self.path.remove(0);
+        }
+    }
+
+    // One of the nearest reachable tiles `pick` accepts, drawn at random from those within
+    // TIE_RANGE of the closest so two bots on one tile don't mirror each other forever.
+    // The previous goal wins if it is still in the draw, so the bot doesn't dither.
+    fn nearest<F:FnMut(Position) -> bool>(&mut self,reach:&FloodFill,mut pick:F) -> Option<Position> {
+        let mut found = vec![];
+        let mut limit = u32::MAX;
+        for &to in reach.reached() {
+            let dist = reach.distance(to).unwrap_or(u32::MAX);
+            if dist > limit {
+                break;
+            }
+            if pick(to) {
+                limit = limit.min(dist + TIE_RANGE);
+                found.push(to);
+            }
+        }
+        if found.is_empty() {
+            return None;
+        }
+        if let Some(goal) = self.goal.filter(|goal| found.contains(goal)) {
+            return Some(goal);
+        }
+        Some(found[(self.next_rand() % found.len() as u64) as usize])
+    }
+
+    // Pick what to do next, in order: get out of a blast, bomb an opponent in line, pick up
+    // an item, line up with an opponent, blow open a wall, or stroll.
+    fn plan(&mut self,sim:&Sim,here:Position,danger:&DangerMap){
+        let skill = self.difficulty.skill();
+        let grid = &sim.grid;
+        let cells = &grid.cells;
+        let player = &sim.players[self.slot];
+        self.path.clear();
+        self.plant = false;
+        let reachable = reach(cells, danger, here, skill.margin);
+        if !is_safe(danger, here) {
+            let reckless = reach(cells, danger, here, 0_f32);
+            if let Some(to) = self.nearest(&reachable, |to| is_safe(danger, to)) {
+                self.walk(player, &reachable, to);
+            }else if let Some(&to) = reckless.reached().iter().max_by(|a,b| danger[a.0][a.1].total_cmp(&danger[b.0][b.1])) {
+                self.walk(player, &reckless, to); // Cornered, hold out where the flame comes last.
+            }
+            return;
+        }
+        if skill.idle_chance > 0 && self.next_rand().is_multiple_of(skill.idle_chance) {
+            return;
+        }
+        let weapon = if skill.best_bomb {player.strongest_weapon()} else {player.weapon};
+        let power = bomb_power(weapon);
+        let opponents:Vec<Position> = sim.players.iter()
+            .filter(|other| other.slot != self.slot && other.state == State2::ALIVE)
+            .map(|other| other.get_position()).collect();
+        let lined_up = |at:Position| opponents.iter().any(|&opponent| in_blast(grid, at, power, opponent));
+        let escapes = |at:Position,from:Position| { // Somewhere safe is still in reach of `from` once the bomb on `at` is down.
+            let danger = with_bomb(grid, danger, at, power);
+            reach(cells, &danger, from, skill.margin + PLANT_SLACK).reached().iter().any(|&to| is_safe(&danger, to))
+        };
+        let traps = |at:Position| opponents.iter().any(|&opponent| in_blast(grid, at, power, opponent) && !escapes(at, opponent));
+        if player.bomb_ready(&sim.grid) && lined_up(here) && escapes(here, here) {
+            self.path = vec![here];
+            self.plant = true;
+            return;
+        }
+        let loot = self.nearest(&reachable, |(i,j)| {
+            cells[i][j].terrain == Terrain::Empty && cells[i][j].item != Item::Default
+                && is_safe(danger, (i,j)) && reachable.distance((i,j)).unwrap_or(u32::MAX) <= skill.loot_range
+        });
+        if let Some(to) = loot {
+            self.walk(player, &reachable, to);
+            return;
+        }
+        let hunt = |bot:&mut Bot| {
+            let trap = if skill.trap {bot.nearest(&reachable, |to| is_safe(danger, to) && traps(to) && escapes(to, to))} else {None};
+            trap.or_else(|| bot.nearest(&reachable, |to| is_safe(danger, to) && lined_up(to) && escapes(to, to)))
+        };
+        let dig = |bot:&mut Bot| bot.nearest(&reachable, |to| is_safe(danger, to) && next_to_wall(cells, to) && escapes(to, to));
+        let target = if skill.hunt_first {
+            hunt(self).or_else(|| dig(self))
+        }else if skill.hunt {
+            dig(self).or_else(|| hunt(self))
+        }else{
+            dig(self)
+        };
+        if let Some(to) = target {
+            self.walk(player, &reachable, to);
+            self.plant = true;
+            return;
+        }
+        let near:Vec<Position> = reachable.reached().iter().copied()
+            .filter(|&to| is_safe(danger, to) && reachable.distance(to).unwrap_or(u32::MAX) <= WANDER_RANGE).collect();
+        if !near.is_empty() {
+            let to = near[(self.next_rand() % near.len() as u64) as usize];
+            self.walk(player, &reachable, to);
+        }
+    }
+}
+
+// Bots on every slot until one is left standing or MATCH_TIME runs out. The winner's slot,
+// None for a draw. Needs no window, used to balance the difficulties.
+pub fn play_match(seed:u64,levels:&[Difficulty]) -> Option<usize> {
+    run_match(seed, levels).0.winner()
+}
+
+// The match as it stood when it ended and how many seconds it took.
+fn run_match(seed:u64,levels:&[Difficulty]) -> (Sim,f32) {
+    let mut sim = Sim::new(seed, levels.len(), LIFES);
+    let mut bots:Vec<Bot> = levels.iter().enumerate().map(|(slot,&level)| Bot::new(slot, level, seed)).collect();
+    let mut time = 0_f32;
+    while !sim.is_over() && time < MATCH_TIME {
+        let inputs:Vec<Input> = bots.iter_mut().map(|bot| bot.think(&sim)).collect();
+        sim.step(&inputs, TICK);
+        sim.drain_events();
+        time += TICK;
+    }
+    (sim,time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bomb::Bomb;
+    use crate::bonus::BonusType;
+    use crate::objects::{Block,Wall};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    const SEED:u64 = 26; // One where a hard bot wins early, most hard against hard matches run out the clock.
+
+    // Open ground `width` by `height` tiles in the top left, border included, blocks everywhere else.
+    // Player one stands on (1,1), any other player on `others`, all spawned in and free to move.
+    fn arena(width:usize,height:usize,others:&[Position]) -> Sim {
+        let mut sim = Sim::new(SEED, others.len() + 1, LIFES);
+        sim.countdown = 0;
+        sim.enemies.clear();
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let grid = &mut sim.grid;
+        for (i,rows) in grid.cells.iter_mut().enumerate() {
+            for (j,cell) in rows.iter_mut().enumerate() {
+                let open = i > 0 && j > 0 && i < width-1 && j < height-1;
+                *cell = Cell::new(if open {Terrain::Empty} else {Terrain::Block});
+                grid.game_objs[i][j] = if open {GameObjs::Default} else {GameObjs::Block(Block::new(i,j,&mut rng))};
+            }
+        }
+        grid.bonus_vec.clear();
+        grid.upgrade_vec.clear();
+        for (player,&(i,j)) in sim.players.iter_mut().zip([(1,1)].iter().chain(others)) {
+            player.rec2.x = i as f32 * TILE_SIZE;
+            player.rec2.y = j as f32 * TILE_SIZE;
+            player.prev_rec2 = player.rec2;
+        }
+        while sim.players.iter().any(|player| player.state != State2::ALIVE) {
+            sim.step(&vec![Input::default();sim.players.len()], TICK);
+        }
+        sim
+    }
+
+    // Player one is the bot, everyone else stands still.
+    fn play(sim:&mut Sim,bot:&mut Bot,ticks:usize){
+        for _ in 0..ticks {
+            let mut inputs = vec![Input::default();sim.players.len()];
+            inputs[0] = bot.think(sim);
+            sim.step(&inputs, TICK);
+        }
+    }
+
+    fn bombs_of(sim:&Sim,owner:usize) -> Vec<Position> {
+        let mut found = vec![];
+        for (i,rows) in sim.grid.game_objs.iter().enumerate() {
+            for (j,obj) in rows.iter().enumerate() {
+                if matches!(obj, GameObjs::Bomb(bomb) if bomb.owner == owner) {
+                    found.push((i,j));
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn hard_bots_settle_a_match_the_same_way_every_time(){
+        let levels = [Difficulty::Hard,Difficulty::Hard];
+        let (sim,time) = run_match(SEED, &levels);
+        assert!(sim.is_over(), "still going after {} seconds", time);
+        assert!(time < MATCH_TIME);
+        let (again,again_time) = run_match(SEED, &levels);
+        assert_eq!(again.hash(), sim.hash());
+        assert_eq!(again_time, time);
+        assert_eq!(play_match(SEED, &levels), sim.winner());
+    }
+
+    #[test]
+    fn a_bot_on_a_fresh_bomb_gets_clear_before_it_goes_off(){
+        let mut sim = arena(7, 5, &[]);
+        let mut bomb = Bomb::new();
+        bomb.set_position(1, 1);
+        bomb.power = 2;
+        sim.grid.cells[1][1].bomb = true;
+        sim.grid.game_objs[1][1] = GameObjs::Bomb(bomb);
+        let mut bot = Bot::new(0, Difficulty::Normal, SEED);
+        let fuse = (EXP_TIME / TICK) as usize;
+        let safe_at = (0..fuse).find(|_| {
+            play(&mut sim, &mut bot, 1);
+            let (i,j) = sim.players[0].get_position();
+            is_safe(&sim.grid.danger_map(), (i,j))
+        });
+        assert!(safe_at.is_some(), "still in the blast when the fuse ran out");
+        play(&mut sim, &mut bot, fuse);
+        assert_eq!(sim.players[0].state, State2::ALIVE);
+    }
+
+    #[test]
+    fn bots_walk_over_items_in_range(){
+        let mut sim = arena(7, 5, &[]);
+        sim.grid.cells[4][2].item = Item::Bonus(BonusType::GoldCoin);
+        let mut bot = Bot::new(0, Difficulty::Normal, SEED);
+        play(&mut sim, &mut bot, 300);
+        assert_eq!(sim.grid.cells[4][2].item, Item::Default);
+        assert_eq!(sim.players[0].gold_coin, 1);
+    }
+
+    #[test]
+    fn bots_plant_next_to_walls(){
+        let mut sim = arena(9, 5, &[]);
+        sim.grid.cells[6][2] = Cell::new(Terrain::Wall);
+        sim.grid.game_objs[6][2] = GameObjs::Wall(Wall::new(6,2));
+        let mut bot = Bot::new(0, Difficulty::Normal, SEED);
+        let mut planted = vec![];
+        for _ in 0..600 {
+            play(&mut sim, &mut bot, 1);
+            planted = bombs_of(&sim, 0);
+            if !planted.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(planted.len(), 1, "no bomb went down");
+        assert!(next_to_wall(&sim.grid.cells, planted[0]), "planted on {:?}, away from the wall", planted[0]);
+    }
+
+    #[test]
+    fn easy_bots_leave_opponents_alone_and_hard_ones_bomb_them(){
+        let bombs_planted = |difficulty:Difficulty| {
+            let mut sim = arena(9, 5, &[(7,3)]);
+            let mut bot = Bot::new(0, difficulty, SEED);
+            (0..600).any(|_| {
+                play(&mut sim, &mut bot, 1);
+                !bombs_of(&sim, 0).is_empty()
+            })
+        };
+        assert!(!bombs_planted(Difficulty::Easy));
+        assert!(bombs_planted(Difficulty::Hard));
+    }
+}
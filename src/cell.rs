@@ -0,0 +1,76 @@
+use serde::{Serialize,Deserialize};
+use crate::bonus::BonusType;
+use crate::upgrade::UpgradeType;
+
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
+pub enum Terrain {
+    Empty,
+    Block,
+    Wall,
+}
+
+// Pickup lying on the cell, or hidden under its wall until the wall is blown up.
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
+pub enum Item {
+    Default,
+    Bonus(BonusType),
+    Upgrade(UpgradeType,usize),
+    Exit,
+}
+
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
+pub enum Flame {
+    Default,
+    Explosion,
+    MidLeft,
+    MidRight,
+    MidTop,
+    MidDown,
+    EndLeft,
+    EndRight,
+    EndTop,
+    EndDown,
+}
+
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
+pub struct Cell {
+    pub terrain:Terrain,
+    pub item:Item,
+    pub bomb:bool,
+    pub flame:Flame,
+}
+
+impl Cell {
+    pub fn new(terrain:Terrain) -> Self {
+        let item = Item::Default;
+        let bomb = false;
+        let flame = Flame::Default;
+        Self { terrain, item, bomb, flame }
+    }
+
+    // Open ground flames can spread into and bombs can be planted on, items don't count.
+    pub fn is_empty(&self) -> bool {
+        self.terrain == Terrain::Empty && !self.bomb && self.flame == Flame::Default
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.terrain == Terrain::Block || self.terrain == Terrain::Wall
+    }
+
+    pub fn is_flame(&self) -> bool {
+        self.flame != Flame::Default
+    }
+
+    // Fixed layout for the desync hash: terrain, item kind, bonus or upgrade type, bomb, flame,
+    // then the upgrade count as u32 LE.
+    pub fn to_bytes(self) -> [u8;9] {
+        let (kind,detail,count) = match self.item {
+            Item::Default => (0,0,0),
+            Item::Bonus(bonus) => (1,bonus as u8,0),
+            Item::Upgrade(upgrade,count) => (2,upgrade as u8,count as u32),
+            Item::Exit => (3,0,0),
+        };
+        let count = count.to_le_bytes();
+        [self.terrain as u8,kind,detail,self.bomb as u8,self.flame as u8,count[0],count[1],count[2],count[3]]
+    }
+}
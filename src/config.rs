@@ -0,0 +1,42 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const APP_DIR:&str = "bomber_man";
+
+// File inside the per user config directory, or the working directory when there is none.
+pub fn config_path(file:&str) -> PathBuf {
+    match dirs::config_dir() {
+        Some(dir) => dir.join(APP_DIR).join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+// `key = value` lines with `#` comments, handed back with their line numbers.
+pub fn read_config(file:&str) -> io::Result<Vec<(usize,String,String)>> {
+    let text = fs::read_to_string(config_path(file))?;
+    let mut pairs = vec![];
+    for (n,line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key,val)) => pairs.push((n+1,key.trim().to_string(),val.trim().to_string())),
+            None => eprintln!("{}:{}: expected `key = value`",file,n+1),
+        }
+    }
+    Ok(pairs)
+}
+
+pub fn write_config(file:&str,pairs:&[(String,String)]) -> io::Result<()> {
+    let path = config_path(file);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = String::new();
+    for (key,val) in pairs {
+        text.push_str(&format!("{} = {}\n",key,val));
+    }
+    fs::write(path, text)
+}
@@ -0,0 +1,331 @@
+use raylib::prelude::*;
+use crate::KeyboardKey::*;
+use rand::{SeedableRng, rngs::StdRng};
+use crate::cell::*;
+use crate::grid::*;
+use crate::map::Map;
+use crate::enemy::{Enemy,EnemyKind};
+use crate::bonus::BonusType;
+use crate::upgrade::UpgradeType;
+use crate::player::PLAYER_COLORS;
+use crate::game::TEXT_SIZE;
+
+pub const EDITOR_MAP:&str = "custom.map"; // Saved to when no `--map` was given.
+const UNDO_LIMIT:usize = 100;
+const ART_SEED:u64 = 0x65646974; // Tile art for the map being edited, redrawn after every change.
+const EXIT_MARK:Color = Color::new(255,203,0,255);
+
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum Brush {
+    Block,
+    Wall,
+    Grass,
+    Item(Item),
+    Spawn(usize),
+    Exit,
+    Enemy(EnemyKind),
+}
+
+// Picked with the mouse wheel or left and right, in this order.
+const BRUSHES:[(&str,Brush);22] = [
+    ("BLOCK",Brush::Block),
+    ("WALL",Brush::Wall),
+    ("GRASS",Brush::Grass),
+    ("HEART",Brush::Item(Item::Bonus(BonusType::Heart))),
+    ("CASH",Brush::Item(Item::Bonus(BonusType::Cash))),
+    ("SILVER COIN",Brush::Item(Item::Bonus(BonusType::SilverCoin))),
+    ("GOLD COIN",Brush::Item(Item::Bonus(BonusType::GoldCoin))),
+    ("DIAMOND",Brush::Item(Item::Bonus(BonusType::Diamond))),
+    ("BLACK BOMB",Brush::Item(Item::Upgrade(UpgradeType::BlackBomb,2))),
+    ("BLUE BOMB",Brush::Item(Item::Upgrade(UpgradeType::BlueBomb,2))),
+    ("PURPLE BOMB",Brush::Item(Item::Upgrade(UpgradeType::PurpleBomb,3))),
+    ("RED BOMB",Brush::Item(Item::Upgrade(UpgradeType::RedBomb,3))),
+    ("SPAWN 1",Brush::Spawn(0)),
+    ("SPAWN 2",Brush::Spawn(1)),
+    ("SPAWN 3",Brush::Spawn(2)),
+    ("SPAWN 4",Brush::Spawn(3)),
+    ("EXIT",Brush::Exit),
+    ("WALKER",Brush::Enemy(EnemyKind::Walker)),
+    ("CHASER",Brush::Enemy(EnemyKind::Chaser)),
+    ("GHOST",Brush::Enemy(EnemyKind::Ghost)),
+    ("BOMB EATER",Brush::Enemy(EnemyKind::BombEater)),
+    ("SPLITTER",Brush::Enemy(EnemyKind::Splitter)),
+];
+
+// Paints a map tile by tile with the mouse, left click paints the brush and right click grass.
+// Drawn through a Grid built from the map, so it looks just like it will in play.
+pub struct Editor {
+    pub map:Map,
+    pub path:String,
+    grid:Grid,
+    enemies:Vec<Enemy>,
+    brush:usize,
+    undo:Vec<Map>,
+    redo:Vec<Map>,
+    stroke:Option<Map>, // The map before the mouse went down, pushed to undo if the stroke changed it.
+    pub message:String,
+    pub level:usize,
+    pub game_map:Option<Map>, // The game's own `--map`, put back on the way out.
+    pub tested:bool,
+}
+
+impl Editor {
+    pub fn new(map:Map,path:String,level:usize,game_map:Option<Map>) -> Self {
+        let grid = Grid::from_map(&map, &mut StdRng::seed_from_u64(ART_SEED));
+        let enemies = vec![];
+        let brush = 0;
+        let undo = vec![];
+        let redo = vec![];
+        let stroke = None;
+        let message = String::new();
+        let tested = false;
+        let mut editor = Self { map, path, grid, enemies, brush, undo, redo, stroke, message, level, game_map, tested };
+        editor.rebuild();
+        editor
+    }
+
+    fn rebuild(&mut self){
+        self.grid = Grid::from_map(&self.map, &mut StdRng::seed_from_u64(ART_SEED));
+        let mut rng = StdRng::seed_from_u64(ART_SEED);
+        self.enemies = self.map.enemies.iter().map(|&(kind,(i,j))| Enemy::new(kind, i, j, &mut rng)).collect();
+    }
+
+    pub fn cycle(&mut self,step:isize){
+        self.brush = (self.brush as isize + step).rem_euclid(BRUSHES.len() as isize) as usize;
+    }
+
+    // Every tile holds one thing, painting replaces whatever was there. The border stays blocks.
+    fn paint(&mut self,brush:Brush,(i,j):Position){
+        let (width,height) = (self.map.cells.len(),self.map.cells[0].len());
+        if i == 0 || j == 0 || i >= width-1 || j >= height-1 {
+            return;
+        }
+        let spawns = &mut self.map.spawns;
+        let spawn = spawns.iter().position(|&pos| pos == (i,j));
+        match (brush,spawn) {
+            (Brush::Spawn(slot),Some(other)) if slot >= spawns.len() => {
+                self.message = format!("SPAWN {} IS THERE",other+1);
+                return;
+            }
+            (Brush::Spawn(slot),_) if slot > spawns.len() => {
+                self.message = format!("PLACE SPAWN {} FIRST",spawns.len()+1);
+                return;
+            }
+            (Brush::Spawn(slot),Some(other)) => spawns.swap(slot, other),
+            (Brush::Spawn(slot),None) if slot == spawns.len() => spawns.push((i,j)),
+            (Brush::Spawn(slot),None) => {spawns[slot] = (i,j)}
+            (_,Some(0)) if spawns.len() == 1 => {
+                self.message = "PLAYER 1 NEEDS A SPAWN".to_string();
+                return;
+            }
+            (_,Some(other)) => {spawns.remove(other);}
+            _ => {}
+        }
+        let map = &mut self.map;
+        map.enemies.retain(|&(_,pos)| pos != (i,j));
+        let cell = match brush {
+            Brush::Block => Cell::new(Terrain::Block),
+            Brush::Wall => Cell::new(Terrain::Wall),
+            Brush::Exit => {
+                for cell in map.cells.iter_mut().flatten().filter(|cell| cell.item == Item::Exit) {
+                    cell.item = Item::Default;
+                }
+                Cell { item: Item::Exit, ..Cell::new(Terrain::Wall) }
+            }
+            Brush::Item(item) => Cell { item, ..Cell::new(Terrain::Empty) },
+            Brush::Enemy(kind) => {
+                map.enemies.push((kind,(i,j)));
+                Cell::new(Terrain::Empty)
+            }
+            Brush::Grass | Brush::Spawn(_) => Cell::new(Terrain::Empty),
+        };
+        map.cells[i][j] = cell;
+    }
+
+    fn push_undo(&mut self,map:Map){
+        self.undo.push(map);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self){
+        if let Some(map) = self.undo.pop() {
+            self.redo.push(std::mem::replace(&mut self.map, map));
+            self.rebuild();
+        }
+    }
+
+    pub fn redo(&mut self){
+        if let Some(map) = self.redo.pop() {
+            self.undo.push(std::mem::replace(&mut self.map, map));
+            self.rebuild();
+        }
+    }
+
+    // The map as the game will read it, or why it wouldn't.
+    pub fn checked(&self) -> Result<Map,String> {
+        Map::parse(&self.path, &self.map.to_text()).map_err(|err| err.to_string())
+    }
+
+    pub fn save(&mut self){
+        self.message = match self.checked().and_then(|map| map.save(&self.path).map_err(|err| err.to_string())) {
+            Ok(()) => format!("SAVED {}",self.path),
+            Err(err) => {eprintln!("failed to save map: {}",err); err}
+        };
+    }
+
+    // Any size loads, the view scrolls over maps bigger than the window.
+    pub fn load(&mut self){
+        self.message = match Map::load(&self.path) {
+            Ok(map) => {
+                let old = std::mem::replace(&mut self.map, map);
+                self.push_undo(old);
+                self.rebuild();
+                format!("LOADED {}",self.path)
+            }
+            Err(err) => {eprintln!("failed to load map: {}",err); err.to_string()}
+        };
+    }
+
+    // `mouse` is in map coordinates, already moved out of the window's letterbox and scrolled
+    // with the view. None when it isn't over the map's part of the screen.
+    fn tile(&self,mouse:Option<Vector2>) -> Option<Position> {
+        let mouse = mouse?;
+        let tile = ((mouse.x / TILE_SIZE) as usize,(mouse.y / TILE_SIZE) as usize);
+        if mouse.x >= 0_f32 && mouse.y >= 0_f32 && tile.0 < self.map.cells.len() && tile.1 < self.map.cells[0].len() {
+            Some(tile)
+        }else{
+            None
+        }
+    }
+
+    // Mouse painting and the Ctrl shortcuts, fixed like Enter on the rebind screen.
+    pub fn update(&mut self,rl:&RaylibHandle,mouse:Option<Vector2>){
+        let wheel = rl.get_mouse_wheel_move();
+        if wheel != 0_f32 {
+            self.cycle(if wheel > 0_f32 {-1} else {1});
+        }
+        let ctrl = rl.is_key_down(KEY_LEFT_CONTROL) || rl.is_key_down(KEY_RIGHT_CONTROL);
+        let shift = rl.is_key_down(KEY_LEFT_SHIFT) || rl.is_key_down(KEY_RIGHT_SHIFT);
+        if ctrl && rl.is_key_pressed(KEY_Z) {
+            if shift {self.redo()} else {self.undo()}
+        }else if ctrl && rl.is_key_pressed(KEY_Y) {
+            self.redo();
+        }else if ctrl && rl.is_key_pressed(KEY_S) {
+            self.save();
+        }else if ctrl && rl.is_key_pressed(KEY_O) {
+            self.load();
+        }
+        let brush = if rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+            Some(BRUSHES[self.brush].1)
+        }else if rl.is_mouse_button_down(MouseButton::MOUSE_RIGHT_BUTTON) {
+            Some(Brush::Grass)
+        }else{
+            None
+        };
+        self.drag(brush, self.tile(mouse));
+    }
+
+    // One frame of a mouse stroke, `brush` while a button is held and None once it's let go.
+    // A whole stroke is one undo step.
+    fn drag(&mut self,brush:Option<Brush>,tile:Option<Position>){
+        match brush {
+            Some(brush) => {
+                if self.stroke.is_none() {
+                    self.stroke = Some(self.map.clone());
+                }
+                if let Some(tile) = tile {
+                    let before = self.map.clone();
+                    self.paint(brush, tile);
+                    if self.map != before {
+                        self.rebuild();
+                    }
+                }
+            }
+            None => if let Some(map) = self.stroke.take() {
+                if map != self.map {
+                    self.push_undo(map);
+                }
+            }
+        }
+    }
+
+    pub fn draw(&mut self,d:&mut impl RaylibDraw,sheets:&Texture2D,mouse:Option<Vector2>,visible:&Visible){
+        self.grid.draw(d, sheets, visible);
+        for enemy in self.enemies.iter().filter(|enemy| visible.overlaps(&enemy.rec2)) {
+            enemy.draw(d, sheets, 1_f32);
+        }
+        let tile = TILE_SIZE as i32;
+        let size = TEXT_SIZE/2;
+        for (slot,&(i,j)) in self.map.spawns.iter().enumerate() {
+            let (x,y) = (i as i32 * tile,j as i32 * tile);
+            let slot_str = (slot+1).to_string();
+            d.draw_rectangle_lines(x, y, tile, tile, PLAYER_COLORS[slot]);
+            d.draw_text(&slot_str, x + tile/2 - measure_text(&slot_str, size)/2, y + tile/2 - size/2, size, PLAYER_COLORS[slot]);
+        }
+        if let Some((i,j)) = self.grid.exit {
+            d.draw_rectangle_lines(i as i32 * tile, j as i32 * tile, tile, tile, EXIT_MARK);
+            d.draw_rectangle_lines(i as i32 * tile + 2, j as i32 * tile + 2, tile - 4, tile - 4, EXIT_MARK);
+        }
+        if let Some((i,j)) = self.tile(mouse) {
+            d.draw_rectangle_lines(i as i32 * tile, j as i32 * tile, tile, tile, Color::YELLOW);
+        }
+    }
+
+    // Brush and message on the HUD row starting at `hud_y`, `hint` lists the keys.
+    pub fn draw_hud(&self,d:&mut impl RaylibDraw,screen_w:i32,hud_y:i32,hint:&str){
+        let size = TEXT_SIZE/2;
+        let y = hud_y + TILE_SIZE as i32/2 - size/2;
+        let brush_str = format!("< {} >",BRUSHES[self.brush].0);
+        d.draw_text(&brush_str, TILE_SIZE as i32/4, y, size, Color::YELLOW);
+        d.draw_text(hint, screen_w - measure_text(hint, size) - TILE_SIZE as i32/4, y, size, Color::WHITE);
+        let x = screen_w/2 - measure_text(&self.message, size)/2;
+        d.draw_text(&self.message, x, TILE_SIZE as i32/4, size, Color::WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor() -> Editor {
+        Editor::new(Map::blank(9, 7), "test.map".to_string(), 1, None)
+    }
+
+    // Press, paint one tile, let go.
+    fn stroke(editor:&mut Editor,brush:Brush,tile:Position){
+        editor.drag(Some(brush), Some(tile));
+        editor.drag(None, None);
+    }
+
+    fn terrain(editor:&Editor,(i,j):Position) -> Terrain {
+        editor.map.cells[i][j].terrain
+    }
+
+    #[test]
+    fn undo_and_redo_a_stroke(){
+        let mut editor = editor();
+        stroke(&mut editor, Brush::Block, (3,3));
+        assert_eq!(terrain(&editor, (3,3)), Terrain::Block);
+        editor.undo();
+        assert_eq!(terrain(&editor, (3,3)), Terrain::Empty);
+        editor.redo();
+        assert_eq!(terrain(&editor, (3,3)), Terrain::Block);
+        assert_eq!(editor.grid.cells[3][3].terrain, Terrain::Block, "the drawn grid follows the map");
+    }
+
+    #[test]
+    fn a_new_stroke_after_undo_drops_the_redo(){
+        let mut editor = editor();
+        stroke(&mut editor, Brush::Block, (3,3));
+        editor.undo();
+        stroke(&mut editor, Brush::Wall, (4,4));
+        editor.redo();
+        assert_eq!(terrain(&editor, (3,3)), Terrain::Empty);
+        assert_eq!(terrain(&editor, (4,4)), Terrain::Wall);
+        editor.undo();
+        assert_eq!(terrain(&editor, (4,4)), Terrain::Empty);
+    }
+}
@@ -0,0 +1,312 @@
+use raylib::prelude::*;
+use rand::{Rng, rngs::StdRng};
+use serde::{Serialize,Deserialize};
+use crate::save::{RectangleDef,ColorDef};
+use crate::grid::*;
+use crate::player::DIR;
+use crate::cell::*;
+use crate::path::*;
+use crate::atlas::atlas;
+
+const DYING_TIME:f32 = 1_f32;
+const HURT_TIME:f32 = 1.2_f32; // Untouchable after a hit so one blast only takes one hit point.
+const TURN_CHANCE:u64 = 4; // One in this many junctions the enemy turns even when it could go straight.
+const SPLIT_COUNT:usize = 2;
+
+const ORC:[&str;4] = ["orc_left","orc_right","orc_up","orc_down"];
+const SKULL:[&str;4] = ["skull_left","skull_right","skull_up","skull_down"];
+const DEATH_COLORS:[Color;2] = [Color::RED,Color::YELLOW];
+// Collison shape const for enemy
+const COLL_MARGIN:f32 = 3_f32;
+const ENEMY_SIZE:f32 = 10_f32;
+
+#[derive(Clone,PartialEq,Debug,Copy,Serialize,Deserialize)]
+pub enum EnemyKind {
+   Walker,
+   Chaser,
+   Ghost,
+   BombEater,
+   Splitter,
+   Splitling,
+}
+
+pub struct Archetype {
+    pub speed:f32,
+    pub hp:usize,
+    pub wall_pass:bool, // Walks through breakable walls, never through blocks.
+    pub chase:bool, // Heads for the nearest player instead of wandering.
+    pub eat_bombs:bool,
+    pub split:Option<EnemyKind>, // Breaks into SPLIT_COUNT of these when killed.
+    pub score:usize,
+    pub clips:[&'static str;4], // Walking left, right, up and down.
+    pub tint:Color,
+}
+
+// Indexed by EnemyKind.
+pub const ARCHETYPES:[Archetype;6] = [
+    Archetype { speed: 14_f32, hp: 1, wall_pass: false, chase: false, eat_bombs: false, split: None, score: 100, clips: ORC, tint: Color::WHITE },
+    Archetype { speed: 32_f32, hp: 1, wall_pass: false, chase: true, eat_bombs: false, split: None, score: 200, clips: SKULL, tint: Color::WHITE },
+    Archetype { speed: 18_f32, hp: 2, wall_pass: true, chase: false, eat_bombs: false, split: None, score: 300, clips: ORC, tint: Color::new(255,255,255,130) },
+    Archetype { speed: 22_f32, hp: 2, wall_pass: false, chase: false, eat_bombs: true, split: None, score: 250, clips: SKULL, tint: Color::ORANGE },
+    Archetype { speed: 20_f32, hp: 1, wall_pass: false, chase: false, eat_bombs: false, split: Some(EnemyKind::Splitling), score: 150, clips: ORC, tint: Color::VIOLET },
+    Archetype { speed: 30_f32, hp: 1, wall_pass: false, chase: false, eat_bombs: false, split: None, score: 50, clips: ORC, tint: Color::PINK },
+];
+
+impl EnemyKind {
+    pub fn archetype(&self) -> &'static Archetype {
+        &ARCHETYPES[*self as usize]
+    }
+}
+
+#[derive(Clone,PartialEq,Debug,Copy,Serialize,Deserialize)]
+pub enum EnemyState {
+   ALIVE,
+   DYING,
+   DEAD,
+}
+
+// Walks from tile to tile and picks a new way at every tile it reaches.
+#[derive(Clone,PartialEq,Debug,Serialize,Deserialize)]
+pub struct Enemy {
+    pub kind:EnemyKind,
+    pub hp:usize,
+    pub hurt:f32,
+    #[serde(with = "RectangleDef")]
+    pub rec:Rectangle,
+    #[serde(with = "RectangleDef")]
+    pub rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
+    pub prev_rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
+    pub rec_shadow:Rectangle,
+    #[serde(with = "ColorDef")]
+    pub tint:Color,
+    pub dir:DIR,
+    pub from:Position,
+    pub target:Position,
+    pub state:EnemyState,
+    pub frames:usize,
+    pub time:f32,
+    pub dying:f32,
+    pub rand:u64, // Own generator state so enemies replay the same from a save or a replay.
+}
+
+fn step(position:Position,dir:DIR) -> Position {
+    let (i,j) = position;
+    match dir {
+        DIR::Up => (i,j-1),
+        DIR::Down => (i,j+1),
+        DIR::Left => (i-1,j),
+        DIR::Right => (i+1,j),
+        _ => (i,j),
+    }
+}
+
+fn reverse(dir:DIR) -> DIR {
+    match dir {
+        DIR::Up => DIR::Down,
+        DIR::Down => DIR::Up,
+        DIR::Left => DIR::Right,
+        DIR::Right => DIR::Left,
+        other => other,
+    }
+}
+
+fn direction(from:Position,to:Position) -> DIR {
+    if to.0 < from.0 {DIR::Left} else if to.0 > from.0 {DIR::Right} else if to.1 < from.1 {DIR::Up} else {DIR::Down}
+}
+
+// What a chaser's path search may cross, same as where it can walk but never into flames.
+fn path_cost(archetype:&Archetype,cell:&Cell) -> Option<u32> {
+    let cell = if archetype.eat_bombs {Cell { bomb: false, ..*cell }} else {*cell};
+    if archetype.wall_pass {ghost_cost(&cell)} else {walker_cost(&cell)}
+}
+
+impl Enemy {
+    pub fn new(kind:EnemyKind,i:usize,j:usize,rng:&mut StdRng) -> Self {
+        Enemy::with_rand(kind, i, j, rng.gen::<u64>())
+    }
+
+    fn with_rand(kind:EnemyKind,i:usize,j:usize,rand:u64) -> Self {
+        let archetype = kind.archetype();
+        let hp = archetype.hp;
+        let hurt = 0_f32;
+        let rec = atlas().frame(archetype.clips[3], 0);
+        let rec2 = Rectangle::new(i as f32 * TILE_SIZE, j as f32 * TILE_SIZE, TILE_SIZE, TILE_SIZE);
+        let prev_rec2 = rec2;
+        let rec_shadow = atlas().frame("shadow", 0);
+        let tint = archetype.tint;
+        let dir = DIR::Down;
+        let from = (i,j);
+        let target = (i,j);
+        let state = EnemyState::ALIVE;
+        let frames = 0;
+        let time = 0_f32;
+        let dying = 0_f32;
+        let rand = rand | 1;
+        Self { kind, hp, hurt, rec, rec2, prev_rec2, rec_shadow, tint, dir, from, target, state, frames, time, dying, rand }
+    }
+
+    fn next_rand(&mut self) -> u64 { // xorshift64
+        self.rand ^= self.rand << 13;
+        self.rand ^= self.rand >> 7;
+        self.rand ^= self.rand << 17;
+        self.rand
+    }
+
+    pub fn archetype(&self) -> &'static Archetype {
+        self.kind.archetype()
+    }
+
+    fn is_open(&self,grid:&Grid,position:Position) -> bool {
+        let cell = grid.cells[position.0][position.1];
+        let archetype = self.archetype();
+        let blocked = if archetype.wall_pass {cell.terrain == Terrain::Block} else {cell.is_solid()};
+        !blocked && (!cell.bomb || archetype.eat_bombs)
+    }
+
+    pub fn get_coll_shape(&self) -> Rectangle {
+        Rectangle::new(self.rec2.x + COLL_MARGIN, self.rec2.y + COLL_MARGIN, ENEMY_SIZE, ENEMY_SIZE)
+    }
+
+    pub fn get_position(&self) -> Position {
+        let i = ((self.rec2.x + MARGIN_POS)/ TILE_SIZE) as usize;
+        let j = ((self.rec2.y + MARGIN_POS)/ TILE_SIZE) as usize;
+        return (i,j);
+    }
+
+    // First step on the shortest path to the closest player, one flood covers them all.
+    fn chase(&self,grid:&Grid,players:&[Position]) -> Option<DIR> {
+        let here = self.target;
+        let archetype = self.archetype();
+        let flood = FloodFill::new(&grid.cells, here, |cell| path_cost(archetype, cell));
+        let player = players.iter().copied().filter(|&player| flood.distance(player).is_some()).min_by_key(|&player| flood.distance(player))?;
+        flood.path_to(player)?.get(1).map(|&next| direction(here, next))
+    }
+
+    // Chasers follow the shortest path to a player when there is one. Otherwise keep going
+    // straight now and then, else any open way, turning back only when boxed in.
+    fn choose_target(&mut self,grid:&Grid,players:&[Position]){
+        let here = self.target;
+        if self.archetype().chase {
+            if let Some(dir) = self.chase(grid, players) {
+                self.dir = dir;
+                self.from = here;
+                self.target = step(here, dir);
+                return;
+            }
+        }
+        let ahead = step(here, self.dir);
+        if self.is_open(grid, ahead) && !self.next_rand().is_multiple_of(TURN_CHANCE) {
+            self.from = here;
+            self.target = ahead;
+            return;
+        }
+        let back = reverse(self.dir);
+        let open:Vec<DIR> = [DIR::Up,DIR::Down,DIR::Left,DIR::Right].into_iter()
+            .filter(|&dir| dir != back && self.is_open(grid, step(here, dir))).collect();
+        let dir = if !open.is_empty() {
+            open[(self.next_rand() % open.len() as u64) as usize]
+        }else if self.is_open(grid, step(here, back)) {
+            back
+        }else{
+            return; // Boxed in, wait for a way to open.
+        };
+        self.dir = dir;
+        self.from = here;
+        self.target = step(here, dir);
+    }
+
+    fn walk(&mut self,grid:&mut Grid,players:&[Position],frame_time:f32){
+        if self.target != self.from && !self.is_open(grid, self.target) { // A bomb landed in the way, head back.
+            self.dir = reverse(self.dir);
+            std::mem::swap(&mut self.from, &mut self.target);
+        }
+        let x = self.target.0 as f32 * TILE_SIZE;
+        let y = self.target.1 as f32 * TILE_SIZE;
+        let dist = self.archetype().speed * frame_time;
+        self.rec2.x += (x - self.rec2.x).clamp(-dist, dist);
+        self.rec2.y += (y - self.rec2.y).clamp(-dist, dist);
+        if self.rec2.x == x && self.rec2.y == y {
+            if self.archetype().eat_bombs {
+                grid.eat_bomb(self.target.0, self.target.1);
+            }
+            self.choose_target(grid, players);
+        }
+    }
+
+    fn animate(&mut self,frame_time:f32){
+        let [left,right,up,down] = self.archetype().clips;
+        let clip = atlas().clip(match self.dir {
+            DIR::Left => left,
+            DIR::Right => right,
+            DIR::Up => up,
+            _ => down,
+        });
+        if self.time > clip.duration {
+            self.time = 0_f32;
+            self.frames += 1;
+        }
+        self.time += frame_time;
+        self.frames %= clip.frames.len();
+        match self.state {
+            EnemyState::ALIVE => {
+                self.rec = clip.frames[self.frames];
+                self.tint = if self.hurt > 0_f32 {DEATH_COLORS[self.frames % DEATH_COLORS.len()]} else {self.archetype().tint};
+            }
+            EnemyState::DYING => {self.tint = DEATH_COLORS[self.frames % DEATH_COLORS.len()]}
+            EnemyState::DEAD => {}
+        }
+    }
+
+    // Returns true on the tick a flame takes the enemy's last hit point.
+    pub fn update(&mut self,grid:&mut Grid,players:&[Position],frame_time:f32) -> bool {
+        self.prev_rec2 = self.rec2;
+        let mut killed = false;
+        match self.state {
+            EnemyState::ALIVE => {
+                self.walk(grid, players, frame_time);
+                self.hurt = (self.hurt - frame_time).max(0_f32);
+                if self.hurt <= 0_f32 && grid.get_collisions(self.get_position(), self.get_coll_shape()).0 {
+                    self.hp -= 1;
+                    self.hurt = HURT_TIME;
+                    if self.hp == 0 {
+                        self.state = EnemyState::DYING;
+                        killed = true;
+                    }
+                }
+            }
+            EnemyState::DYING => {
+                self.dying += frame_time;
+                if self.dying > DYING_TIME {
+                    self.state = EnemyState::DEAD;
+                }
+            }
+            EnemyState::DEAD => {return false}
+        }
+        self.animate(frame_time);
+        return killed;
+    }
+
+    // The pieces a splitter breaks into, safe from the blast that split it for a moment.
+    pub fn split(&mut self) -> Vec<Enemy> {
+        let Some(kind) = self.archetype().split else { return vec![] };
+        let (i,j) = self.get_position();
+        (0..SPLIT_COUNT).map(|_| {
+            let mut piece = Enemy::with_rand(kind, i, j, self.next_rand());
+            piece.hurt = HURT_TIME;
+            piece
+        }).collect()
+    }
+
+    pub fn draw(&self,d:&mut impl RaylibDraw,sheets:&Texture2D,alpha:f32){
+        if self.state == EnemyState::DEAD {
+            return;
+        }
+        let x = self.prev_rec2.x + (self.rec2.x - self.prev_rec2.x) * alpha;
+        let y = self.prev_rec2.y + (self.rec2.y - self.prev_rec2.y) * alpha;
+        let dest = Rectangle::new(x, y, self.rec2.width, self.rec2.height);
+        d.draw_texture_pro(sheets, self.rec_shadow, dest, Vector2::default(), O, Color::WHITE);
+        d.draw_texture_pro(sheets, self.rec, dest, Vector2::default(), O, self.tint);
+    }
+}
@@ -0,0 +1,42 @@
+// FNV-1a over raw bytes. Unlike std's hashers it gives the same value on every build and
+// platform, so it's safe to write to files and compare between peers.
+const OFFSET_BASIS:u64 = 0xcbf29ce484222325;
+const PRIME:u64 = 0x100000001b3;
+
+pub struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(OFFSET_BASIS)
+    }
+}
+
+impl Fnv {
+    pub fn write(&mut self,bytes:&[u8]){
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv(bytes:&[u8]) -> u64 {
+        let mut hasher = Fnv::default();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn matches_the_reference_values(){
+        assert_eq!(fnv(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv(b"foobar"), 0x85944171f73967e8);
+    }
+}
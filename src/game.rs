@@ -3,22 +3,41 @@ use crate::KeyboardKey::*;
 use crate::bonus::*;
 use crate::player::*;
 use crate::grid::*;
+use crate::sim::*;
+use crate::replay::*;
+use crate::save::*;
+use crate::keymap::*;
+use crate::input::*;
+use crate::upgrade::*;
+use crate::net::*;
+use crate::path::*;
+use crate::bot::*;
+use crate::level::*;
+use crate::map::{Map,MAP_SIZE};
+use crate::cell::Cell;
+use crate::editor::*;
+use crate::menu::*;
+use crate::settings::*;
+use crate::atlas::atlas;
+use raylib::core::window::{WindowState,get_current_monitor,get_monitor_width,get_monitor_height};
+
+// None for a sound that failed to load, it stays silent.
+pub type GameSounds<'a> = (Option<&'a Sound>, Option<&'a Sound>, Option<&'a Sound>, Option<&'a Sound>, Option<&'a Sound>, Option<&'a Sound>,Option<&'a Sound>);
 
 const BACKGROUND_COLOR:Color = Color::new(28, 52, 112, 255); 
 const BLUR_WHITE:Color = Color::new(255,255,255,70);
-const GO_FRAMES:&[f32;2] = &[0_f32,144_f32];
-const GO_WIDTH:f32 = GO_FRAMES[1];
-const GO_Y:&[f32;1]= &[288_f32];
 
-const CD_Y:&[f32;2] = &[256_f32,272_f32];
-const CD_FRAMES:&[f32;5] = &[0_f32,32_f32,64_f32,108_f32,180_f32];
-const CD_WIDTH:f32 = 48_f32;
+const DANGER_HORIZON:f32 = 3_f32; // Fuses longer than this aren't shown by the danger overlay.
+const DANGER_COLOR:Color = Color::new(230,41,55,0);
+
+const LIFE_BONUS:usize = 100; // Points for every life left when a level is cleared.
+const TALLY_RATE:f32 = 300_f32; // Bonus points counted up per second on the clear screen.
 
-const PAUSED_Y:&[f32;1] = &[304_f32];
-const P_FRAMES:&[f32;2] = &[0_f32,112_f32];
-const P_WIDTH:f32 = P_FRAMES[1];
+const VIEW_TILES:(usize,usize) = MAP_SIZE; // The most of the map on screen at once, bigger maps scroll.
+const CAMERA_DAMPING:f32 = 6_f32; // How quickly the view catches up with the players, per second.
+const EDGE_SCROLL:f32 = 12_f32; // Tiles per second the editor scrolls with the mouse at the view's edge.
 
-pub const TEXT_SIZE:i32 = (SCALED_TILE + SCALED_TILE/4_f32) as i32;
+pub const TEXT_SIZE:i32 = (TILE_SIZE + TILE_SIZE/4_f32) as i32;
 
 #[macro_export]
 macro_rules! anim_obj {
@@ -34,30 +53,36 @@ macro_rules! anim_obj {
 }
 
 macro_rules! impl_text_obj {
-    ($name:ident,$y:expr,$w:expr,$frames:expr,$dur:expr) => {
+    ($name:ident,$clip:literal) => {
         impl $name {
           fn new(w:i32,h:i32) -> Self{
             let frames = 0;
-            let rec = Rectangle::new(GO_FRAMES[frames],$y[frames],$w,TILE_SIZE);
-            let x = (w as f32 /2.0) - ($w*SCALE/2.0);
-            let y = (h as f32 /2.0) - ((SCALED_TILE/2.0));
-            let rec2 = Rectangle::new(x,y,$w*SCALE,SCALED_TILE);
+            let rec = atlas().frame($clip, frames);
+            let x = (w as f32 /2.0) - (rec.width/2.0);
+            let y = (h as f32 /2.0) - (rec.height/2.0);
+            let rec2 = Rectangle::new(x,y,rec.width,rec.height);
             let time = 0_f32;
             Self { rec, rec2, frames, time}
            }
-        
-            fn draw_animate(&mut self,d:&mut RaylibDrawHandle,texts:&Texture2D,frame_time:f32){
+        }
+    };
+}
+
+macro_rules! impl_text_anim {
+    ($name:ident,$clip:literal) => {
+        impl $name {
+            fn draw_animate(&mut self,d:&mut impl RaylibDraw,texts:&Texture2D,frame_time:f32){
               d.draw_texture_pro(texts, self.rec,self.rec2, Vector2::default(), O, Color::WHITE);
 
-              if self.time > $dur{
+              let clip = atlas().clip($clip);
+              if self.time > clip.duration{
                 self.time  = 0_f32;
                 self.frames += 1;
               }
 
-              self.frames %= $frames.len();
+              self.frames %= clip.frames.len();
               self.time += frame_time;
-              self.rec.x = $frames[self.frames];
-              self.rec.y = $y[self.frames%$y.len()];
+              self.rec = clip.frames[self.frames];
             }
         }
     };
@@ -67,25 +92,48 @@ anim_obj!(GameOver);
 anim_obj!(Paused);
 anim_obj!(CountDown);
 
-impl_text_obj!(GameOver,GO_Y,GO_WIDTH,GO_FRAMES,ANIM_DURATION);
-impl_text_obj!(Paused,PAUSED_Y,P_WIDTH,P_FRAMES,ANIM_DURATION);
-impl_text_obj!(CountDown,CD_Y,CD_WIDTH,CD_FRAMES,0.35);
+impl_text_obj!(GameOver,"game_over");
+impl_text_obj!(Paused,"paused");
+impl_text_obj!(CountDown,"countdown");
+
+impl_text_anim!(GameOver,"game_over");
+impl_text_anim!(Paused,"paused");
 
 #[derive(PartialEq,Clone)]
 pub enum GameState {
+    TITLE,
+    MENU,
+    BATTLE,
+    OPTIONS,
+    CREDITS,
     STARTING,
     RUNNING,
     PAUSED,
     GAMEOVER,
+    KEYS,
+    LEVELCLEAR,
+    LEVELS,
+    EDITOR,
+}
+
+impl GameState {
+    // In play the sim moves the state along, every other screen only changes on the player's keys.
+    fn in_play(&self) -> bool {
+        matches!(self, GameState::STARTING | GameState::RUNNING | GameState::GAMEOVER | GameState::LEVELCLEAR)
+    }
+
+    // Fixed ticks run, the sim holds still through the countdown and a pause only reads the inputs.
+    fn ticking(&self) -> bool {
+        matches!(self, GameState::STARTING | GameState::RUNNING | GameState::PAUSED)
+    }
 }
 
 pub struct Game {
     pub state:GameState,
-    pub menu_enable:bool,
+    screens:Vec<GameState>, // Screens opened over each other, going back pops the last.
     pub screen_w:i32,
     pub screen_h:i32,
-    pub player:Player,
-    pub grid:Grid,
+    pub sim:Sim,
     pub heart:Bonus,
     pub cash:Bonus,
     pub silver_coin:Bonus,
@@ -96,41 +144,126 @@ pub struct Game {
     count_down:CountDown,
     frames:usize,
     time:f32,
+    accumulator:f32,
+    pending:Vec<Input>,
+    pub recording:Replay,
+    pub record_path:Option<String>,
+    pub playback:Option<Playback>,
+    pub resumed:bool,
+    message:String,
+    pub keymap:KeyMap,
+    keys_player:usize,
+    keys_selected:usize,
+    keys_waiting:bool,
+    pub gamepads:Gamepads,
+    actions:ActionState,
+    player_actions:Vec<ActionState>,
+    pub net:Option<NetSession>,
+    click_path:Vec<Position>,
+    pub settings:Settings,
+    settings_dirty:bool, // Set when the options change, the window and audio catch up on the next update.
+    window_fitted:(i32,i32), // The window size last asked for, see `fit_window`.
+    mouse:Vector2, // In game coordinates, see `screen_rect`. `world_mouse` has it over the map.
+    camera:Option<Vector2>, // Top left of the view of the map, None jumps straight to the players on the next frame.
+    bots:Vec<Bot>,
+    tally:f32,
+    unlocked:usize,
+    levels_selected:usize,
+    map:Option<Map>,
+    pub map_path:Option<String>,
+    editor:Option<Editor>, // Kept while play-testing so the editor can be gone back to.
+    menu_selected:usize,
+    options_selected:usize,
+    battle:BattleSetup,
+    battle_selected:usize,
+    pub quit:bool,
+}
+
+// Where a native size lands in a window, centered with bars on the sides left over. Integer
+// scaling keeps every sprite pixel the same size and falls back to shrinking when the window is too small.
+fn fit_rect((native_w,native_h):(i32,i32),(width,height):(i32,i32),pixel_perfect:bool) -> Rectangle {
+    let mut zoom = (width as f32 / native_w as f32).min(height as f32 / native_h as f32);
+    if pixel_perfect && zoom >= 1_f32 {
+      zoom = zoom.floor();
+    }
+    let (w,h) = (native_w as f32 * zoom,native_h as f32 * zoom);
+    Rectangle::new(((width as f32 - w)/2_f32).floor(), ((height as f32 - h)/2_f32).floor(), w, h)
+}
+
+// Window size for a game, the view of the map with one HUD row per player under it.
+fn screen_size(players:usize) -> (i32,i32) {
+    (TILE_SIZE as i32 * VIEW_TILES.0 as i32,TILE_SIZE as i32 * (VIEW_TILES.1 + players) as i32)
 }
 
 impl Game {
- pub fn new() -> Self{
-     let state = GameState::STARTING;
-     let menu_enable = false;
-     let player = Player::new();
-     let grid = Grid::new();
-     let i = grid.cells.len();
-     let j = grid.cells[0].len() + 1;
-     let screen_w = SCALED_TILE as i32 * i as i32;
-     let screen_h = SCALED_TILE as i32 * j as i32;
-   
-     let heart = Bonus::new(BonusType::Heart, i-2, j-1, SCALE);
-     let cash = Bonus::new(BonusType::Cash, 4, j-1, SCALE);
-     let silver_coin = Bonus::new(BonusType::SilverCoin, 6, j-1, SCALE);
-     let gold_coin = Bonus::new(BonusType::GoldCoin, 8, j-1, SCALE);
-     let diamond = Bonus::new(BonusType::Diamond, 10, j-1, SCALE);
+ // `map` replaces every level's map and the battle map.
+ pub fn new(seed:u64,players:usize,map:Option<Map>) -> Self{
+     let state = if players > 1 {GameState::STARTING} else {GameState::TITLE}; // A battle asked for on the command line starts right away.
+     let screens = vec![];
+     let settings = Settings::load();
+     let sim = Sim::on_map(seed,1,players,settings.lives,map.as_ref());
+     let players = sim.players.len();
+     let (screen_w,screen_h) = screen_size(players);
+     let (i,hud_j) = VIEW_TILES; // Icons sit on the first HUD row and get shifted down for the others.
+     let heart = Bonus::new(BonusType::Heart, i-2, hud_j);
+     let cash = Bonus::new(BonusType::Cash, 4, hud_j);
+     let silver_coin = Bonus::new(BonusType::SilverCoin, 6, hud_j);
+     let gold_coin = Bonus::new(BonusType::GoldCoin, 8, hud_j);
+     let diamond = Bonus::new(BonusType::Diamond, 10, hud_j);
 
      let game_over_text = GameOver::new(screen_w,screen_h);
      let paused_text = Paused::new(screen_w,screen_h);
      let count_down = CountDown::new(screen_w,screen_h);
      let frames = 0;
      let time = 0_f32;
-     Self { state,menu_enable,screen_w,screen_h, player,heart,cash,silver_coin,gold_coin,diamond,grid,game_over_text,paused_text,count_down,frames,time}
+     let accumulator = 0_f32;
+     let pending = vec![Input::default();players];
+     let recording = Replay::new(seed,sim.level,players,settings.lives,map.as_ref());
+     let record_path = None;
+     let playback = None;
+     let resumed = false;
+     let message = String::new();
+     let keymap = KeyMap::load();
+     let keys_player = 0;
+     let keys_selected = 0;
+     let keys_waiting = false;
+     let gamepads = Gamepads::new();
+     let actions = ActionState::default();
+     let player_actions = vec![ActionState::default();players];
+     let net = None;
+     let click_path = vec![];
+     let settings_dirty = true;
+     let window_fitted = (0,0);
+     let mouse = Vector2::default();
+     let camera = None;
+     let bots = vec![];
+     let tally = 0_f32;
+     let unlocked = load_unlocked();
+     let levels_selected = 0;
+     let map_path = None;
+     let editor = None;
+     let menu_selected = MENU_ITEMS.iter().position(|&item| item == if save_exists() {MenuItem::Continue} else {MenuItem::NewGame}).unwrap();
+     let options_selected = 0;
+     let battle = BattleSetup::new();
+     let battle_selected = 0;
+     let quit = false;
+     Self { state,screens,screen_w,screen_h,sim,heart,cash,silver_coin,gold_coin,diamond,game_over_text,paused_text,count_down,frames,time,accumulator,pending,recording,record_path,playback,resumed,message,keymap,keys_player,keys_selected,keys_waiting,gamepads,actions,player_actions,net,click_path,settings,settings_dirty,window_fitted,mouse,camera,bots,tally,unlocked,levels_selected,map,map_path,editor,menu_selected,options_selected,battle,battle_selected,quit}
    }
 
+ // The countdown's frames are spread over the sim's countdown so the last one shows as play starts,
+ // and it flashes between its plain and lit clips.
  fn anim_count_down(&mut self,frame_time:f32){
   if self.time > ANIM_DURATION{
     self.frames += 1;
     self.time = 0_f32;
   }
   self.time += frame_time;
-  self.frames %= CD_Y.len();
-  self.count_down.rec.y = CD_Y[self.frames];
+  self.frames %= 2;
+  let clip = if self.frames == 0 {"countdown"} else {"countdown_flash"};
+  let last = atlas().clip(clip).frames.len() - 1;
+  let elapsed = COUNTDOWN_TICKS - self.sim.countdown.min(COUNTDOWN_TICKS);
+  self.count_down.frames = elapsed * last / COUNTDOWN_TICKS;
+  self.count_down.rec = atlas().frame(clip, self.count_down.frames);
 }  
 
  pub fn update_icons(&mut self,frame_time:f32){
@@ -142,107 +275,1079 @@ impl Game {
  }
 
 pub fn update_game_state(&mut self){
-   if self.state != GameState::PAUSED {
-    match self.player.state{
-    State2::SPAWN => {self.state = GameState::STARTING}
-    State2::ALIVE => {self.state = GameState::RUNNING}
-    State2::DEAD => {self.state = GameState::GAMEOVER}
-    _ => {}
+   if self.state.in_play() {
+    let net_down = self.net.as_ref().is_some_and(|net| net.is_down());
+    if self.sim.cleared {
+      if self.state != GameState::LEVELCLEAR {
+        self.state = GameState::LEVELCLEAR;
+        self.tally = 0_f32;
+        self.save_recording();
+        if self.editor.is_none() { // Clearing a play-test opens nothing in the campaign.
+          self.unlock(self.sim.level + 1);
+        }
+      }
+    }else if self.sim.is_over() || net_down {
+      if self.state != GameState::GAMEOVER {
+        self.state = GameState::GAMEOVER;
+        self.save_recording();
+        self.sim.grid.events.push(SoundEvent::GameOver);
+      }
+    }else if self.sim.countdown > 0 {
+      self.state = GameState::STARTING;
+    }else{
+      self.state = GameState::RUNNING;
     }
    }
-    if self.count_down.frames > MAX_FRAME && self.state == GameState::STARTING {
-       self.state = GameState::RUNNING;
-       self.player.state = State2::SPAWNING;
+}
+
+// Start the current level over, or the replay's.
+pub fn restart(&mut self,playback:Option<Playback>){
+    let level = playback.as_ref().map_or(self.sim.level, |playback| playback.replay.level);
+    self.play_level(level, playback);
+}
+
+// Start a level afresh, nothing carries over from the ones before it.
+pub fn play_level(&mut self,level:usize,playback:Option<Playback>){
+    let players = self.sim.players.len();
+    let seed = match &playback {
+      Some(playback) => playback.replay.seed,
+      None => self.sim.seed,
+    };
+    let lives = playback.as_ref().map_or(self.settings.lives, |playback| playback.replay.lives);
+    self.start(Sim::on_map(seed,level,players,lives,self.map.as_ref()), playback, lives);
+}
+
+// Put `sim` in play from its countdown. A new recording starts unless a replay is being watched.
+fn start(&mut self,sim:Sim,playback:Option<Playback>,lives:usize){
+    let players = sim.players.len();
+    self.state = GameState::STARTING;
+    self.screens.clear();
+    self.camera = None;
+    self.accumulator = 0_f32;
+    self.pending = vec![Input::default();players];
+    if playback.is_none() {
+      self.recording = Replay::new(sim.seed,sim.level,players,lives,self.map.as_ref());
+    }
+    self.playback = playback;
+    self.resumed = false;
+    for bot in self.bots.iter_mut() {
+      *bot = Bot::new(bot.slot, bot.difficulty, sim.seed);
+    }
+    self.sim = sim;
+}
+
+fn clear_bonus(&self) -> usize {
+    self.sim.players[0].lifes * LIFE_BONUS
+}
+
+// Count up the clear bonus, once it's all in go on to the next level.
+pub fn next_level(&mut self){
+    let bonus = self.clear_bonus();
+    if self.tally < bonus as f32 {
+      self.tally = bonus as f32;
+      return;
+    }
+    self.sim.players[0].score += bonus;
+    let sim = self.sim.next_level(self.map.as_ref());
+    self.start(sim, None, self.settings.lives);
+    self.resumed = true; // Carried over lives and score don't come back from the seed, so it can't be replayed.
+}
+
+// Open up a campaign level on the level select, for good.
+fn unlock(&mut self,level:usize){
+    let level = level.min(CAMPAIGN.len());
+    if level > self.unlocked {
+      self.unlocked = level;
+      save_unlocked(level);
+    }
+}
+
+// Hand the last player slots to the computer, one difficulty each.
+pub fn set_bots(&mut self,levels:&[Difficulty]){
+    let players = self.sim.players.len();
+    let first = players - levels.len().min(players - 1); // Player one is always a human.
+    self.bots = (first..players).zip(levels).map(|(slot,&level)| Bot::new(slot, level, self.sim.seed)).collect();
+}
+
+// Switch between the solo game and a battle, the window grows or shrinks to fit the HUD rows.
+pub fn set_players(&mut self,players:usize,levels:&[Difficulty]){
+    self.sim = Sim::on_map(self.sim.seed,1,players,self.settings.lives,self.map.as_ref());
+    (self.screen_w,self.screen_h) = screen_size(self.sim.players.len());
+    self.game_over_text = GameOver::new(self.screen_w,self.screen_h);
+    self.paused_text = Paused::new(self.screen_w,self.screen_h);
+    self.count_down = CountDown::new(self.screen_w,self.screen_h);
+    self.set_bots(levels);
+}
+
+// Play online, the session decides the map and which player is ours.
+pub fn set_net(&mut self,net:NetSession){
+    self.start(Sim::new(net.seed,NET_PLAYERS,self.settings.lives), None, self.settings.lives);
+    self.net = Some(net);
+}
+
+pub fn resume(&mut self){
+    match load_game() {
+      Ok((sim,bots)) => {
+        if sim.players.len() != self.sim.players.len() {
+          self.set_players(sim.players.len(), &[]);
+        }
+        self.start(sim, None, self.settings.lives);
+        self.set_bots(&bots); // Once the saved sim is in, bots are seeded from it.
+        self.resumed = true; // The recording would start mid game, so it can't be replayed.
+      }
+      Err(err) => {
+        eprintln!("failed to load save: {}",err);
+        self.restart(None);
+        self.message = "SAVE COULD NOT BE LOADED".to_string();
+      }
+    }
+}
+
+pub fn save(&mut self){
+    let bots:Vec<Difficulty> = self.bots.iter().map(|bot| bot.difficulty).collect();
+    self.message = match save_game(&self.sim, &bots) {
+      Ok(()) => "GAME SAVED".to_string(),
+      Err(err) => {eprintln!("failed to save game: {}",err); "SAVE FAILED".to_string()}
+    };
+}
+
+pub fn save_recording(&self){
+    if self.resumed {
+      return;
+    }
+    if let (Some(path),None) = (&self.record_path,&self.playback) {
+      if let Err(err) = self.recording.save(path) {
+        eprintln!("failed to save replay: {}",err);
+      }
+    }
+}
+
+pub fn handle_keys_screen(&mut self,rl:&mut RaylibHandle){
+    let actions = player_actions(self.keys_player);
+    if self.keys_waiting { // The next key pressed gets bound to the selected action.
+      if let Some(key) = rl.get_key_pressed() {
+        self.keymap.bind(self.keys_player, actions[self.keys_selected], key);
+        self.keymap.save();
+        self.keys_waiting = false;
+      }
+    }else if self.actions.pressed(Action::MoveUp) {
+      self.keys_selected = (self.keys_selected + actions.len() - 1) % actions.len();
+    }else if self.actions.pressed(Action::MoveDown) {
+      self.keys_selected = (self.keys_selected + 1) % actions.len();
+    }else if self.actions.pressed(Action::MoveLeft) || self.actions.pressed(Action::MoveRight) { // Page through the players.
+      let step = if self.actions.pressed(Action::MoveLeft) {MAX_PLAYERS - 1} else {1};
+      self.keys_player = (self.keys_player + step) % MAX_PLAYERS;
+      self.keys_selected = self.keys_selected.min(player_actions(self.keys_player).len() - 1);
+    }else if rl.is_key_pressed(KEY_ENTER) { // Fixed so a bad binding can always be undone.
+      self.keys_waiting = true;
+    }else if self.actions.pressed(Action::Rebind) || self.actions.pressed(Action::Menu) {
+      self.back();
+    }
+}
+
+// Enter or Continue picks a row on every menu screen, the menu key goes back.
+fn confirm(&self,rl:&RaylibHandle) -> bool {
+    rl.is_key_pressed(KEY_ENTER) || self.actions.pressed(Action::Continue)
+}
+
+// Up and down through `len` rows, wrapping round.
+fn select(&self,selected:usize,len:usize) -> usize {
+    if self.actions.pressed(Action::MoveUp) {
+      (selected + len - 1) % len
+    }else if self.actions.pressed(Action::MoveDown) {
+      (selected + 1) % len
+    }else{
+      selected
+    }
+}
+
+// Open a screen over the current one, `back` returns to it.
+fn open(&mut self,state:GameState){
+    let under = std::mem::replace(&mut self.state, state);
+    self.screens.push(under);
+}
+
+fn back(&mut self){
+    if let Some(state) = self.screens.pop() {
+      self.state = state;
+    }
+}
+
+// Over a game the menu gets a Resume row, a running game comes back through the count down.
+fn open_menu(&mut self){
+    let under = if self.state == GameState::RUNNING {GameState::STARTING} else {self.state.clone()};
+    self.screens.push(under);
+    self.state = GameState::MENU;
+    self.menu_selected = 0;
+}
+
+fn menu_items(&self) -> Vec<MenuItem> {
+    let over_game = self.screens.first().is_some_and(|state| *state != GameState::TITLE);
+    MENU_ITEMS.iter().copied().filter(|&item| item != MenuItem::Resume || over_game).collect()
+}
+
+pub fn handle_title(&mut self,rl:&mut RaylibHandle){
+    if self.confirm(rl) || self.actions.pressed(Action::Pause) {
+      self.open(GameState::MENU);
+    }
+}
+
+pub fn handle_menu(&mut self,rl:&mut RaylibHandle){
+    let items = self.menu_items();
+    self.menu_selected = self.select(self.menu_selected.min(items.len() - 1), items.len());
+    if self.actions.pressed(Action::Menu) {
+      self.back();
+    }else if self.confirm(rl) {
+      match items[self.menu_selected] {
+        MenuItem::Resume => self.back(),
+        MenuItem::NewGame => {
+          self.set_players(1, &[]);
+          self.play_level(1, None);
+        }
+        MenuItem::Continue => if save_exists() {self.resume()},
+        MenuItem::Levels => self.open_levels(),
+        MenuItem::Battle => self.open(GameState::BATTLE),
+        MenuItem::Editor => self.open_editor(),
+        MenuItem::Options => self.open(GameState::OPTIONS),
+        MenuItem::Credits => self.open(GameState::CREDITS),
+        MenuItem::Quit => self.quit = true,
+      }
+    }
+}
+
+pub fn handle_battle_screen(&mut self,rl:&mut RaylibHandle){
+    self.battle_selected = self.select(self.battle_selected, BATTLE_ROWS);
+    if self.actions.pressed(Action::MoveLeft) {
+      self.battle.change(self.battle_selected, -1);
+    }else if self.actions.pressed(Action::MoveRight) {
+      self.battle.change(self.battle_selected, 1);
+    }else if self.actions.pressed(Action::Menu) {
+      self.back();
+    }else if self.confirm(rl) && self.battle_selected == BATTLE_ROWS - 1 {
+      self.set_players(self.battle.players, &self.battle.levels());
+      self.play_level(1, None);
+    }
+}
+
+pub fn handle_options_screen(&mut self,rl:&mut RaylibHandle){
+    self.options_selected = self.select(self.options_selected, OPTION_ITEMS.len());
+    let item = OPTION_ITEMS[self.options_selected];
+    let step = if self.actions.pressed(Action::MoveLeft) {-1} else if self.actions.pressed(Action::MoveRight) || self.confirm(rl) {1} else {0};
+    if self.actions.pressed(Action::Menu) || (item == OptionItem::Back && self.confirm(rl)) {
+      self.back();
+    }else if item == OptionItem::Controls && self.confirm(rl) {
+      self.keys_waiting = false;
+      self.open(GameState::KEYS);
+    }else if step != 0 {
+      self.settings.change(item, step);
+      self.settings.save();
+      self.settings_dirty = true;
+    }
+}
+
+fn open_levels(&mut self){
+    self.levels_selected = (self.sim.level - 1) % CAMPAIGN.len();
+    self.levels_selected = self.levels_selected.min(self.unlocked - 1);
+    self.open(GameState::LEVELS);
+}
+
+pub fn handle_levels_screen(&mut self,rl:&mut RaylibHandle){
+    self.levels_selected = self.select(self.levels_selected, self.unlocked);
+    if self.confirm(rl) {
+      if self.sim.is_battle() {
+        self.set_players(1, &[]);
+      }
+      self.play_level(self.levels_selected + 1, None);
+    }else if self.actions.pressed(Action::LevelSelect) || self.actions.pressed(Action::Menu) {
+      self.back();
+    }
+}
+
+// Edit the `--map` or a blank map the size of this one. The campaign is left where it was.
+pub fn open_editor(&mut self){
+    let path = self.map_path.clone().unwrap_or(EDITOR_MAP.to_string());
+    let map = match &self.map {
+      Some(map) => map.clone(),
+      None => Map::blank(self.sim.grid.cells.len(), self.sim.grid.cells[0].len()),
+    };
+    self.editor = Some(Editor::new(map, path, self.sim.level, self.map.clone()));
+    self.camera = None;
+    self.open(GameState::EDITOR);
+}
+
+// Play the map being edited from the start, as the game would load it.
+pub fn play_test(&mut self){
+    let Some(editor) = &mut self.editor else { return };
+    match editor.checked() {
+      Ok(map) => {
+        editor.tested = true;
+        self.map = Some(map);
+        if self.sim.is_battle() {
+          self.set_players(1, &[]);
+        }
+        self.play_level(1, None);
+      }
+      Err(err) => editor.message = err,
+    }
+}
+
+pub fn close_editor(&mut self){
+    let Some(editor) = self.editor.take() else { return };
+    self.map = editor.game_map;
+    self.camera = None;
+    if editor.tested {
+      self.play_level(editor.level, None);
+    }else{
+      self.back();
+    }
+}
+
+pub fn handle_editor(&mut self,rl:&mut RaylibHandle){
+    let mouse = self.world_mouse();
+    if self.actions.pressed(Action::PlayTest) {
+      self.play_test();
+    }else if self.actions.pressed(Action::Editor) || self.actions.pressed(Action::Menu) {
+      self.close_editor();
+    }else if let Some(editor) = &mut self.editor {
+      if self.actions.pressed(Action::MoveLeft) || self.actions.pressed(Action::MoveUp) {
+        editor.cycle(-1);
+      }else if self.actions.pressed(Action::MoveRight) || self.actions.pressed(Action::MoveDown) {
+        editor.cycle(1);
+      }
+      editor.update(rl, mouse);
     }
 }
 
 pub fn handle_game_state(&mut self,rl:&mut RaylibHandle){
-      if rl.is_key_pressed(KEY_P) && self.state != GameState::GAMEOVER{
-       self.state = if self.state == GameState::PAUSED{GameState::STARTING}else{GameState::PAUSED};
-      }else if rl.is_key_down(KEY_ESCAPE) {
-        self.menu_enable = !self.menu_enable;
-      }else  if rl.is_key_pressed(KEY_R){
-        self.grid = Grid::new();
-        self.player = Player::new();
-        self.count_down.frames = 0;
-     }
-    }
-
-   pub fn draw_game_state(&mut self,d:&mut RaylibDrawHandle,texts:&Texture2D,frame_time:f32){
+      match self.state {
+        GameState::TITLE => self.handle_title(rl),
+        GameState::MENU => self.handle_menu(rl),
+        GameState::BATTLE => self.handle_battle_screen(rl),
+        GameState::OPTIONS => self.handle_options_screen(rl),
+        GameState::CREDITS => if self.confirm(rl) || self.actions.pressed(Action::Menu) {self.back()},
+        GameState::KEYS => self.handle_keys_screen(rl),
+        GameState::LEVELS => self.handle_levels_screen(rl),
+        GameState::EDITOR => self.handle_editor(rl),
+        _ => self.handle_play(),
+      }
+      if ![GameState::TITLE,GameState::MENU,GameState::PAUSED,GameState::STARTING].contains(&self.state) {
+        self.message.clear();
+      }
+    }
+
+// Keys over the game itself, from the count down to the game over and level clear screens.
+fn handle_play(&mut self){
+      let online = self.net.is_some(); // Both peers have to stay on the same game, nothing local may change it.
+      let in_play = self.state == GameState::RUNNING || self.state == GameState::STARTING;
+      let testing = [Action::PlayTest,Action::Editor,Action::Menu].iter().any(|&action| self.actions.pressed(action));
+      if self.editor.is_some() && testing {
+        self.state = GameState::EDITOR; // Back from a play-test.
+      }else if self.actions.pressed(Action::Menu) && !online {
+        self.open_menu();
+      }else if self.actions.pressed(Action::Editor) && !self.sim.is_battle() && !online && !in_play{
+        self.open_editor();
+      }else if self.actions.pressed(Action::LevelSelect) && !self.sim.is_battle() && !online && !in_play && self.editor.is_none(){
+        self.open_levels();
+      }else if self.actions.pressed(Action::DangerOverlay) {
+        self.settings.danger_overlay = !self.settings.danger_overlay;
+        self.settings.save();
+      }else  if self.actions.pressed(Action::Restart) && !online{
+        self.restart(None);
+      }else if self.actions.pressed(Action::WatchReplay) && self.state == GameState::GAMEOVER && !self.resumed && !online{
+        let playback = Playback::new(self.recording.clone());
+        self.restart(Some(playback));
+      }else if self.actions.pressed(Action::Save) && self.state == GameState::PAUSED && !online{
+        self.save();
+      }else if self.actions.pressed(Action::Rebind) && self.state == GameState::PAUSED{
+        self.keys_waiting = false;
+        self.open(GameState::KEYS);
+      }else if self.actions.pressed(Action::Continue) && self.state == GameState::LEVELCLEAR{
+        self.next_level();
+      }
+    }
+
+   pub fn draw_game_state(&mut self,d:&mut impl RaylibDraw,texts:&Texture2D,frame_time:f32){
       match self.state{
-        GameState::GAMEOVER => {self.game_over_text.draw_animate(d, texts,frame_time)}
-        GameState::PAUSED => {self.paused_text.draw_animate(d, texts, frame_time)}
-        GameState::STARTING => {self.count_down.draw_animate(d, texts, frame_time); self.anim_count_down(frame_time)}
+        GameState::GAMEOVER => {
+          self.game_over_text.draw_animate(d, texts,frame_time);
+          self.draw_winner(d);
+          if !self.resumed {self.draw_hint(d,&format!("PRESS {} TO WATCH REPLAY",self.key_name(Action::WatchReplay)))}
+        }
+        GameState::PAUSED => {
+          self.paused_text.draw_animate(d, texts, frame_time);
+          self.draw_hint(d,&format!("PRESS {} TO SAVE, {} FOR THE MENU",self.key_name(Action::Save),self.key_name(Action::Menu)))
+        }
+        GameState::TITLE => {self.draw_title(d)}
+        GameState::MENU => {self.draw_menu(d)}
+        GameState::BATTLE => {self.draw_battle_screen(d)}
+        GameState::OPTIONS => {self.draw_options_screen(d)}
+        GameState::CREDITS => {self.draw_credits(d)}
+        GameState::KEYS => {self.draw_keys_screen(d)}
+        GameState::LEVELCLEAR => {
+          self.draw_level_clear(d);
+          self.draw_hint(d,&format!("PRESS {} FOR LEVEL {} {}",self.key_name(Action::Continue),self.sim.level+1,campaign_level(self.sim.level+1).name))
+        }
+        GameState::LEVELS => {self.draw_levels_screen(d)}
+        GameState::EDITOR => {}
+        GameState::STARTING => {
+          self.anim_count_down(frame_time);
+          d.draw_texture_pro(texts, self.count_down.rec, self.count_down.rec2, Vector2::default(), O, Color::WHITE);
+          if let Some(name) = self.sim.level_name() {self.draw_hint(d,&format!("LEVEL {} {}",self.sim.level,name))}
+        }
       _ => {} 
    
    }  
   }
 
-  pub fn draw_blur(&self,d:&mut RaylibDrawHandle){
-      d.draw_rectangle(0, 0, self.screen_w,self.screen_h-(SCALED_TILE as i32), BLUR_WHITE)
+  pub fn draw_blur(&self,d:&mut impl RaylibDraw){
+      d.draw_rectangle(0, 0, self.screen_w,self.hud_y(0), BLUR_WHITE)
+  }
+
+  // Title over rows of text, the same layout for every list screen.
+  fn draw_list(&self,d:&mut impl RaylibDraw,title:&str,rows:&[(String,Color)],hint_str:&str){
+      self.draw_blur(d);
+      let size = TEXT_SIZE/2;
+      let x = self.screen_w/2 - (TILE_SIZE*4_f32) as i32;
+      let mut y = (TILE_SIZE/2_f32) as i32;
+      d.draw_text(title, x, y, TEXT_SIZE, Color::WHITE);
+      y += TEXT_SIZE + size/2;
+      for (row,color) in rows {
+        d.draw_text(row, x, y, size, *color);
+        y += size + size/4;
+      }
+      d.draw_text(hint_str, x, y + size/2, size, Color::WHITE);
+  }
+
+  fn row_color(selected:bool,enabled:bool) -> Color {
+      if !enabled {Color::GRAY} else if selected {Color::YELLOW} else {Color::WHITE}
+  }
+
+  fn menu_hint(&self) -> String {
+      format!("ENTER TO PICK, {} TO GO BACK",self.key_name(Action::Menu))
+  }
+
+  pub fn draw_title(&self,d:&mut impl RaylibDraw){
+      self.draw_blur(d);
+      let title_str = "BOMBER-MAN";
+      let y = (self.game_over_text.rec2.y - TILE_SIZE*2_f32) as i32;
+      d.draw_text(title_str, self.screen_w/2 - measure_text(title_str, TEXT_SIZE*2)/2, y, TEXT_SIZE*2, Color::YELLOW);
+      self.draw_hint(d,&format!("PRESS ENTER OR {} TO START",self.key_name(Action::Continue)));
+  }
+
+  pub fn draw_menu(&self,d:&mut impl RaylibDraw){
+      let rows:Vec<(String,Color)> = self.menu_items().iter().enumerate().map(|(n,item)| {
+        let enabled = *item != MenuItem::Continue || save_exists();
+        (item.label().to_string(),Game::row_color(n == self.menu_selected, enabled))
+      }).collect();
+      self.draw_list(d, "BOMBER-MAN", &rows, &self.menu_hint());
   }
 
-  pub fn draw_score(&self,d:&mut RaylibDrawHandle){
+  pub fn draw_battle_screen(&self,d:&mut impl RaylibDraw){
+      let rows:Vec<(String,Color)> = self.battle.rows().into_iter().enumerate().map(|(n,row)| (row,Game::row_color(n == self.battle_selected, true))).collect();
+      self.draw_list(d, "BATTLE", &rows, &self.menu_hint());
+  }
+
+  pub fn draw_options_screen(&self,d:&mut impl RaylibDraw){
+      let rows:Vec<(String,Color)> = OPTION_ITEMS.iter().enumerate().map(|(n,&item)| {
+        let row = match self.settings.value(item) {
+          Some(value) => format!("{}  < {} >",item.label(),value),
+          None => item.label().to_string(),
+        };
+        (row,Game::row_color(n == self.options_selected, true))
+      }).collect();
+      self.draw_list(d, "OPTIONS", &rows, &self.menu_hint());
+  }
+
+  pub fn draw_credits(&self,d:&mut impl RaylibDraw){
+      let rows:Vec<(String,Color)> = CREDITS.iter().map(|line| (line.to_string(),Color::WHITE)).collect();
+      self.draw_list(d, "CREDITS", &rows, &format!("{} TO GO BACK",self.key_name(Action::Menu)));
+  }
+
+  // Campaign levels cleared so far and the next one, locked ones are listed but can't be picked.
+  pub fn draw_levels_screen(&self,d:&mut impl RaylibDraw){
+      let rows:Vec<(String,Color)> = CAMPAIGN.iter().enumerate().map(|(n,level)| {
+        let name = if n < self.unlocked {level.name} else {"LOCKED"};
+        (format!("{}  {}",n+1,name),Game::row_color(n == self.levels_selected, n < self.unlocked))
+      }).collect();
+      self.draw_list(d, "SELECT LEVEL", &rows, &self.menu_hint());
+  }
+
+  // Level score and the life bonus counting up into the total.
+  pub fn draw_level_clear(&self,d:&mut impl RaylibDraw){
+      self.draw_blur(d);
+      let size = TEXT_SIZE/2;
+      let mut y = (self.game_over_text.rec2.y - TILE_SIZE*3_f32) as i32;
+      let title_str = format!("LEVEL {} CLEAR",self.sim.level);
+      d.draw_text(&title_str, self.screen_w/2 - measure_text(&title_str, TEXT_SIZE)/2, y, TEXT_SIZE, Color::YELLOW);
+      y += TEXT_SIZE + size/2;
+      let player = &self.sim.players[0];
+      let tally = self.tally as usize;
+      let lines = [
+        format!("LEVEL SCORE {}",self.sim.level_score()),
+        format!("LIVES {} x {} = {}",player.lifes,LIFE_BONUS,tally),
+        format!("TOTAL {}",player.score + player.temp_score + tally),
+      ];
+      for line in lines.iter() {
+        d.draw_text(line, self.screen_w/2 - measure_text(line, size)/2, y, size, Color::WHITE);
+        y += size + size/2;
+      }
+  }
+
+  fn hud_y(&self,row:usize) -> i32 { // Top of a player's HUD row.
+      self.screen_h - (TILE_SIZE as i32)*(self.sim.players.len() - row) as i32
+  }
+
+  fn key_name(&self,action:Action) -> String {
+      key_name(self.keymap.key(action))
+  }
+
+  pub fn draw_keys_screen(&self,d:&mut impl RaylibDraw){
+      self.draw_blur(d);
+      let size = TEXT_SIZE/2;
+      let x = self.screen_w/2 - (TILE_SIZE*4_f32) as i32;
+      let mut y = (TILE_SIZE/2_f32) as i32;
+      let title_str = format!("PLAYER {} CONTROLS",self.keys_player+1);
+      d.draw_text(&title_str, x, y, TEXT_SIZE, PLAYER_COLORS[self.keys_player]);
+      y += TEXT_SIZE + size/2;
+      for (n,&action) in player_actions(self.keys_player).iter().enumerate() {
+        let color = if n == self.keys_selected {Color::YELLOW} else {Color::WHITE};
+        let key = self.keymap.player_key(self.keys_player, action).map_or(String::new(), key_name);
+        let key_str = if n == self.keys_selected && self.keys_waiting {"...".to_string()} else {key};
+        let name_str = action.name().replace('_'," ").to_uppercase();
+        d.draw_text(&name_str, x, y, size, color);
+        d.draw_text(&key_str, x + (TILE_SIZE*5_f32) as i32, y, size, color);
+        y += size + size/4;
+      }
+      let hint_str = format!("ENTER TO REBIND, {}/{} FOR OTHER PLAYERS, {} TO GO BACK",self.key_name(Action::MoveLeft),self.key_name(Action::MoveRight),self.key_name(Action::Rebind));
+      d.draw_text(&hint_str, x, y + size/2, size, Color::WHITE);
+  }
+
+  pub fn draw_score(&self,d:&mut impl RaylibDraw,row:usize){
+      let player = &self.sim.players[row];
       let mut zeroes = "0000".to_string();
-      let score = self.player.score.to_string();
+      let score = player.score.to_string();
       for _i in 1..score.len(){zeroes.pop();}
-      let y = self.screen_h - TEXT_SIZE + TEXT_SIZE/6;
+      let y = self.hud_y(row) + TILE_SIZE as i32 - TEXT_SIZE + TEXT_SIZE/6;
       let score_str = format!("{}{}",zeroes,score);
-      d.draw_text(&score_str, 0,y, TEXT_SIZE,Color::WHITE);
-  }
-
-pub fn draw_icons(&mut self,d:&mut RaylibDrawHandle,sheets:&Texture2D){
-  self.heart.draw(sheets, d);
-  self.cash.draw(sheets, d);
-  self.silver_coin.draw(sheets, d);
-  self.gold_coin.draw(sheets, d);
-  self.diamond.draw(sheets, d);
-}
-
-pub fn draw_text(&mut self,d:&mut RaylibDrawHandle){
-  let life_str = format!(" x{}",self.player.lifes);
-  let x = (self.heart.rec2.x + SCALED_TILE/2_f32) as i32;
-  let y = (self.heart.rec2.y + SCALED_TILE/2_f32) as i32;
-  d.draw_text(&life_str, x, y,TEXT_SIZE/2, Color::WHITE);
-  let cash_str = format!(" x{}",self.player.cash);
-  let x1 = (self.cash.rec2.x + SCALED_TILE/2_f32) as i32;
-  let y1 = (self.cash.rec2.y + SCALED_TILE/2_f32) as i32;
-  d.draw_text(&cash_str, x1, y1,TEXT_SIZE/2, Color::WHITE);
-  let silver_str = format!(" x{}",self.player.silver_coin);
-  let x2 = (self.silver_coin.rec2.x + SCALED_TILE/2_f32) as i32;
-  let y2 = (self.silver_coin.rec2.y + SCALED_TILE/2_f32) as i32;
-  d.draw_text(&silver_str, x2, y2,TEXT_SIZE/2, Color::WHITE);
-  let gold_str = format!(" x{}",self.player.gold_coin);
-  let x3 = (self.gold_coin.rec2.x + SCALED_TILE/2_f32) as i32;
-  let y3 = (self.gold_coin.rec2.y + SCALED_TILE/2_f32) as i32;
-  d.draw_text(&gold_str, x3, y3,TEXT_SIZE/2, Color::WHITE);
-  let diamond_str = format!(" x{}",self.player.diamond);
-  let x4 = (self.diamond.rec2.x + SCALED_TILE/2_f32) as i32;
-  let y4 = (self.diamond.rec2.y + SCALED_TILE/2_f32) as i32;
-  d.draw_text(&diamond_str, x4, y4,TEXT_SIZE/2, Color::WHITE);
-}
-
-pub fn draw(&mut self,d:&mut RaylibDrawHandle,sheets:&Texture2D,frame_time:f32){
-  d.clear_background(BACKGROUND_COLOR);   
-  self.grid.draw(d, sheets);
-  self.player.draw(d,sheets);
+      d.draw_text(&score_str, 0,y, TEXT_SIZE,player.color);
+  }
+
+fn hud_icons(&self,row:usize) -> [Bonus;5] { // The icons moved down onto a player's row.
+  let mut icons = [self.heart.clone(),self.cash.clone(),self.silver_coin.clone(),self.gold_coin.clone(),self.diamond.clone()];
+  for icon in icons.iter_mut() {
+    icon.rec2.y += TILE_SIZE * row as f32;
+  }
+  return icons;
+}
+
+pub fn draw_icons(&self,d:&mut impl RaylibDraw,sheets:&Texture2D,row:usize){
+  for icon in self.hud_icons(row) {
+    icon.draw(sheets, d);
+  }
+}
+
+pub fn draw_text(&self,d:&mut impl RaylibDraw,row:usize){
+  let player = &self.sim.players[row];
+  let counts = [player.lifes,player.cash,player.silver_coin,player.gold_coin,player.diamond];
+  for (icon,count) in self.hud_icons(row).iter().zip(counts) {
+    let count_str = format!(" x{}",count);
+    let x = (icon.rec2.x + TILE_SIZE/2_f32) as i32;
+    let y = (icon.rec2.y + TILE_SIZE/2_f32) as i32;
+    d.draw_text(&count_str, x, y,TEXT_SIZE/2, Color::WHITE);
+  }
+}
+
+pub fn draw_weapon(&self,d:&mut impl RaylibDraw,sheets:&Texture2D,row:usize){
+  let player = &self.sim.players[row];
+  let weapon = player.weapon;
+  if weapon != UpgradeType::Default {
+    let i = (self.diamond.rec2.x / TILE_SIZE) as usize + 2;
+    let j = (self.diamond.rec2.y / TILE_SIZE) as usize + row;
+    Upgrade::new(weapon, player.get_bombs(weapon), i, j).draw(sheets, d);
+  }
+}
+
+// Red over every tile a flame will reach within DANGER_HORIZON, deeper the sooner it burns.
+pub fn draw_danger(&self,d:&mut impl RaylibDraw,visible:&Visible){
+  for (i,rows) in self.sim.grid.danger_map().iter().enumerate() {
+    for (j,&time) in rows.iter().enumerate() {
+      if time < DANGER_HORIZON && visible.contains((i,j)) {
+        let alpha = 60_f32 + 110_f32 * (1_f32 - time / DANGER_HORIZON);
+        let tint = Color { a:alpha as u8, ..DANGER_COLOR };
+        d.draw_rectangle(i as i32 * TILE_SIZE as i32, j as i32 * TILE_SIZE as i32, TILE_SIZE as i32, TILE_SIZE as i32, tint);
+      }
+    }
+  }
+}
+
+pub fn draw_seed(&self,d:&mut impl RaylibDraw){
+  let seed_str = if self.sim.is_battle() {format!("SEED {}",self.sim.seed)} else {format!("LEVEL {}  SEED {}",self.sim.level,self.sim.seed)};
+  let x = (self.diamond.rec2.x + TILE_SIZE*4_f32) as i32;
+  let y = (self.diamond.rec2.y + TILE_SIZE/2_f32) as i32;
+  d.draw_text(&seed_str, x, y,TEXT_SIZE/2, Color::WHITE);
+}
+
+// Level clock over the top wall, red for the last half minute.
+pub fn draw_time(&self,d:&mut impl RaylibDraw){
+  if let Some(time_left) = self.sim.time_left {
+    let secs = time_left.ceil() as usize;
+    let color = if secs <= 30 {Color::RED} else {Color::WHITE};
+    let time_str = format!("{}:{:02}",secs/60,secs%60);
+    let x = self.screen_w/2 - measure_text(&time_str, TEXT_SIZE/2)/2;
+    d.draw_text(&time_str, x, TILE_SIZE as i32/4,TEXT_SIZE/2, color);
+  }
+}
+
+pub fn draw_hint(&self,d:&mut impl RaylibDraw,hint_str:&str){
+  let x = self.screen_w/2 - measure_text(hint_str, TEXT_SIZE/2)/2;
+  let y = (self.game_over_text.rec2.y + TILE_SIZE*1.5) as i32;
+  d.draw_text(hint_str, x, y,TEXT_SIZE/2, Color::WHITE);
+}
+
+pub fn draw_winner(&self,d:&mut impl RaylibDraw){
+  if !self.sim.is_battle() {
+    return;
+  }
+  let (winner_str,color) = match self.sim.winner() {
+    Some(slot) => (format!("PLAYER {} WINS",slot+1),PLAYER_COLORS[slot]),
+    None => ("DRAW".to_string(),Color::WHITE),
+  };
+  let x = self.screen_w/2 - measure_text(&winner_str, TEXT_SIZE)/2;
+  let y = (self.game_over_text.rec2.y - TILE_SIZE*1.5) as i32;
+  d.draw_text(&winner_str, x, y,TEXT_SIZE, color);
+}
+
+pub fn draw_message(&self,d:&mut impl RaylibDraw){
+  let x = self.screen_w/2 - measure_text(&self.message, TEXT_SIZE/2)/2;
+  let y = (self.game_over_text.rec2.y + TILE_SIZE*2.5) as i32;
+  d.draw_text(&self.message, x, y,TEXT_SIZE/2, Color::WHITE);
+}
+
+pub fn draw_editor(&self,d:&mut impl RaylibDraw){
+  let hint_str = format!("CTRL+Z UNDO  CTRL+Y REDO  CTRL+S SAVE  CTRL+O LOAD  {} PLAY  {} LEAVE",self.key_name(Action::PlayTest),self.key_name(Action::Editor));
+  if let Some(editor) = &self.editor {
+    editor.draw_hud(d, self.screen_w, self.hud_y(0), &hint_str);
+  }
+}
+
+// Play-testing an editor map.
+pub fn draw_play_test(&self,d:&mut impl RaylibDraw){
+  if self.editor.is_some() {
+    let test_str = format!("TEST, {} TO EDIT",self.key_name(Action::PlayTest));
+    d.draw_text(&test_str, TILE_SIZE as i32/4, TILE_SIZE as i32/4,TEXT_SIZE/2, Color::YELLOW);
+  }
+}
+
+pub fn draw_playback(&self,d:&mut impl RaylibDraw){
+  if self.playback.is_some() {
+    d.draw_text("REPLAY", TILE_SIZE as i32/4, TILE_SIZE as i32/4,TEXT_SIZE/2, Color::RED);
+  }
+}
+
+// The map and everything on it, only what the view can see.
+pub fn draw_world(&mut self,d:&mut impl RaylibDraw,sheets:&Texture2D){
+  let visible = Visible::new(self.camera.unwrap_or_default(), self.view_size(), self.world_cells());
+  if self.state == GameState::EDITOR {
+    let mouse = self.world_mouse();
+    if let Some(editor) = &mut self.editor {
+      editor.draw(d, sheets, mouse, &visible);
+    }
+    return;
+  }
+  self.sim.grid.draw(d, sheets, &visible);
+  if self.settings.danger_overlay {
+    self.draw_danger(d, &visible);
+  }
+  let alpha = if self.state == GameState::RUNNING {self.accumulator / TICK} else {1_f32};
+  for enemy in self.sim.enemies.iter().filter(|enemy| visible.overlaps(&enemy.rec2)) {
+    enemy.draw(d,sheets,alpha);
+  }
+  for player in self.sim.players.iter_mut() {
+    player.draw(d,sheets,alpha);
+  }
+}
+
+// The world scrolls under the camera, the HUD and every screen over it stay put.
+pub fn draw(&mut self,d:&mut impl RaylibDraw,sheets:&Texture2D,frame_time:f32){
+  d.draw_rectangle(0, 0, self.screen_w, self.screen_h, BACKGROUND_COLOR);
+  {
+    let mut d = d.begin_mode2D(self.world_camera());
+    self.draw_world(&mut d, sheets);
+  }
+  d.draw_rectangle(0, self.hud_y(0), self.screen_w, self.screen_h - self.hud_y(0), BACKGROUND_COLOR); // Over whatever of the map hangs below the view.
+  if self.state == GameState::EDITOR {
+    self.draw_editor(d);
+    return;
+  }
   self.draw_game_state(d,sheets,frame_time);
-  self.draw_score(d);
-  self.draw_icons(d, sheets);
-  self.draw_text(d);
+  for row in 0..self.sim.players.len() {
+    self.draw_score(d,row);
+    self.draw_icons(d,sheets,row);
+    self.draw_text(d,row);
+    self.draw_weapon(d,sheets,row);
+  }
+  self.draw_seed(d);
+  self.draw_time(d);
+  self.draw_playback(d);
+  self.draw_play_test(d);
+  self.draw_message(d);
   //self.draw_blur(d);
   }
 
+  // Menus answer to player one's keys and every gamepad. In a battle each player
+  // gets their own keys plus the gamepad in their slot, alone the player gets everything.
+  pub fn poll_input(&mut self,rl:&RaylibHandle){
+    if let Some(change) = self.gamepads.update(rl) {
+      self.message = change;
+    }
+    let players = self.sim.players.len();
+    let keyboards:Vec<Keyboard> = (0..players).map(|player| Keyboard { rl, keymap: &self.keymap, player }).collect();
+    let pads:Vec<Gamepad> = self.gamepads.ids().into_iter().map(|id| Gamepad { rl, id }).collect();
+    let mut sources:Vec<&dyn InputSource> = vec![&keyboards[0]];
+    for pad in &pads {
+      sources.push(pad);
+    }
+    self.actions = ActionState::poll(&sources);
+    if players == 1 || self.net.is_some() { // Online the remote side comes from the session.
+      self.player_actions = vec![self.actions;players];
+      return;
+    }
+    self.player_actions = keyboards.iter().enumerate().map(|(player,keyboard)| {
+      let mut sources:Vec<&dyn InputSource> = vec![keyboard];
+      if let Some(pad) = pads.iter().find(|pad| pad.id == player as i32) {
+        sources.push(pad);
+      }
+      ActionState::poll(&sources)
+    }).collect();
+  }
+
+  fn next_inputs(&mut self) -> Vec<Input> { // Replayed input while watching, otherwise the live input which gets recorded.
+    match &mut self.playback {
+      Some(playback) => playback.next().unwrap_or_else(|| vec![Input::default();self.sim.players.len()]),
+      None => {
+        let mut inputs = self.pending.clone();
+        for bot in self.bots.iter_mut() {
+          inputs[bot.slot] = bot.think(&self.sim);
+        }
+        self.recording.push(&inputs);
+        inputs
+      }
+    }
+  }
+
+  // Solo click to move, a click on the map walks player one to that tile along the shortest
+  // open path. Returns the arrow keys to hold this frame, so the walk records like any input.
+  fn click_to_move(&mut self,rl:&RaylibHandle) -> Option<Input> {
+    let player = &self.sim.players[0];
+    let moving = [Action::MoveUp,Action::MoveDown,Action::MoveLeft,Action::MoveRight].iter().any(|&action| self.actions.down(action));
+    if self.sim.is_battle() || self.playback.is_some() || self.net.is_some() || self.state == GameState::EDITOR || player.state != State2::ALIVE || moving {
+      self.click_path.clear();
+      return None;
+    }
+    if rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+      let tile = self.world_mouse().map(|mouse| ((mouse.x / TILE_SIZE) as usize,(mouse.y / TILE_SIZE) as usize));
+      let cells = &self.sim.grid.cells;
+      self.click_path = match tile {
+        Some(tile) if tile.0 < cells.len() && tile.1 < cells[0].len() => {
+          find_path(cells, player.get_position(), tile, walker_cost).unwrap_or_default().into_iter().skip(1).collect()
+        }
+        _ => vec![],
+      };
+    }
+    let &next = self.click_path.first()?;
+    let steer = player.steer(next);
+    if steer.is_none() {
+      self.click_path.remove(0);
+    }
+    Some(steer.unwrap_or_default())
+  }
+
+  // Step one tick, false when online and the peer's input for it hasn't arrived.
+  fn tick(&mut self) -> bool {
+    let inputs = match &self.net {
+      Some(net) => match net.inputs() {
+        Some(inputs) => {self.recording.push(&inputs); inputs}
+        None => return false,
+      },
+      None => self.next_inputs(),
+    };
+    if inputs.iter().any(|input| input.pause) {
+      self.state = if self.state == GameState::PAUSED{GameState::STARTING}else{GameState::PAUSED};
+    }
+    if self.state == GameState::RUNNING || self.state == GameState::STARTING {
+      self.sim.step(&inputs, TICK);
+      self.update_icons(TICK);
+    }
+    if let Some(net) = &mut self.net {
+      net.advance(&self.pending[net.slot], self.sim.hash());
+    }
+    return true;
+  }
+
+  pub fn play_sounds(&mut self,audio:&mut RaylibAudio,sounds:GameSounds){
+    let (exp_sound,bonus_sound,gameover,upgrade_sound,win_sound,burning_sound,punch) = sounds;
+    for event in self.sim.drain_events(){
+      let sound = match event {
+        SoundEvent::Explosion => exp_sound,
+        SoundEvent::Bonus => bonus_sound,
+        SoundEvent::Upgrade => upgrade_sound,
+        SoundEvent::EnemyDeath => burning_sound,
+        SoundEvent::Punch => punch,
+        SoundEvent::Win => win_sound,
+        SoundEvent::GameOver => gameover,
+      };
+      if let Some(sound) = sound {
+        audio.play_sound(sound);
+      }
+    }
+  }
+
+  fn clear_presses(&mut self){
+    for pending in self.pending.iter_mut() {
+      pending.bomb = false;
+      pending.cycle = false;
+      pending.pause = false;
+    }
+  }
+
+  // The game is laid out at one pixel per sprite pixel, this is what it's drawn at before scaling to the window.
+  pub fn native_size(&self) -> (i32,i32) {
+    (self.screen_w,self.screen_h)
+  }
+
+  // Window size for the chosen multiple of the native resolution, fullscreen takes the whole monitor instead.
+  pub fn window_size(&self) -> (i32,i32) {
+    let scale = WINDOW_SCALES[self.settings.window_scale] as i32;
+    let (width,height) = self.native_size();
+    (width * scale,height * scale)
+  }
+
+  // The view scrolled over the map.
+  pub fn world_camera(&self) -> Camera2D {
+    Camera2D { offset: Vector2::default(), target: self.camera.unwrap_or_default(), rotation: 0_f32, zoom: 1_f32 }
+  }
+
+  // The map's part of the screen, in game coordinates.
+  fn view_size(&self) -> Vector2 {
+    Vector2::new(VIEW_TILES.0 as f32 * TILE_SIZE, VIEW_TILES.1 as f32 * TILE_SIZE)
+  }
+
+  // Tiles of the map on screen, the editor's while editing.
+  fn world_cells(&self) -> &Vec<Vec<Cell>> {
+    match (&self.state,&self.editor) {
+      (GameState::EDITOR,Some(editor)) => &editor.map.cells,
+      _ => &self.sim.grid.cells,
+    }
+  }
+
+  // The mouse over the map, scrolled with the view. None over the HUD or off the screen.
+  fn world_mouse(&self) -> Option<Vector2> {
+    let view = self.view_size();
+    let mouse = self.mouse;
+    if mouse.x < 0_f32 || mouse.y < 0_f32 || mouse.x >= view.x || mouse.y >= view.y {
+      return None;
+    }
+    Some(mouse + self.camera.unwrap_or_default())
+  }
+
+  // Keeps the view on the map, a map smaller than the view sits in the middle of it.
+  fn clamp_camera(&self,camera:Vector2) -> Vector2 {
+    let cells = self.world_cells();
+    let world = Vector2::new(cells.len() as f32 * TILE_SIZE, cells[0].len() as f32 * TILE_SIZE);
+    clamp_view(camera, world, self.view_size())
+  }
+
+  // The view centered on the players it follows, online our own and otherwise every human.
+  fn camera_goal(&self) -> Vector2 {
+    let followed:Vec<&Player> = self.sim.players.iter().enumerate().filter(|&(slot,_)| match &self.net {
+      Some(net) => slot == net.slot,
+      None => !self.bots.iter().any(|bot| bot.slot == slot),
+    }).map(|(_,player)| player).collect();
+    let mut center = Vector2::default();
+    for player in &followed {
+      center += Vector2::new(player.rec2.x + player.rec2.width/2_f32, player.rec2.y + player.rec2.height/2_f32);
+    }
+    center / followed.len() as f32 - self.view_size() / 2_f32 // Player one is never a bot, so someone is followed.
+  }
+
+  // Which way the mouse pushes the editor's view, -1, 0 or 1 on each axis.
+  fn edge_scroll(&self) -> Vector2 {
+    if self.world_mouse().is_none() {
+      return Vector2::default();
+    }
+    let view = self.view_size();
+    let edge = TILE_SIZE/2_f32;
+    let push = |at:f32,len:f32| if at < edge {-1_f32} else if at >= len - edge {1_f32} else {0_f32};
+    Vector2::new(push(self.mouse.x, view.x), push(self.mouse.y, view.y))
+  }
+
+  // Eases the view after the players, the editor scrolls it with the mouse at the view's edge instead.
+  fn move_camera(&mut self,frame_time:f32){
+    let editing = self.state == GameState::EDITOR;
+    let camera = match self.camera {
+      None if editing => Vector2::default(),
+      None => self.camera_goal(),
+      Some(camera) if editing => camera + self.edge_scroll() * (EDGE_SCROLL * TILE_SIZE * frame_time),
+      Some(camera) => camera + (self.camera_goal() - camera) * (1_f32 - (-CAMERA_DAMPING * frame_time).exp()),
+    };
+    self.camera = Some(self.clamp_camera(camera));
+  }
+
+  // Where the native target lands in the window.
+  pub fn screen_rect(&self,width:i32,height:i32) -> Rectangle {
+    fit_rect(self.native_size(), (width,height), self.settings.pixel_perfect)
+  }
+
+  // The window can be resized by hand, it only snaps to the chosen size when that changes.
+  fn fit_window(&mut self,rl:&mut RaylibHandle){
+    if self.settings.fullscreen != rl.is_window_fullscreen() {
+      if self.settings.fullscreen { // Fullscreen keeps the window's size as its resolution, so grow to the monitor first.
+        let monitor = get_current_monitor();
+        rl.set_window_size(get_monitor_width(monitor), get_monitor_height(monitor));
+      }else{
+        self.window_fitted = (0,0);
+      }
+      rl.toggle_fullscreen();
+    }
+    let size = self.window_size();
+    if !rl.is_window_fullscreen() && self.window_fitted != size { // Battles have more HUD rows than the solo game.
+      rl.set_window_size(size.0, size.1);
+      self.window_fitted = size;
+    }
+  }
+
+  // Volumes, vsync and the frame cap, the jingles count as music and everything else as effects.
+  fn apply_settings(&mut self,rl:&mut RaylibHandle,audio:&mut RaylibAudio,sounds:GameSounds){
+    if !self.settings_dirty {
+      return;
+    }
+    self.settings_dirty = false;
+    let (exp_sound,bonus_sound,gameover,upgrade_sound,win_sound,burning_sound,punch) = sounds;
+    audio.set_master_volume(self.settings.master_volume as f32 / 100_f32);
+    for sound in [exp_sound,bonus_sound,upgrade_sound,burning_sound,punch].into_iter().flatten() {
+      audio.set_sound_volume(sound, self.settings.sfx_volume as f32 / 100_f32);
+    }
+    for sound in [gameover,win_sound].into_iter().flatten() {
+      audio.set_sound_volume(sound, self.settings.music_volume as f32 / 100_f32);
+    }
+    let vsync = WindowState::default().set_vsync_hint(true);
+    if self.settings.vsync {
+      rl.set_window_state(vsync);
+    }else{
+      rl.clear_window_state(vsync);
+    }
+    rl.set_target_fps(FPS_CAPS[self.settings.fps_cap]);
+  }
+
   pub fn update(&mut self,rl:&mut raylib::RaylibHandle,audio:&mut RaylibAudio,sounds:GameSounds,frame_time:f32){
-    let (&ref exp_sound,&ref bonus_sound,&ref gameover,&ref upgrade_sound,&ref win_sound,&ref burning_sound,&ref punch) = sounds;
-    let player_sounds:PlayerSounds = (&bonus_sound,&upgrade_sound);
+    self.apply_settings(rl, audio, sounds);
+    self.fit_window(rl);
+    let screen = self.screen_rect(rl.get_screen_width(), rl.get_screen_height());
+    let mouse = rl.get_mouse_position();
+    self.mouse = Vector2::new((mouse.x - screen.x) / screen.width * self.screen_w as f32, (mouse.y - screen.y) / screen.height * self.screen_h as f32);
+    self.poll_input(rl);
+    if let Some(net) = &mut self.net {
+      net.poll();
+    }
+    let steer = self.click_to_move(rl);
+    for (n,(pending,actions)) in self.pending.iter_mut().zip(&self.player_actions).enumerate() {
+      let mut input = actions.to_input();
+      if let (0,Some(steer)) = (n,steer) {
+        input = Input { up: steer.up, down: steer.down, left: steer.left, right: steer.right, ..input };
+      }
+      let bomb = pending.bomb || input.bomb; // Keep a press until a tick consumes it.
+      let cycle = pending.cycle || input.cycle;
+      let pause = pending.pause || input.pause;
+      *pending = Input { bomb, cycle, pause, ..input };
+    }
     self.handle_game_state(rl);
+    if let Some(message) = self.net.as_ref().and_then(|net| net.status_message()) {
+      self.message = message;
+    }
     self.update_game_state();
-    if self.state == GameState::RUNNING {
-    self.player.update(rl, &mut self.grid, audio, player_sounds, frame_time);
-    self.grid.update(audio, exp_sound, frame_time);
-    self.update_icons(frame_time);
+    if self.state == GameState::LEVELCLEAR {
+      self.tally = (self.tally + TALLY_RATE*frame_time).min(self.clear_bonus() as f32);
     }
+    if self.state.ticking() {
+      self.accumulator += frame_time.min(MAX_FRAME_TIME);
+      while self.accumulator >= TICK {
+        if !self.tick() { // Stalled, hold the presses and wait for the peer.
+          self.accumulator = self.accumulator.min(TICK);
+          break;
+        }
+        self.clear_presses();
+        self.accumulator -= TICK;
+        self.update_game_state();
+        if !self.state.ticking() { // Game over or level clear, no more ticks on the back of this frame.
+          self.accumulator = 0_f32;
+          break;
+        }
+      }
+    }else{
+      self.clear_presses();
+    }
+    self.move_camera(frame_time);
+    self.play_sounds(audio, sounds);
  }
  
 }
+
+// The top left of a `view` over a `world`, both in game coordinates, kept from showing past the world's edges.
+fn clamp_view(camera:Vector2,world:Vector2,view:Vector2) -> Vector2 {
+  let clamp = |at:f32,world:f32,view:f32| if world <= view {(world - view)/2_f32} else {at.clamp(0_f32, world - view)};
+  Vector2::new(clamp(camera.x, world.x, view.x), clamp(camera.y, world.y, view.y))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NATIVE:(i32,i32) = (240,208);
+
+  #[test]
+  fn pixel_perfect_floors_the_zoom(){
+    let rect = fit_rect(NATIVE, (600,520), true); // 2.5x fits, 2x is drawn.
+    assert_eq!((rect.width,rect.height), (480_f32,416_f32));
+    assert_eq!((rect.x,rect.y), (60_f32,52_f32));
+    let rect = fit_rect(NATIVE, (600,520), false);
+    assert_eq!((rect.x,rect.y,rect.width,rect.height), (0_f32,0_f32,600_f32,520_f32));
+  }
+
+  #[test]
+  fn a_wider_window_gets_bars_on_the_sides(){
+    let rect = fit_rect(NATIVE, (1000,416), false);
+    assert_eq!((rect.width,rect.height), (480_f32,416_f32));
+    assert_eq!((rect.x,rect.y), (260_f32,0_f32));
+    let rect = fit_rect(NATIVE, (480,1000), true);
+    assert_eq!((rect.x,rect.y,rect.width,rect.height), (0_f32,292_f32,480_f32,416_f32));
+  }
+
+  #[test]
+  fn a_window_under_native_size_shrinks(){
+    let rect = fit_rect(NATIVE, (120,104), true);
+    assert_eq!((rect.x,rect.y,rect.width,rect.height), (0_f32,0_f32,120_f32,104_f32));
+  }
+
+  fn tiles(i:f32,j:f32) -> Vector2 {
+    Vector2::new(i * TILE_SIZE, j * TILE_SIZE)
+  }
+
+  #[test]
+  fn the_camera_never_shows_past_the_map(){
+    let (world,view) = (tiles(64_f32, 40_f32), tiles(VIEW_TILES.0 as f32, VIEW_TILES.1 as f32));
+    for camera in [tiles(-5_f32, -5_f32), tiles(50_f32, 30_f32), tiles(10_f32, -1_f32), tiles(-1_f32, 39_f32)] {
+      let at = clamp_view(camera, world, view);
+      assert!(at.x >= 0_f32 && at.y >= 0_f32, "{:?} shows past the top left", camera);
+      assert!(at.x + view.x <= world.x && at.y + view.y <= world.y, "{:?} shows past the bottom right", camera);
+    }
+    assert_eq!(clamp_view(tiles(10_f32, 5_f32), world, view), tiles(10_f32, 5_f32), "inside the map it stays put");
+    assert_eq!(clamp_view(tiles(50_f32, 30_f32), world, view), tiles(32_f32, 22_f32));
+  }
+
+  #[test]
+  fn a_map_smaller_than_the_view_sits_in_the_middle(){
+    let view = tiles(VIEW_TILES.0 as f32, VIEW_TILES.1 as f32);
+    let at = clamp_view(tiles(3_f32, 3_f32), tiles(12_f32, 8_f32), view);
+    assert_eq!(at, tiles(-10_f32, -5_f32));
+  }
+}
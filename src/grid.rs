@@ -1,31 +1,36 @@
 use raylib::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::Range;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use crate::objects::*;
 use crate::bomb::*;
-use crate::noise::*;
 use crate::bonus::*;
 use crate::upgrade::*;
+use crate::sim::SoundEvent;
+use crate::cell::*;
+use crate::map::Map;
+use crate::atlas::atlas;
+use serde::{Serialize,Deserialize};
 
-const ROWS:usize = 32;
-const COLS:usize = 18;
-
-pub type GameSounds<'a> = (&'a Sound, &'a Sound, &'a Sound, &'a Sound, &'a Sound, &'a Sound,&'a Sound);
-pub type CollisonBools = (bool,bool,bool,bool,bool);
+pub type CollisonBools = (bool,bool,bool,bool,bool,bool);
 pub type Position = (usize,usize);
+pub type DangerMap = Vec<Vec<f32>>; // Seconds until a flame covers each cell, INFINITY when none will.
 
 pub const TILE_SIZE:f32 = 16_f32;
-pub const SCALE:f32 = 3_f32;
-pub const SCALED_TILE:f32 = TILE_SIZE*SCALE;
-pub const MARGIN_POS:f32 = TILE_SIZE/8_f32*SCALE;
+pub const MARGIN_POS:f32 = TILE_SIZE/8_f32;
+
+const EXIT_SEED:u64 = 0x65786974; // Mixed into the map seed so the exit doesn't follow the map rng.
+const EXIT_CLOSED:Color = Color::new(70,70,84,255);
+const EXIT_OPEN:Color = Color::new(255,203,0,255);
 
 pub const MAX_FRAME:usize = 3;
 pub const ANIM_DURATION:f32 = 0.22_f32;
 pub const MAX_RAND_FRAME:usize = 15;
-//frames const
 pub const O:f32 = 0_f32;
-pub const FRAMES:[f32;14] = [0.0,16.0,32.0,48.0,64.0,80.0,96.0,112.0,128.0,144.0,160.0,172.0,188.0,204.0];
 //sub neg mid and end flamns enum values
 
-#[derive(PartialEq,Clone,Debug,Copy)] // Seprate the flamne objs and add bonus and upgrade objs and create a new accumulator for flame obj
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)] // Seprate the flamne objs and add bonus and upgrade objs and create a new accumulator for flame obj
 pub enum GameObjs {
     Default,
     Wall(Wall),
@@ -41,150 +46,170 @@ pub enum GameObjs {
     FlameDownMid(FlameDownMid),
 }
 
-macro_rules! impl_inject_flames {
-    ($obj:ident,$name:ident,$check:expr,$check2:expr,
-        $flame_mid_left:expr,$flame_end_left:expr,
-        $flame_mid_right:expr,$flame_end_right:expr,
-        $flame_mid_top:expr,$flame_end_top:expr,
-        $flame_mid_down:expr,$flame_end_down:expr,
-        $matrix:ident) => {
-    impl $obj {
-        pub fn $name(&mut self, l: usize, r: usize, c: usize) {
-            if self.cells[r][c] == $check {
-                for &(x, y, mid, end) in 
-                [(-1, 0,$flame_mid_left, $flame_end_left),
-                 (1, 0, $flame_mid_right,$flame_end_right),
-                 (0, -1,$flame_mid_top,$flame_end_top), 
-                 (0, 1, $flame_mid_down,$flame_end_down)].iter() {
-                    for i in 1..=l {
-                        let row = (r as isize + x as isize * i as isize) as usize;
-                        let col = (c as isize + y as isize * i as isize) as usize;
-                        let cell = &mut self.$matrix[row][col];
-                        if *cell != $check2 {
-                            break;
-                        }
-                        *cell = if i == l { end } else { mid };
-                    }
-                }
-            }
-        } 
-      }
-    };
+// Plain rectangle overlap, kept off raylib's native call so the sim runs headless.
+pub fn overlaps(a:&Rectangle,b:&Rectangle) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+// Tiles the camera can see, the ends exclusive. Drawing skips everything outside them.
+#[derive(Clone,Debug)]
+pub struct Visible {
+    pub cols:Range<usize>,
+    pub rows:Range<usize>,
+}
+
+impl Visible {
+    // Tiles under a view of `size` with its top left at `corner`, both in game coordinates.
+    pub fn new(corner:Vector2,size:Vector2,cells:&[Vec<Cell>]) -> Self {
+        let first = |at:f32| (at / TILE_SIZE).floor().max(0_f32) as usize;
+        let last = |at:f32,len:usize| ((at / TILE_SIZE).ceil().max(0_f32) as usize).min(len);
+        let cols = first(corner.x)..last(corner.x + size.x, cells.len());
+        let rows = first(corner.y)..last(corner.y + size.y, cells[0].len());
+        Self { cols, rows }
+    }
+
+    pub fn contains(&self,(i,j):Position) -> bool {
+        self.cols.contains(&i) && self.rows.contains(&j)
+    }
+
+    pub fn overlaps(&self,rec:&Rectangle) -> bool {
+        let tiles = Rectangle::new(self.cols.start as f32 * TILE_SIZE, self.rows.start as f32 * TILE_SIZE, self.cols.len() as f32 * TILE_SIZE, self.rows.len() as f32 * TILE_SIZE);
+        overlaps(&tiles, rec)
+    }
 }
 
-impl_inject_flames!(Grid,inject_flames,EXPLOSION,EMPTY,
-    FLAME_MID_LEFT,FLAME_END_LEFT,FLAME_MID_RIGHT,
-    FLAME_END_RIGHT,FLAME_MID_TOP,FLAME_END_TOP,
-    FLAME_MID_DOWN,FLAME_END_DOWN,cells);
-    
-impl_inject_flames!(Grid,inject_flames_obj,EXPLOSION,GameObjs::Default, 
-    GameObjs::FlameLeftMid(FlameLeftMid::new()),GameObjs::FlameLeftEnd(FlameLeftEnd::new()),
-    GameObjs::FlameRightMid(FlameRightMid::new()),GameObjs::FlameRightEnd(FlameRightEnd::new()), 
-    GameObjs::FlameTopMid(FlameTopMid::new()), GameObjs::FlameTopEnd(FlameTopEnd::new()),
-    GameObjs::FlameDownMid(FlameDownMid::new()), GameObjs::FlameDownEnd(FlameDownEnd::new()),game_objs);
+// Bomb waiting to go off, ordered so the heap pops the shortest fuse first.
+#[derive(PartialEq)]
+struct Fuse(f32,usize,usize);
 
+impl Eq for Fuse {}
+
+impl PartialOrd for Fuse {
+    fn partial_cmp(&self,other:&Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fuse {
+    fn cmp(&self,other:&Self) -> Ordering {
+        other.0.total_cmp(&self.0)
+    }
+}
+
+#[derive(Serialize,Deserialize)]
 pub struct Grid {
   pub empty_vec:Vec<Empty>,
   pub grass_vec:Vec<Grass>,
   pub bonus_vec:Vec<Bonus>,
   pub upgrade_vec:Vec<Upgrade>,
-  pub cells:Vec<Vec<i8>>,
+  pub cells:Vec<Vec<Cell>>,
   pub game_objs:Vec<Vec<GameObjs>>,
+  pub spawns:Vec<Position>,
+  pub exit:Option<Position>,
+  pub exit_open:bool,
+  #[serde(skip)]
+  pub events:Vec<SoundEvent>,
 }
 
 impl Grid {
-    pub fn new() -> Self  {
-        let mut cells = noise(ROWS, COLS);
+    // Objects for every tile of the map. Pass the rng the map was made with so a seed gives the same tile art.
+    pub fn from_map(map:&Map,rng:&mut StdRng) -> Self  {
+        let cells = map.cells.clone();
         let mut empty_vec:Vec<Empty> = vec![];
         let mut grass_vec:Vec<Grass> = vec![];
         let mut bonus_vec:Vec<Bonus> = vec![];
         let mut upgrade_vec:Vec<Upgrade> = vec![];
-        let mut game_objs:Vec<Vec<GameObjs>> = vec![vec![GameObjs::Default;COLS]; ROWS];
+        let mut game_objs:Vec<Vec<GameObjs>> = vec![vec![GameObjs::Default;cells[0].len()]; cells.len()];
 
-        for (i,rows) in cells.iter_mut().enumerate(){
-          for (j,cell) in rows.iter_mut().enumerate(){
-                if *cell == WALL {
-                    let wall = Wall::new(i,j,SCALE);
+        for (i,rows) in cells.iter().enumerate(){
+          for (j,cell) in rows.iter().enumerate(){
+                match cell.terrain {
+                  Terrain::Wall => {
+                    let wall = Wall::new(i,j);
                     game_objs[i][j] = GameObjs::Wall(wall);
-                }else if *cell == BLOCK{
-                    let block = Block::new(i,j,SCALE);
+                  }
+                  Terrain::Block => {
+                    let block = Block::new(i,j,rng);
                     game_objs[i][j] = GameObjs::Block(block);
-                }else if *cell == EMPTY{
-                    let grass = Grass::new(i,j,SCALE);
-                    grass_vec.push(grass);
-                }else if *cell == HEART {
-                   let bonus = Bonus::new(BonusType::Heart, i, j, SCALE);
-                   bonus_vec.push(bonus);
-                   *cell = EMPTY;
-                }else if *cell == CASH {
-                  let bonus = Bonus::new(BonusType::Cash, i, j, SCALE);
-                  bonus_vec.push(bonus);
-                  *cell = EMPTY;
-               }else if *cell == SILVER_COIN {
-                   let bonus = Bonus::new(BonusType::SilverCoin, i, j, SCALE);
-                   bonus_vec.push(bonus);
-                  *cell = EMPTY;
-               }else if *cell == GOLD_COIN {
-                  let bonus = Bonus::new(BonusType::GoldCoin, i, j, SCALE);
-                  bonus_vec.push(bonus);
-                  *cell = EMPTY;
-               }else if *cell == DIAMOND {
-                  let bonus = Bonus::new(BonusType::Diamond, i, j, SCALE);
-                  bonus_vec.push(bonus);
-                  *cell = EMPTY;
-               }else if *cell == BOMB_BLACK_2X {
-                   let upgrade = Upgrade::new(UpgradeType::BlackBomb,2, i, j, SCALE);
-                   upgrade_vec.push(upgrade);
-                  *cell = EMPTY;
-               }else if *cell == BOMB_BLACK_3X {
-                   let upgrade = Upgrade::new(UpgradeType::BlackBomb,3, i, j, SCALE);
-                   upgrade_vec.push(upgrade);
-                  *cell = EMPTY;
-               }else if *cell == BOMB_BLUE_2X {
-                  let upgrade = Upgrade::new(UpgradeType::BlueBomb,2, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                 *cell = EMPTY;
-                }else if *cell == BOMB_BLUE_3X {
-                  let upgrade = Upgrade::new(UpgradeType::BlueBomb,3, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                 *cell = EMPTY;
-                }else if *cell == BOMB_PURPLE_2X {
-                  let upgrade = Upgrade::new(UpgradeType::PurpleBomb,2, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                  *cell = EMPTY;
-               }else if *cell == BOMB_PURPLE_3X {
-                  let upgrade = Upgrade::new(UpgradeType::PurpleBomb,3, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                 *cell = EMPTY;
-               }else if *cell == BOMB_RED_2X {
-                  let upgrade = Upgrade::new(UpgradeType::RedBomb,2, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                 *cell = EMPTY;
-                }else if *cell == BOMB_RED_3X {
-                  let upgrade = Upgrade::new(UpgradeType::RedBomb,3, i, j, SCALE);
-                  upgrade_vec.push(upgrade);
-                 *cell = EMPTY;
-                }else if *cell == WIN_CELL {
-                  *cell = EMPTY;
+                  }
+                  Terrain::Empty => {
+                    match cell.item {
+                      Item::Bonus(bonus_type) => {bonus_vec.push(Bonus::new(bonus_type, i, j));}
+                      Item::Upgrade(up_type,val) => {upgrade_vec.push(Upgrade::new(up_type,val, i, j));}
+                      Item::Default | Item::Exit => {grass_vec.push(Grass::new(i,j,rng));}
+                    }
+                  }
                 }
-                let empty = Empty::new(i,j,SCALE);
+                let empty = Empty::new(i,j,rng);
                 empty_vec.push(empty);
             }
         }
-        return Self {empty_vec,bonus_vec,upgrade_vec,grass_vec,cells,game_objs};
+        let spawns = map.spawns.clone();
+        let exit = map.cells.iter().flatten().position(|cell| cell.item == Item::Exit).map(|n| (n / cells[0].len(), n % cells[0].len()));
+        let exit_open = false;
+        let events = vec![];
+        return Self {empty_vec,bonus_vec,upgrade_vec,grass_vec,cells,game_objs,spawns,exit,exit_open,events};
+    }
+
+    // Bury the level exit under a random breakable wall, in place of whatever item it hid.
+    pub fn hide_exit(&mut self,seed:u64){
+        let mut rng = StdRng::seed_from_u64(seed ^ EXIT_SEED);
+        let mut walls = vec![];
+        for (i,rows) in self.cells.iter().enumerate() {
+          for (j,cell) in rows.iter().enumerate() {
+            if cell.terrain == Terrain::Wall {
+              walls.push((i,j));
+            }
+          }
+        }
+        if walls.is_empty() {
+          return;
+        }
+        let (i,j) = walls[rng.gen_range(0..walls.len())];
+        self.cells[i][j].item = Item::Exit;
+        self.exit = Some((i,j));
     }
 
     pub fn rm_game_obj(&mut self,i:usize,j:usize){
-        self.cells[i][j] = EMPTY;
+        if self.cells[i][j].terrain == Terrain::Wall {
+          self.reveal_item(i, j);
+        }
+        let cell = &mut self.cells[i][j];
+        cell.terrain = Terrain::Empty;
+        cell.bomb = false;
+        cell.flame = Flame::Default;
         self.game_objs[i][j] = GameObjs::Default;  
     }
 
-    pub fn rm_bonus_obj(&mut self,i:usize){
-      self.bonus_vec[i].bonus_type = BonusType::Default;
+    // Turn the item hidden under a wall into a visible pickup.
+    fn reveal_item(&mut self,i:usize,j:usize){
+      match self.cells[i][j].item {
+        Item::Bonus(bonus_type) => {self.bonus_vec.push(Bonus::new(bonus_type, i, j));}
+        Item::Upgrade(up_type,val) => {self.upgrade_vec.push(Upgrade::new(up_type,val, i, j));}
+        Item::Default | Item::Exit => {}
+      }
     }
 
-    pub fn rm_upgrade_obj(&mut self,i:usize){
-      self.upgrade_vec[i].up_type = UpgradeType::Default;
+    // Swallow a bomb that hasn't gone off yet, true if there was one.
+    pub fn eat_bomb(&mut self,i:usize,j:usize) -> bool {
+      match self.game_objs[i][j] {
+        GameObjs::Bomb(bomb) if bomb.state == State::IDEAL => {self.rm_game_obj(i, j); true}
+        _ => false,
+      }
+    }
+
+    pub fn rm_item(&mut self,i:usize,j:usize){
+      self.cells[i][j].item = Item::Default;
+      for bonus in &mut self.bonus_vec {
+        if bonus.get_position() == (i,j) {
+          bonus.bonus_type = BonusType::Default;
+        }
+      }
+      for upgrade in &mut self.upgrade_vec {
+        if upgrade.get_position() == (i,j) {
+          upgrade.up_type = UpgradeType::Default;
+        }
+      }
     }
 
     pub fn get_collisions(&self,position:Position,obj_rec:Rectangle) -> CollisonBools {
@@ -192,43 +217,136 @@ impl Grid {
       // Collison bools
       let mut fatal_coll = false; // for Explosion,Flame and Enemies
       let mut neutral_coll = false; // For Walls and Blocks.
-      let bonus_coll = false; // For Bonus Coins
-      let upgrade_coll = false; // For Upgrades Bombs and Life
-      let win_coll = false; // For reaching the winning place
+      let mut bomb_coll = false; // For planted Bombs
+      let mut bonus_coll = false; // For Bonus Coins
+      let mut upgrade_coll = false; // For Upgrades Bombs and Life
+      let mut win_coll = false; // For reaching the winning place
 
       for r in (i)..=(i+1){
          for c in (j)..=(j+1){
              let cell = self.cells[r][c];
-             let cell_x = SCALED_TILE * (r as f32);
-             let cell_y = SCALED_TILE * (c as f32);
+             let cell_x = TILE_SIZE * (r as f32);
+             let cell_y = TILE_SIZE * (c as f32);
 
-             if cell_x + SCALED_TILE > obj_rec.x && cell_x < obj_rec.x + obj_rec.width &&
-                cell_y + SCALED_TILE > obj_rec.y && cell_y < obj_rec.y + obj_rec.height {
-                  if cell == BLOCK || cell == WALL{
+             if cell_x + TILE_SIZE > obj_rec.x && cell_x < obj_rec.x + obj_rec.width &&
+                cell_y + TILE_SIZE > obj_rec.y && cell_y < obj_rec.y + obj_rec.height {
+                  if cell.is_solid(){
                      neutral_coll = true;  
                   }
-                  if cell < EMPTY {
+                  if cell.is_flame() {
                      fatal_coll = true;  
                   }
+                  if cell.bomb {
+                     bomb_coll = true;
+                  }
+                  if cell.terrain == Terrain::Empty {
+                    match cell.item {
+                      Item::Bonus(_) => {bonus_coll = true}
+                      Item::Upgrade(_,_) => {upgrade_coll = true}
+                      Item::Exit => {win_coll = self.exit_open && (r,c) == position} // Standing on it, not brushing past.
+                      Item::Default => {}
+                    }
+                  }
                 }
              }
           }
-      return (fatal_coll,neutral_coll,bonus_coll,upgrade_coll,win_coll);
+      return (fatal_coll,neutral_coll,bomb_coll,bonus_coll,upgrade_coll,win_coll);
+    }
+
+    // Bombs a player has down that haven't finished going off.
+    pub fn live_bombs(&self,owner:usize) -> usize {
+      self.game_objs.iter().flatten().filter(|obj| matches!(obj, GameObjs::Bomb(bomb) if bomb.owner == owner && bomb.state != State::EXPLOADED)).count()
+    }
+
+    // Slot of the player whose blast covers the flames `obj_rec` touches, the same cells get_collisions looks at.
+    pub fn burned_by(&self,position:Position,obj_rec:Rectangle) -> Option<usize> {
+      let (i,j) = position;
+      for r in i..=i+1 {
+        for c in j..=j+1 {
+          let cell_x = TILE_SIZE * (r as f32);
+          let cell_y = TILE_SIZE * (c as f32);
+          let touches = cell_x + TILE_SIZE > obj_rec.x && cell_x < obj_rec.x + obj_rec.width &&
+                        cell_y + TILE_SIZE > obj_rec.y && cell_y < obj_rec.y + obj_rec.height;
+          if !touches || !self.cells[r][c].is_flame() { // Border cells never burn, don't trace back from them.
+            continue;
+          }
+          if let Some(owner) = self.blast_owner(r, c) {
+            return Some(owner);
+          }
+        }
+      }
+      None
+    }
+
+    // Owner of the bomb whose flames reach cell r,c, following the flame back along its row or column.
+    fn blast_owner(&self,r:usize,c:usize) -> Option<usize> {
+      if let GameObjs::Bomb(bomb) = self.game_objs[r][c] {
+        return Some(bomb.owner);
+      }
+      for &(x,y) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+        for k in 1..=MAX_BOMB_POWER {
+          let row = (r as isize + x * k as isize) as usize;
+          let col = (c as isize + y * k as isize) as usize;
+          match self.game_objs[row][col] {
+            GameObjs::Bomb(bomb) if bomb.state == State::EXPLOADING && bomb.power >= k => return Some(bomb.owner),
+            _ if !self.cells[row][col].is_flame() => break,
+            _ => {}
+          }
+        }
+      }
+      None
+    }
+
+    // A blast running into cell i,j stops short of it, flames only go down on empty ground.
+    pub fn stops_blast(&self,i:usize,j:usize) -> bool {
+        !self.cells[i][j].is_empty() || self.game_objs[i][j] != GameObjs::Default
+    }
+
+    pub fn inject_flames(&mut self, l: usize, r: usize, c: usize) {
+        if self.cells[r][c].flame != Flame::Explosion {
+            return;
+        }
+        for &(x, y, mid, end) in 
+        [(-1, 0, Flame::MidLeft, Flame::EndLeft),
+         (1, 0, Flame::MidRight, Flame::EndRight),
+         (0, -1, Flame::MidTop, Flame::EndTop), 
+         (0, 1, Flame::MidDown, Flame::EndDown)].iter() {
+            for i in 1..=l {
+                let row = (r as isize + x as isize * i as isize) as usize;
+                let col = (c as isize + y as isize * i as isize) as usize;
+                if self.stops_blast(row, col) {
+                    break;
+                }
+                let flame = if i == l { end } else { mid };
+                self.cells[row][col].flame = flame;
+                self.game_objs[row][col] = match flame { // Flame obj draws itself while the cell carries its flame.
+                    Flame::MidLeft => GameObjs::FlameLeftMid(FlameLeftMid::new()),
+                    Flame::EndLeft => GameObjs::FlameLeftEnd(FlameLeftEnd::new()),
+                    Flame::MidRight => GameObjs::FlameRightMid(FlameRightMid::new()),
+                    Flame::EndRight => GameObjs::FlameRightEnd(FlameRightEnd::new()),
+                    Flame::MidTop => GameObjs::FlameTopMid(FlameTopMid::new()),
+                    Flame::EndTop => GameObjs::FlameTopEnd(FlameTopEnd::new()),
+                    Flame::MidDown => GameObjs::FlameDownMid(FlameDownMid::new()),
+                    Flame::EndDown => GameObjs::FlameDownEnd(FlameDownEnd::new()),
+                    _ => GameObjs::Default,
+                };
+            }
+        }
     }
 
      pub fn eject_flames(&mut self, r: usize, c: usize) {
-        self.cells[r][c] = EMPTY;
+        self.cells[r][c].flame = Flame::Default;
         for &(x, y) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
             for i in 1..= MAX_BOMB_POWER {
                 let row = (r as isize + x as isize * i as isize) as usize;
                 let col = (c as isize + y as isize * i as isize) as usize;
-                let cell = &mut self.cells[row][col];
-                if *cell > EMPTY {
-                    break;
-                }else if *cell == EXPLOSION{
+                let cell = self.cells[row][col];
+                if cell.flame == Flame::Explosion{
                   self.rm_game_obj(row, col);
                   self.eject_flames( row, col);
                   break;
+                }else if cell.is_solid() || cell.bomb {
+                    break;
                 }
                 self.rm_game_obj(row, col);
                 if i == MAX_BOMB_POWER {
@@ -238,32 +356,91 @@ impl Grid {
         }
     }
 
-    fn get_shadow_val(&self,i:usize,j:usize) -> f32 {
-        let left = self.cells[i-1][j] == WALL || self.cells[i-1][j] == BLOCK;
-        let top = self.cells[i][j-1] == WALL || self.cells[i][j-1] == BLOCK;
-        let top_left = self.cells[i-1][j-1] == WALL || self.cells[i-1][j-1] == BLOCK;
+    // When each cell will next be on fire, from every bomb's fuse and power. Bombs go off in order
+    // of their fuse and set off any bomb their flames reach, which then counts from that moment.
+    // Flames stop where `stops_blast` says, the same as inject_flames.
+    pub fn danger_map(&self) -> DangerMap {
+      let mut danger = vec![vec![f32::INFINITY;self.cells[0].len()];self.cells.len()];
+      let mut set_off = danger.clone(); // Earliest moment each bomb goes off.
+      let mut fuses = BinaryHeap::new();
+      for (i,rows) in self.game_objs.iter().enumerate() {
+        for (j,obj) in rows.iter().enumerate() {
+          if self.cells[i][j].is_flame() {
+            danger[i][j] = 0_f32;
+          }
+          if let GameObjs::Bomb(bomb) = obj {
+            if bomb.state != State::EXPLOADED {
+              set_off[i][j] = bomb.exploading_time.max(0_f32);
+              fuses.push(Fuse(set_off[i][j],i,j));
+            }
+          }
+        }
+      }
+      while let Some(Fuse(time,i,j)) = fuses.pop() {
+        if time > set_off[i][j] {
+          continue; // Already set off sooner by another bomb.
+        }
+        danger[i][j] = danger[i][j].min(time);
+        let power = match self.game_objs[i][j] {
+          GameObjs::Bomb(bomb) => bomb.power,
+          _ => continue,
+        };
+        for &(x,y) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+          for k in 1..=power {
+            let row = (i as isize + x * k as isize) as usize;
+            let col = (j as isize + y * k as isize) as usize;
+            if self.cells[row][col].bomb {
+              if time < set_off[row][col] {
+                set_off[row][col] = time;
+                fuses.push(Fuse(time,row,col));
+              }
+              break;
+            }
+            if self.stops_blast(row, col) {
+              break;
+            }
+            danger[row][col] = danger[row][col].min(time);
+          }
+        }
+      }
+      return danger;
+    }
+
+    // Frame of the floor clip with the shadows the walls above and left of it cast.
+    fn get_shadow_val(&self,i:usize,j:usize) -> usize {
+        let left = self.cells[i-1][j].is_solid();
+        let top = self.cells[i][j-1].is_solid();
+        let top_left = self.cells[i-1][j-1].is_solid();
 
         if !left && !top && !top_left {
-          return FRAMES[0]; 
+          return 0;
         }else if left && top {
-          return FRAMES[4]; 
+          return 4;
         }else if top {
-          return FRAMES[2];  
+          return 2;
         }else if left{
-          return FRAMES[3];
+          return 3;
         }
-        return FRAMES[1];
+        return 1;
     }
 
-    pub fn draw(&mut self,d:&mut RaylibDrawHandle,sheets:&Texture2D){
+    // Dark doorway, its frame lights up once the exit opens.
+    fn draw_exit(&self,d:&mut impl RaylibDraw,i:usize,j:usize){
+        let door = Rectangle::new(i as f32*TILE_SIZE + MARGIN_POS, j as f32*TILE_SIZE + MARGIN_POS, TILE_SIZE - 2_f32*MARGIN_POS, TILE_SIZE - MARGIN_POS);
+        let frame = if self.exit_open {EXIT_OPEN} else {EXIT_CLOSED};
+        d.draw_rectangle_rec(door, Color::BLACK);
+        d.draw_rectangle_lines_ex(door, 1, frame);
+    }
+
+    pub fn draw(&mut self,d:&mut impl RaylibDraw,sheets:&Texture2D,visible:&Visible){
        let mut empty_count = 0;
-    // Draw the empty dynamic shadow tile map first;
-      for (i,rows) in self.cells.iter().enumerate(){
-        for (j,cell) in rows.iter().enumerate(){
-            if *cell != BLOCK {
+    // Draw the empty dynamic shadow tile map first, the empties are only sprites so any of them will do;
+      for i in visible.cols.clone(){
+        for j in visible.rows.clone(){
+            if self.cells[i][j].terrain != Terrain::Block {
                 let frame_val = self.get_shadow_val(i, j);
                 let local_empty = &mut self.empty_vec[empty_count];
-                local_empty.rec.x = frame_val;  
+                local_empty.rec = atlas().frame("floor", frame_val);
                 local_empty.set_position(i,j);
                 local_empty.draw(sheets, d);
                 empty_count += 1;
@@ -271,22 +448,28 @@ impl Grid {
         }
       }
     // Draw grass
-    for grass in &mut self.grass_vec{
+    for grass in self.grass_vec.iter_mut().filter(|grass| visible.overlaps(&grass.rec2)){
         grass.draw(sheets, d);
     }
     // Draw Bonus
-    for bonus in &mut self.bonus_vec{
+    for bonus in self.bonus_vec.iter_mut().filter(|bonus| visible.overlaps(&bonus.rec2)){
         bonus.draw(sheets, d);
     }
      // Draw Upgrades
-    for upgrade in &mut self.upgrade_vec{
+    for upgrade in self.upgrade_vec.iter_mut().filter(|upgrade| visible.overlaps(&upgrade.rec2)){
         upgrade.draw(sheets, d);
     }
+    // Draw the exit once its wall is gone
+    if let Some((i,j)) = self.exit {
+        if self.cells[i][j].terrain == Terrain::Empty && visible.contains((i,j)) {
+          self.draw_exit(d, i, j);
+        }
+    }
     //For Dynamic Objects
-       for (i,rows) in self.game_objs.iter_mut().enumerate(){
-          for (j,obj) in rows.iter_mut().enumerate() {
-               let cell = self.cells[i][j];
-               match obj {
+       for i in visible.cols.clone(){
+          for j in visible.rows.clone() {
+               let flame = self.cells[i][j].flame;
+               match &mut self.game_objs[i][j] {
                   GameObjs::Block(obj) => {obj.draw(sheets, d);},
                   GameObjs::Wall(obj) => {
                     let local_wall = obj;//Get the wall from vector for the current position.
@@ -300,25 +483,25 @@ impl Grid {
                     let local_bomb = obj; //Get BOMB
                     match local_bomb.state {
                       State::IDEAL => {local_bomb.draw(sheets, d);}
-                      State::EXPLOADING => {local_bomb.draw_exp(sheets, d, i, j,cell);},
+                      State::EXPLOADING => {local_bomb.draw_exp(sheets, d, i, j,flame);},
                       _ => {}
                     }
                   }
-                  GameObjs::FlameLeftEnd(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameRightEnd(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameTopEnd(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameDownEnd(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameLeftMid(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameRightMid(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameTopMid(obj) => {obj.draw(sheets, d, i, j,cell);}
-                  GameObjs::FlameDownMid(obj) => {obj.draw(sheets, d, i, j,cell);}
+                  GameObjs::FlameLeftEnd(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameRightEnd(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameTopEnd(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameDownEnd(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameLeftMid(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameRightMid(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameTopMid(obj) => {obj.draw(sheets, d, i, j,flame);}
+                  GameObjs::FlameDownMid(obj) => {obj.draw(sheets, d, i, j,flame);}
                   _ => {}
                }  
             }
           }
     }
 
-    pub fn update(&mut self,audio:&mut RaylibAudio,exp_sound:&Sound,frame_time:f32){
+    pub fn update(&mut self,frame_time:f32){
       // Update Bonus objects
       for bonus in &mut self.bonus_vec{
         bonus.animate(frame_time);
@@ -327,7 +510,7 @@ impl Grid {
       for i in 0..self.game_objs.len(){
         for j in 0..self.game_objs[i].len() {
              let obj = &mut self.game_objs[i][j];
-             let cell = self.cells[i][j]; 
+             let flame = self.cells[i][j].flame; 
              match obj {
                 GameObjs::Wall(obj) => {
                   let local_wall = obj;//Get the wall from vector for the current position.
@@ -340,29 +523,166 @@ impl Grid {
                  GameObjs::Bomb(obj) => {
                   let local_bomb = obj; //Get BOMB
                   let power = local_bomb.power;
-                  local_bomb.update(frame_time,audio,exp_sound);
+                  local_bomb.update(frame_time);
                   match local_bomb.state {
                     State::IDEAL => {local_bomb.chain_exp(i, j, &self.cells);}
                     State::EXPLOADING => {
-                       local_bomb.anim_exp(cell,frame_time);
-                       self.cells[i][j] = EXPLOSION; // Give Signal
-                       self.inject_flames_obj(power, i, j);// Inject Flame obj 
-                       self.inject_flames(power,i, j); // Injects flames consts in matrix to give signal to flame objs
+                       local_bomb.anim_exp(flame,frame_time);
+                       if flame != Flame::Explosion { // First tick of the blast, chained or timed out.
+                         self.events.push(SoundEvent::Explosion);
+                       }
+                       self.cells[i][j].flame = Flame::Explosion; // Give Signal
+                       self.inject_flames(power,i, j); // Injects flames in the cells and flame objs to draw them
                     },
                     State::EXPLOADED => {self.eject_flames( i, j);self.rm_game_obj(i, j);},
                   }
                 }
-               GameObjs::FlameLeftEnd(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameRightEnd(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameTopEnd(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameDownEnd(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameLeftMid(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameRightMid(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameTopMid(obj) => {obj.anim(cell,frame_time);}
-               GameObjs::FlameDownMid(obj) => {obj.anim(cell,frame_time);}
+               GameObjs::FlameLeftEnd(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameRightEnd(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameTopEnd(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameDownEnd(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameLeftMid(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameRightMid(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameTopMid(obj) => {obj.anim(flame,frame_time);}
+               GameObjs::FlameDownMid(obj) => {obj.anim(flame,frame_time);}
                _ => {}
              }  
           }
       }
     }        
  }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::TICK;
+    use crate::map::MAP_SIZE;
+
+    fn grid_on(text:&str) -> Grid {
+        let map = Map::parse("test", text).unwrap();
+        Grid::from_map(&map, &mut StdRng::seed_from_u64(1))
+    }
+
+    const OPEN:&str = "#########\n#1......#\n#.......#\n#.......#\n#########\n";
+
+    fn place_bomb(grid:&mut Grid,(i,j):Position,power:usize,fuse:f32){
+        let mut bomb = Bomb::new();
+        bomb.set_position(i, j);
+        bomb.power = power;
+        bomb.exploading_time = fuse;
+        grid.cells[i][j].bomb = true;
+        grid.game_objs[i][j] = GameObjs::Bomb(bomb);
+    }
+
+    #[test]
+    fn chained_bombs_take_the_earliest_fuse(){
+        let mut grid = grid_on(OPEN);
+        place_bomb(&mut grid, (2,2), 2, 1_f32);
+        place_bomb(&mut grid, (4,2), 2, 5_f32);
+        let danger = grid.danger_map();
+        assert_eq!(danger[3][2], 1_f32);
+        assert_eq!(danger[4][2], 1_f32, "set off by the first bomb");
+        assert_eq!(danger[6][2], 1_f32, "and its flames come with it");
+        assert_eq!(danger[4][3], 1_f32);
+        assert_eq!(danger[7][2], f32::INFINITY, "out of reach of both");
+        assert_eq!(danger[1][1], f32::INFINITY);
+    }
+
+    #[test]
+    fn a_shorter_fuse_sets_off_the_longer_one(){
+        let mut grid = grid_on(OPEN);
+        place_bomb(&mut grid, (2,2), 2, 3_f32);
+        place_bomb(&mut grid, (4,2), 2, 1_f32);
+        let danger = grid.danger_map();
+        assert_eq!(danger[2][2], 1_f32);
+        assert_eq!(danger[1][2], 1_f32, "the first bomb's flames come when it is set off, not at its own fuse");
+    }
+
+    #[test]
+    fn a_later_bomb_keeps_its_own_fuse_out_of_reach(){
+        let mut grid = grid_on(OPEN);
+        place_bomb(&mut grid, (1,2), 1, 1_f32);
+        place_bomb(&mut grid, (5,2), 1, 3_f32);
+        let danger = grid.danger_map();
+        assert_eq!(danger[2][2], 1_f32);
+        assert_eq!(danger[5][2], 3_f32);
+        assert_eq!(danger[6][2], 3_f32);
+    }
+
+    #[test]
+    fn walls_and_blocks_stop_the_blast(){
+        let mut grid = grid_on("#########\n#1......#\n#.+.#...#\n#.......#\n#########\n");
+        place_bomb(&mut grid, (3,2), 3, 2_f32);
+        place_bomb(&mut grid, (6,2), 3, 4_f32);
+        let danger = grid.danger_map();
+        assert_eq!(danger[2][2], f32::INFINITY, "the breakable wall takes the blast");
+        assert_eq!(danger[1][2], f32::INFINITY);
+        assert_eq!(danger[4][2], f32::INFINITY, "the block takes the blast");
+        assert_eq!(danger[5][2], 4_f32, "so the second bomb isn't set off");
+        assert_eq!(danger[3][1], 2_f32);
+        assert_eq!(danger[3][3], 2_f32);
+    }
+
+    #[test]
+    fn burning_cells_are_dangerous_now_and_stop_the_next_blast(){
+        let mut grid = grid_on(OPEN);
+        grid.cells[5][2].flame = Flame::MidLeft;
+        grid.game_objs[5][2] = GameObjs::FlameLeftMid(FlameLeftMid::new());
+        place_bomb(&mut grid, (2,2), 5, 1_f32);
+        let danger = grid.danger_map();
+        assert_eq!(danger[5][2], 0_f32);
+        assert_eq!(danger[4][2], 1_f32);
+        assert_eq!(danger[6][2], f32::INFINITY, "the old flame holds the new one back like inject_flames does");
+    }
+
+    // The prediction puts flame on exactly the cells the real blast does, with pickups lying in its way.
+    #[test]
+    fn items_in_the_blast_line_burn_as_predicted(){
+        let mut grid = grid_on(OPEN);
+        grid.cells[3][2].item = Item::Bonus(BonusType::GoldCoin);
+        grid.cells[2][3].item = Item::Upgrade(UpgradeType::BlackBomb, 1);
+        place_bomb(&mut grid, (2,2), 3, TICK);
+        let danger = grid.danger_map();
+        for _ in 0..10 {
+            grid.update(TICK);
+        }
+        for (i,rows) in danger.iter().enumerate() {
+            for (j,time) in rows.iter().enumerate() {
+                assert_eq!(time.is_finite(), grid.cells[i][j].is_flame(), "cell {:?}", (i,j));
+            }
+        }
+        assert!(danger[5][2].is_finite(), "the blast runs on past the coin");
+    }
+
+    // A 64x40 map under a view the size of a battle map, as the game draws it.
+    fn big_view(corner:(f32,f32)) -> Visible {
+        let cells = Map::blank(64, 40).cells;
+        let view = Vector2::new(MAP_SIZE.0 as f32 * TILE_SIZE, MAP_SIZE.1 as f32 * TILE_SIZE);
+        Visible::new(Vector2::new(corner.0 * TILE_SIZE, corner.1 * TILE_SIZE), view, &cells)
+    }
+
+    #[test]
+    fn only_tiles_on_screen_are_visible(){
+        let visible = big_view((10.5, 5.25));
+        assert_eq!(visible.cols, 10..43, "a half tile at each side");
+        assert_eq!(visible.rows, 5..24);
+        assert!(visible.contains((10,5)));
+        assert!(!visible.contains((9,5)));
+        assert!(!visible.contains((43,23)));
+        let aligned = big_view((20_f32, 12_f32));
+        assert_eq!(aligned.cols.len(), MAP_SIZE.0);
+        assert_eq!(aligned.rows.len(), MAP_SIZE.1);
+    }
+
+    #[test]
+    fn visible_tiles_stay_on_the_map_at_the_corners(){
+        let top_left = big_view((0_f32, 0_f32));
+        assert_eq!((top_left.cols, top_left.rows), (0..32, 0..18));
+        let bottom_right = big_view((32_f32, 22_f32));
+        assert_eq!((bottom_right.cols, bottom_right.rows), (32..64, 22..40));
+        let before = big_view((-3_f32, -2.5));
+        assert_eq!((before.cols, before.rows), (0..29, 0..16));
+        let past = big_view((60.5, 38.5));
+        assert_eq!((past.cols, past.rows), (60..64, 38..40));
+    }
+}
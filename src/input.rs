@@ -0,0 +1,207 @@
+use raylib::prelude::*;
+use raylib::consts::GamepadButton::*;
+use raylib::consts::GamepadAxis::*;
+use crate::keymap::*;
+use crate::sim::Input;
+
+pub const MAX_GAMEPADS:usize = 4;
+const DEADZONE:f32 = 0.35;
+
+// Anything actions can be read from, the keyboard, a gamepad or a scripted source in tests.
+pub trait InputSource {
+    fn is_down(&self,action:Action) -> bool;
+    fn is_pressed(&self,action:Action) -> bool;
+}
+
+// One player's share of the keyboard.
+pub struct Keyboard<'a> {
+    pub rl:&'a RaylibHandle,
+    pub keymap:&'a KeyMap,
+    pub player:usize,
+}
+
+impl<'a> InputSource for Keyboard<'a> {
+    fn is_down(&self,action:Action) -> bool {
+        self.keymap.is_down(self.rl, self.player, action)
+    }
+
+    fn is_pressed(&self,action:Action) -> bool {
+        self.keymap.is_pressed(self.rl, self.player, action)
+    }
+}
+
+pub struct Gamepad<'a> {
+    pub rl:&'a RaylibHandle,
+    pub id:i32,
+}
+
+fn gamepad_button(action:Action) -> Option<GamepadButton> {
+    match action {
+        Action::MoveUp => Some(GAMEPAD_BUTTON_LEFT_FACE_UP),
+        Action::MoveDown => Some(GAMEPAD_BUTTON_LEFT_FACE_DOWN),
+        Action::MoveLeft => Some(GAMEPAD_BUTTON_LEFT_FACE_LEFT),
+        Action::MoveRight => Some(GAMEPAD_BUTTON_LEFT_FACE_RIGHT),
+        Action::PlantBomb => Some(GAMEPAD_BUTTON_RIGHT_FACE_DOWN),
+        Action::CycleWeapon => Some(GAMEPAD_BUTTON_RIGHT_FACE_LEFT),
+        Action::Pause => Some(GAMEPAD_BUTTON_MIDDLE_RIGHT), // Start
+        Action::Restart => Some(GAMEPAD_BUTTON_MIDDLE_LEFT), // Select
+        Action::WatchReplay => Some(GAMEPAD_BUTTON_RIGHT_FACE_UP),
+        Action::Save => Some(GAMEPAD_BUTTON_RIGHT_FACE_UP),
+        Action::Continue => Some(GAMEPAD_BUTTON_RIGHT_FACE_DOWN),
+        Action::Menu => Some(GAMEPAD_BUTTON_RIGHT_FACE_RIGHT),
+        _ => None,
+    }
+}
+
+impl<'a> Gamepad<'a> {
+    // Left stick as a direction, only the stronger axis counts so diagonals don't fight.
+    fn stick(&self,action:Action) -> bool {
+        let x = self.rl.get_gamepad_axis_movement(self.id, GAMEPAD_AXIS_LEFT_X);
+        let y = self.rl.get_gamepad_axis_movement(self.id, GAMEPAD_AXIS_LEFT_Y);
+        let vertical = y.abs() >= x.abs();
+        match action {
+            Action::MoveUp => vertical && y < -DEADZONE,
+            Action::MoveDown => vertical && y > DEADZONE,
+            Action::MoveLeft => !vertical && x < -DEADZONE,
+            Action::MoveRight => !vertical && x > DEADZONE,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> InputSource for Gamepad<'a> {
+    fn is_down(&self,action:Action) -> bool {
+        let button = gamepad_button(action).is_some_and(|button| self.rl.is_gamepad_button_down(self.id, button));
+        button || self.stick(action)
+    }
+
+    fn is_pressed(&self,action:Action) -> bool {
+        gamepad_button(action).is_some_and(|button| self.rl.is_gamepad_button_pressed(self.id, button))
+    }
+}
+
+// Every action's state for one frame, merged over all sources.
+#[derive(PartialEq,Clone,Debug,Copy,Default)]
+pub struct ActionState {
+    down:[bool;ACTIONS.len()],
+    pressed:[bool;ACTIONS.len()],
+}
+
+impl ActionState {
+    pub fn poll(sources:&[&dyn InputSource]) -> Self {
+        let mut state = ActionState::default();
+        for (n,&action) in ACTIONS.iter().enumerate() {
+            state.down[n] = sources.iter().any(|source| source.is_down(action));
+            state.pressed[n] = sources.iter().any(|source| source.is_pressed(action));
+        }
+        return state;
+    }
+
+    pub fn down(&self,action:Action) -> bool {
+        self.down[action as usize]
+    }
+
+    pub fn pressed(&self,action:Action) -> bool {
+        self.pressed[action as usize]
+    }
+
+    pub fn to_input(self) -> Input {
+        let up = self.down(Action::MoveUp);
+        let down = self.down(Action::MoveDown);
+        let left = self.down(Action::MoveLeft);
+        let right = self.down(Action::MoveRight);
+        let bomb = self.pressed(Action::PlantBomb);
+        let cycle = self.pressed(Action::CycleWeapon);
+        let pause = self.pressed(Action::Pause);
+        Input { up, down, left, right, bomb, cycle, pause }
+    }
+}
+
+// Tracks which gamepad slots are plugged in so pads can come and go mid game.
+pub struct Gamepads {
+    connected:[bool;MAX_GAMEPADS],
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        let connected = [false;MAX_GAMEPADS];
+        Self { connected }
+    }
+
+    // Refresh the slots and describe whatever changed since the last frame.
+    pub fn update(&mut self,rl:&RaylibHandle) -> Option<String> {
+        let mut change = None;
+        for id in 0..MAX_GAMEPADS {
+            let available = rl.is_gamepad_available(id as i32);
+            if available != self.connected[id] {
+                let what = if available {"CONNECTED"} else {"DISCONNECTED"};
+                change = Some(format!("GAMEPAD {} {}",id+1,what));
+            }
+            self.connected[id] = available;
+        }
+        return change;
+    }
+
+    pub fn ids(&self) -> Vec<i32> {
+        (0..MAX_GAMEPADS).filter(|&id| self.connected[id]).map(|id| id as i32).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays back which actions are held each frame, pressed on the frame they go down like raylib's keys.
+    #[derive(Default)]
+    struct Scripted {
+        held:Vec<Action>,
+        before:Vec<Action>,
+    }
+
+    impl Scripted {
+        fn frame(&mut self,held:&[Action]){
+            self.before = std::mem::replace(&mut self.held, held.to_vec());
+        }
+    }
+
+    impl InputSource for Scripted {
+        fn is_down(&self,action:Action) -> bool {
+            self.held.contains(&action)
+        }
+
+        fn is_pressed(&self,action:Action) -> bool {
+            self.held.contains(&action) && !self.before.contains(&action)
+        }
+    }
+
+    #[test]
+    fn press_hold_and_release(){
+        let mut keys = Scripted::default();
+        let mut frames = vec![];
+        for held in [&[Action::PlantBomb][..],&[Action::PlantBomb],&[]] {
+            keys.frame(held);
+            let state = ActionState::poll(&[&keys]);
+            frames.push((state.down(Action::PlantBomb),state.pressed(Action::PlantBomb),state.to_input().bomb));
+        }
+        assert_eq!(frames, [(true,true,true),(true,false,false),(false,false,false)]);
+    }
+
+    #[test]
+    fn moving_follows_the_held_keys(){
+        let mut keys = Scripted::default();
+        keys.frame(&[Action::MoveLeft]);
+        keys.frame(&[Action::MoveLeft]);
+        let input = ActionState::poll(&[&keys]).to_input();
+        assert_eq!(input, Input { left: true, ..Input::default() });
+    }
+
+    #[test]
+    fn keyboard_and_gamepad_merge(){
+        let (mut keys,mut pad) = (Scripted::default(),Scripted::default());
+        keys.frame(&[Action::MoveUp]);
+        pad.frame(&[Action::PlantBomb,Action::Pause]);
+        let state = ActionState::poll(&[&keys,&pad]);
+        assert_eq!(state.to_input(), Input { up: true, bomb: true, pause: true, ..Input::default() });
+        assert!(state.pressed(Action::Pause) && !state.pressed(Action::Menu));
+    }
+}
@@ -0,0 +1,224 @@
+use raylib::prelude::*;
+use raylib::core::input::key_from_i32;
+use raylib::consts::KeyboardKey::*;
+use crate::config::*;
+use crate::sim::MAX_PLAYERS;
+
+pub const KEYMAP_FILE:&str = "keys.cfg";
+const MAX_KEY_CODE:i32 = 350;
+
+#[derive(PartialEq,Clone,Debug,Copy)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PlantBomb,
+    CycleWeapon,
+    Pause,
+    Restart,
+    Menu,
+    WatchReplay,
+    Save,
+    Continue,
+    Rebind,
+    DangerOverlay,
+    LevelSelect,
+    Editor,
+    PlayTest,
+}
+
+pub const ACTIONS:[Action;17] = [
+    Action::MoveUp,Action::MoveDown,Action::MoveLeft,Action::MoveRight,
+    Action::PlantBomb,Action::CycleWeapon,Action::Pause,Action::Restart,Action::Menu,
+    Action::WatchReplay,Action::Save,Action::Continue,Action::Rebind,
+    Action::DangerOverlay,Action::LevelSelect,Action::Editor,Action::PlayTest,
+];
+
+pub const PLAYER_ACTIONS:usize = 6; // Movement, bombs and weapon, the first actions, are bound for every player.
+
+// Keyboard defaults for players two to four, in ACTIONS order.
+const EXTRA_KEYS:[[KeyboardKey;PLAYER_ACTIONS];MAX_PLAYERS-1] = [
+    [KEY_I,KEY_K,KEY_J,KEY_L,KEY_U,KEY_O],
+    [KEY_KP_8,KEY_KP_5,KEY_KP_4,KEY_KP_6,KEY_KP_0,KEY_KP_DECIMAL],
+    [KEY_T,KEY_G,KEY_F,KEY_H,KEY_Y,KEY_E],
+];
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::PlantBomb => "plant_bomb",
+            Action::CycleWeapon => "cycle_weapon",
+            Action::Pause => "pause",
+            Action::Restart => "restart",
+            Action::Menu => "menu",
+            Action::WatchReplay => "watch_replay",
+            Action::Save => "save",
+            Action::Continue => "continue",
+            Action::Rebind => "rebind",
+            Action::DangerOverlay => "danger_overlay",
+            Action::LevelSelect => "level_select",
+            Action::Editor => "editor",
+            Action::PlayTest => "play_test",
+        }
+    }
+
+    pub fn default_key(&self) -> KeyboardKey {
+        match self {
+            Action::MoveUp => KEY_UP,
+            Action::MoveDown => KEY_DOWN,
+            Action::MoveLeft => KEY_LEFT,
+            Action::MoveRight => KEY_RIGHT,
+            Action::PlantBomb => KEY_B,
+            Action::CycleWeapon => KEY_V,
+            Action::Pause => KEY_P,
+            Action::Restart => KEY_R,
+            Action::Menu => KEY_ESCAPE,
+            Action::WatchReplay => KEY_W,
+            Action::Save => KEY_S,
+            Action::Continue => KEY_C,
+            Action::Rebind => KEY_F1,
+            Action::DangerOverlay => KEY_F2,
+            Action::LevelSelect => KEY_F3,
+            Action::Editor => KEY_F4,
+            Action::PlayTest => KEY_F5,
+        }
+    }
+}
+
+// Actions a player has bindings for, player one also owns the menu keys.
+pub fn player_actions(player:usize) -> &'static [Action] {
+    if player == 0 {&ACTIONS} else {&ACTIONS[..PLAYER_ACTIONS]}
+}
+
+// Config name, `move_up` for player one and `p2_move_up` for the others.
+fn config_name(player:usize,action:Action) -> String {
+    if player == 0 {action.name().to_string()} else {format!("p{}_{}",player+1,action.name())}
+}
+
+fn from_config_name(name:&str) -> Option<(usize,Action)> {
+    (0..MAX_PLAYERS).flat_map(|player| player_actions(player).iter().map(move |&action| (player,action)))
+        .find(|&(player,action)| config_name(player, action) == name)
+}
+
+// Raylib's own constant name, e.g. "KEY_UP", used in the config file.
+pub fn key_code_name(key:KeyboardKey) -> String {
+    format!("{:?}",key)
+}
+
+// Short name for on screen hints, e.g. "UP".
+pub fn key_name(key:KeyboardKey) -> String {
+    key_code_name(key).trim_start_matches("KEY_").replace('_'," ")
+}
+
+fn key_from_name(name:&str) -> Option<KeyboardKey> {
+    (1..MAX_KEY_CODE).filter_map(key_from_i32).find(|&key| key_code_name(key) == name)
+}
+
+pub struct KeyMap {
+    keys:Vec<Vec<KeyboardKey>>, // Per player, indexed by action in ACTIONS order.
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        let mut keys:Vec<Vec<KeyboardKey>> = vec![ACTIONS.iter().map(|action| action.default_key()).collect()];
+        keys.extend(EXTRA_KEYS.iter().map(|extra| extra.to_vec()));
+        Self { keys }
+    }
+
+    pub fn load() -> Self {
+        match read_config(KEYMAP_FILE) {
+            Ok(pairs) => KeyMap::from_pairs(&pairs),
+            Err(_) => KeyMap::new(),
+        }
+    }
+
+    // Defaults overridden by whatever the config lines bind, bad lines are reported and skipped.
+    fn from_pairs(pairs:&[(usize,String,String)]) -> Self {
+        let mut keymap = KeyMap::new();
+        for (line,name,val) in pairs {
+            match (from_config_name(name),key_from_name(val)) {
+                (Some((player,action)),Some(key)) => {keymap.keys[player][action as usize] = key}
+                (None,_) => eprintln!("{}:{}: unknown action `{}`",KEYMAP_FILE,line,name),
+                (_,None) => eprintln!("{}:{}: unknown key `{}`",KEYMAP_FILE,line,val),
+            }
+        }
+        keymap
+    }
+
+    fn to_pairs(&self) -> Vec<(String,String)> {
+        let mut pairs = vec![];
+        for player in 0..MAX_PLAYERS {
+            for &action in player_actions(player) {
+                pairs.push((config_name(player, action),key_code_name(self.keys[player][action as usize])));
+            }
+        }
+        pairs
+    }
+
+    pub fn save(&self){
+        if let Err(err) = write_config(KEYMAP_FILE, &self.to_pairs()) {
+            eprintln!("failed to save key map: {}",err);
+        }
+    }
+
+    // Player one's key, the one on screen hints.
+    pub fn key(&self,action:Action) -> KeyboardKey {
+        self.keys[0][action as usize]
+    }
+
+    pub fn player_key(&self,player:usize,action:Action) -> Option<KeyboardKey> {
+        self.keys.get(player)?.get(action as usize).copied()
+    }
+
+    // Bind a key, the action that held it before takes over the old key so nothing is left unbound.
+    pub fn bind(&mut self,player:usize,action:Action,key:KeyboardKey){
+        let old = self.keys[player][action as usize];
+        for bound in self.keys.iter_mut().flatten() {
+            if *bound == key {
+                *bound = old;
+            }
+        }
+        self.keys[player][action as usize] = key;
+    }
+
+    pub fn is_down(&self,rl:&RaylibHandle,player:usize,action:Action) -> bool {
+        self.player_key(player, action).is_some_and(|key| rl.is_key_down(key))
+    }
+
+    pub fn is_pressed(&self,rl:&RaylibHandle,player:usize,action:Action) -> bool {
+        self.player_key(player, action).is_some_and(|key| rl.is_key_pressed(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Config lines the way `read_config` hands them back.
+    fn lines(pairs:&[(String,String)]) -> Vec<(usize,String,String)> {
+        pairs.iter().enumerate().map(|(n,(name,val))| (n+1,name.clone(),val.clone())).collect()
+    }
+
+    #[test]
+    fn saved_bindings_read_back(){
+        let mut keymap = KeyMap::new();
+        keymap.bind(0, Action::PlantBomb, KEY_SPACE);
+        keymap.bind(2, Action::MoveLeft, KEY_Z);
+        let loaded = KeyMap::from_pairs(&lines(&keymap.to_pairs()));
+        assert_eq!(loaded.keys, keymap.keys);
+        assert_eq!(loaded.key(Action::PlantBomb), KEY_SPACE);
+        assert_eq!(loaded.player_key(2, Action::MoveLeft), Some(KEY_Z));
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_keep_the_defaults(){
+        let pairs = [("jump".to_string(),"KEY_A".to_string()),("move_up".to_string(),"KEY_NOPE".to_string()),("p2_menu".to_string(),"KEY_M".to_string())];
+        let loaded = KeyMap::from_pairs(&lines(&pairs));
+        assert_eq!(loaded.keys, KeyMap::new().keys);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io;
+use rand::rngs::StdRng;
+use crate::cell::*;
+use crate::map::{Map,MAP_SIZE,LARGE_MAP};
+use crate::config::*;
+use crate::assets;
+use crate::enemy::EnemyKind::{self,*};
+use crate::bonus::BonusType;
+use crate::upgrade::UpgradeType;
+
+pub const PROGRESS_FILE:&str = "progress.cfg";
+
+// Where a level's map comes from.
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum MapSource {
+    Random, // Noise from the run's seed, so `--seed` changes it.
+    Seed(u64), // Noise from a fixed seed, the same map in every run.
+    File(&'static str), // Drawn by hand, by its name among the assets, see `src/map.rs` for the glyphs.
+}
+
+impl MapSource {
+    // Seed for the map, its exit and its enemies, a fixed seed wins over the run's.
+    pub fn seed(&self,seed:u64) -> u64 {
+        match self {
+            MapSource::Seed(fixed) => *fixed,
+            _ => seed,
+        }
+    }
+
+    // Noise maps come from the rng at `size`, hand-made ones get their hidden items from it.
+    pub fn build(&self,size:(usize,usize),rng:&mut StdRng,drops:&Drops) -> io::Result<Map> {
+        match self {
+            MapSource::File(name) => {
+                let mut map = Map::parse(name, &assets::read_text(name)?)?;
+                map.fill_walls(rng, drops);
+                Ok(map)
+            }
+            _ => Ok(Map::noise(size, rng, drops)),
+        }
+    }
+}
+
+// One in `chance` breakable walls hides an item, picked evenly from `items`. List an item twice to make it twice as common.
+pub struct Drops {
+    pub chance:u32,
+    pub items:&'static [Item],
+}
+
+pub struct Level {
+    pub name:&'static str,
+    pub map:MapSource,
+    pub size:(usize,usize), // Tiles across and down of a noise map, map files bring their own.
+    pub roster:&'static [EnemyKind],
+    pub time_limit:f32, // Seconds, running out costs a life and restarts the clock.
+    pub drops:Drops,
+}
+
+const ALL_ITEMS:[Item;9] = [
+    Item::Bonus(BonusType::Heart),
+    Item::Bonus(BonusType::Cash),
+    Item::Bonus(BonusType::SilverCoin),
+    Item::Bonus(BonusType::GoldCoin),
+    Item::Bonus(BonusType::Diamond),
+    Item::Upgrade(UpgradeType::BlackBomb,2),
+    Item::Upgrade(UpgradeType::BlueBomb,2),
+    Item::Upgrade(UpgradeType::PurpleBomb,3),
+    Item::Upgrade(UpgradeType::RedBomb,3),
+];
+
+const COINS:[Item;5] = [
+    Item::Bonus(BonusType::Cash),
+    Item::Bonus(BonusType::Cash),
+    Item::Bonus(BonusType::SilverCoin),
+    Item::Bonus(BonusType::SilverCoin),
+    Item::Bonus(BonusType::GoldCoin),
+];
+
+const ARSENAL:[Item;6] = [
+    Item::Bonus(BonusType::Heart),
+    Item::Upgrade(UpgradeType::BlackBomb,2),
+    Item::Upgrade(UpgradeType::BlueBomb,2),
+    Item::Upgrade(UpgradeType::BlueBomb,2),
+    Item::Upgrade(UpgradeType::PurpleBomb,3),
+    Item::Upgrade(UpgradeType::RedBomb,3),
+];
+
+const TREASURE:[Item;4] = [
+    Item::Bonus(BonusType::SilverCoin),
+    Item::Bonus(BonusType::GoldCoin),
+    Item::Bonus(BonusType::GoldCoin),
+    Item::Bonus(BonusType::Diamond),
+];
+
+// Battles and anything without a level of its own.
+pub const DEFAULT_DROPS:Drops = Drops { chance: 5, items: &ALL_ITEMS };
+
+// Played in order, clearing the last one starts over from the first with the score kept.
+pub const CAMPAIGN:[Level;6] = [
+    Level { name: "MEADOW", map: MapSource::Random, size: MAP_SIZE, roster: &[Walker,Walker,Walker], time_limit: 240_f32, drops: Drops { chance: 4, items: &COINS } },
+    Level { name: "CROSSROADS", map: MapSource::File("maps/crossroads.map"), size: MAP_SIZE, roster: &[Walker,Walker,Chaser,Splitter], time_limit: 240_f32, drops: Drops { chance: 5, items: &ARSENAL } },
+    Level { name: "HAUNTED WOODS", map: MapSource::Seed(120727), size: MAP_SIZE, roster: &[Walker,Ghost,Ghost,Splitter], time_limit: 210_f32, drops: DEFAULT_DROPS },
+    Level { name: "FORTRESS", map: MapSource::File("maps/fortress.map"), size: MAP_SIZE, roster: &[Chaser,Chaser,BombEater,Ghost,Walker], time_limit: 210_f32, drops: Drops { chance: 4, items: &ARSENAL } },
+    Level { name: "TREASURY", map: MapSource::Random, size: LARGE_MAP, roster: &[BombEater,BombEater,BombEater,Splitter,Splitter,Splitter,Chaser,Chaser], time_limit: 300_f32, drops: Drops { chance: 3, items: &TREASURE } },
+    Level { name: "LAST STAND", map: MapSource::Random, size: LARGE_MAP, roster: &[Walker,Walker,Walker,Chaser,Chaser,Ghost,Ghost,BombEater,BombEater,Splitter], time_limit: 300_f32, drops: Drops { chance: 6, items: &ARSENAL } },
+];
+
+// Levels count from one and loop round the campaign, a stray 0 reads as the first.
+pub fn campaign_level(level:usize) -> &'static Level {
+    &CAMPAIGN[level.saturating_sub(1) % CAMPAIGN.len()]
+}
+
+// How many campaign levels the level select offers, the first is always open.
+pub fn load_unlocked() -> usize {
+    unlocked_from_pairs(&read_config(PROGRESS_FILE).unwrap_or_default())
+}
+
+pub fn save_unlocked(unlocked:usize){
+    if let Err(err) = write_config(PROGRESS_FILE, &unlocked_to_pairs(unlocked)) {
+        eprintln!("failed to save progress: {}",err);
+    }
+}
+
+fn unlocked_from_pairs(pairs:&[(usize,String,String)]) -> usize {
+    let unlocked = pairs.iter().find(|(_,key,_)| key == "unlocked").and_then(|(_,_,val)| val.parse().ok());
+    unlocked.unwrap_or(1).clamp(1, CAMPAIGN.len())
+}
+
+fn unlocked_to_pairs(unlocked:usize) -> Vec<(String,String)> {
+    vec![("unlocked".to_string(),unlocked.clamp(1, CAMPAIGN.len()).to_string())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What `load_unlocked` reads back after `save_unlocked`.
+    fn saved(unlocked:usize) -> usize {
+        let pairs:Vec<(usize,String,String)> = unlocked_to_pairs(unlocked).into_iter().map(|(key,val)| (1,key,val)).collect();
+        unlocked_from_pairs(&pairs)
+    }
+
+    fn read(val:&str) -> usize {
+        unlocked_from_pairs(&[(1,"unlocked".to_string(),val.to_string())])
+    }
+
+    #[test]
+    fn unlocked_levels_read_back_within_the_campaign(){
+        assert_eq!(saved(3), 3);
+        assert_eq!(saved(0), 1, "the first level is always open");
+        assert_eq!(saved(99), CAMPAIGN.len());
+        assert_eq!(read("99"), CAMPAIGN.len());
+        assert_eq!(read("0"), 1);
+        assert_eq!(read("lots"), 1);
+        assert_eq!(unlocked_from_pairs(&[]), 1);
+    }
+
+    #[test]
+    fn the_campaign_loops(){
+        let len = CAMPAIGN.len();
+        assert_eq!(campaign_level(1).name, CAMPAIGN[0].name);
+        assert_eq!(campaign_level(len).name, CAMPAIGN[len-1].name);
+        assert_eq!(campaign_level(len + 1).name, CAMPAIGN[0].name);
+        assert_eq!(campaign_level(2*len + 2).name, CAMPAIGN[1].name);
+        assert_eq!(campaign_level(0).name, CAMPAIGN[0].name);
+    }
+}
@@ -6,52 +6,244 @@ mod noise;
 mod player;
 mod objects;
 mod upgrade;
+mod sim;
+mod replay;
+mod save;
+mod cell;
+mod config;
+mod keymap;
+mod input;
+mod net;
+mod fnv;
+mod enemy;
+mod path;
+mod bot;
+mod level;
+mod map;
+mod editor;
+mod menu;
+mod settings;
+mod atlas;
+mod assets;
 
 use raylib::prelude::*;
-use raylib::core::audio::Sound;
-use grid::*;
 use game::*;
+use replay::*;
+use net::*;
+use bot::*;
+use map::Map;
+use atlas::*;
 
-const SPRITE_SHEET:&str = "assets/spritesheet.png";
+// Asset names inside the assets directory, see assets.rs for where that is.
+const SPRITE_SHEET:&str = "spritesheet.png";
+const SPRITE_ATLAS:&str = "spritesheet.json"; // Names the clips on the sheet, see atlas.rs.
 
-const EXP_SOUND:&str = "assets/sounds/exp.ogg";
-const BONUS_SOUND:&str = "assets/sounds/bonus.ogg";
-const UPGRADE_SOUND:&str = "assets/sounds/upgrade.ogg";
-const GAMEOVER_SOUND:&str = "assets/sounds/gameover.ogg";
-const WIN_SOUND:&str = "assets/sounds/win.ogg";
-const BURNING_SOUND:&str = "assets/sounds/burning.ogg";
-const PUNCH_SOUND:&str = "assets/sounds/punch.ogg";
+const EXP_SOUND:&str = "sounds/exp.ogg";
+const BONUS_SOUND:&str = "sounds/bonus.ogg";
+const UPGRADE_SOUND:&str = "sounds/upgrade.ogg";
+const GAMEOVER_SOUND:&str = "sounds/gameover.ogg";
+const WIN_SOUND:&str = "sounds/win.ogg";
+const BURNING_SOUND:&str = "sounds/burning.ogg";
+const PUNCH_SOUND:&str = "sounds/punch.ogg";
+
+// Value following `flag` on the command line.
+fn get_arg(flag:&str) -> Option<String> {
+    let args:Vec<String> = std::env::args().collect();
+    for i in 0..args.len() {
+        if args[i] == flag && i + 1 < args.len() {
+            return Some(args[i+1].clone());
+        }
+    }
+    return None;
+}
+
+// Map seed from `--seed <n>`, or a fresh random one.
+fn parse_seed() -> u64 {
+    if let Some(seed) = get_arg("--seed").and_then(|seed| seed.parse::<u64>().ok()) {
+        return seed;
+    }
+    return rand::random::<u32>() as u64; // Keep fresh seeds short enough to read off the HUD.
+}
+
+// Player count from `--players <n>`, one unless a battle is asked for. `--bots` alone
+// means one human against that many bots.
+fn parse_players() -> usize {
+    let bots = parse_bots();
+    get_arg("--players").and_then(|players| players.parse::<usize>().ok()).unwrap_or(if bots > 0 {bots + 1} else {1})
+}
+
+// How many of the last player slots `--bots <n>` hands to the computer.
+fn parse_bots() -> usize {
+    get_arg("--bots").and_then(|bots| bots.parse::<usize>().ok()).unwrap_or(0)
+}
+
+// One difficulty per bot from `--bot-level easy,hard`, the last one repeats for the rest.
+fn parse_bot_levels(bots:usize) -> Vec<Difficulty> {
+    let names = get_arg("--bot-level").unwrap_or(Difficulty::Normal.name().to_string());
+    let mut levels:Vec<Difficulty> = names.split(',').map(|name| Difficulty::from_name(name.trim()).unwrap_or_else(|| {
+        eprintln!("unknown bot level `{}`, use easy, normal or hard",name);
+        std::process::exit(1)
+    })).collect();
+    while levels.len() < bots {
+        levels.push(*levels.last().unwrap());
+    }
+    levels.truncate(bots);
+    return levels;
+}
+
+// Hand-made map from `--map <file>`, a broken one is reported and nothing starts.
+fn parse_map() -> Option<Map> {
+    let path = get_arg("--map")?;
+    match Map::load(&path) {
+        Ok(map) => Some(map),
+        Err(err) => {eprintln!("failed to load map: {}",err); std::process::exit(1)}
+    }
+}
+
+// `--headless <matches>` plays bots against each other without a window and prints who won.
+fn run_headless(matches:&str,seed:u64,players:usize){
+    let matches = matches.parse::<u64>().unwrap_or_else(|_| {eprintln!("bad match count `{}`",matches); std::process::exit(1)});
+    let levels = parse_bot_levels(players.clamp(2,sim::MAX_PLAYERS));
+    let mut wins = vec![0;levels.len()];
+    let mut draws = 0;
+    for n in 0..matches {
+        match play_match(seed.wrapping_add(n), &levels) {
+            Some(slot) => wins[slot] += 1,
+            None => draws += 1,
+        }
+    }
+    for (slot,level) in levels.iter().enumerate() {
+        println!("player {} ({}): {} wins",slot+1,level.name(),wins[slot]);
+    }
+    println!("draws: {}",draws);
+}
+
+// `--host <port>` or `--join <ip:port>` for an online game, `--latency <ms>` and `--loss <0..1>`
+// fake a bad network for testing.
+fn connect(seed:u64) -> Option<NetSession> {
+    let latency = get_arg("--latency").and_then(|ms| ms.parse::<u64>().ok()).unwrap_or(0);
+    let loss = get_arg("--loss").and_then(|loss| loss.parse::<f32>().ok()).unwrap_or(0_f32);
+    let online = get_arg("--host").is_some() || get_arg("--join").is_some();
+    if online && get_arg("--map").is_some() { // The handshake only carries the seed, the peer would play another map.
+        eprintln!("`--map` can't be used with `--host` or `--join`, online games play the host's generated map");
+        std::process::exit(1)
+    }
+    let net = if let Some(port) = get_arg("--host") {
+        let port = port.parse::<u16>().unwrap_or_else(|_| {eprintln!("bad port `{}`",port); std::process::exit(1)});
+        NetSession::host(port, seed, Shim::new(latency, loss))
+    } else if let Some(addr) = get_arg("--join") {
+        NetSession::join(&addr, Shim::new(latency, loss))
+    } else {
+        return None;
+    };
+    match net {
+        Ok(net) => Some(net),
+        Err(err) => {eprintln!("failed to connect: {}",err); std::process::exit(1)}
+    }
+}
+
+// The native resolution the game is drawn at, a GPU that can't make one is the end of it.
+fn render_target(rl:&mut RaylibHandle,thread:&RaylibThread,(width,height):(i32,i32)) -> RenderTexture2D {
+    rl.load_render_texture(thread, width as u32, height as u32).unwrap_or_else(|err| {
+        eprintln!("failed to create the {}x{} render target: {}",width,height,err);
+        std::process::exit(1)
+    })
+}
 
 fn main() {    
     //LOAD -->
-    let mut game = Game::new();
+    if let Some(dir) = get_arg("--assets") {
+        if let Err(err) = assets::use_dir(dir.into()) {
+            eprintln!("bad assets directory: {}",err);
+            std::process::exit(1)
+        }
+    }
+    let map = parse_map();
+    let replay = get_arg("--replay").and_then(|path| match Replay::load(&path).and_then(|replay| replay.check_map(&path, map.as_ref()).map(|_| replay)) {
+        Ok(replay) => Some(replay),
+        Err(err) => {eprintln!("failed to load replay: {}",err); None}
+    });
+    let players = match &replay {
+        Some(replay) => replay.players,
+        None => parse_players(),
+    };
+    let seed = parse_seed();
+    if let Some(matches) = get_arg("--headless") {
+        run_headless(&matches, seed, players);
+        return;
+    }
+    match Atlas::load(SPRITE_ATLAS) {
+        Ok(atlas) => use_atlas(atlas),
+        Err(err) => {eprintln!("failed to load sprite atlas: {}",err); std::process::exit(1)}
+    }
+    let net = connect(seed);
+    let players = if net.is_some() {NET_PLAYERS} else {players};
+    let mut game = Game::new(seed,players,map);
+    game.record_path = get_arg("--record");
+    game.map_path = get_arg("--map");
+    if let Some(net) = net {
+        game.set_net(net);
+    }else if players > 1 {
+        game.set_bots(&parse_bot_levels(parse_bots().min(players - 1)));
+    }
+    if let Some(replay) = replay {
+        game.restart(Some(Playback::new(replay)));
+    }
+    let (width,height) = game.window_size();
     let (mut rl, thread) = raylib::init()
-        .size(game.screen_w, game.screen_h)
+        .size(width, height)
+        .resizable()
         .title("BOMBER-MAN")
         .build();
+    rl.set_exit_key(None); // Escape opens the menu, Quit on it closes the game.
 
     //ALl textures Assets
-    let sheets = rl.load_texture(&thread, SPRITE_SHEET).unwrap();   
+    let sheets = assets::load_texture(&mut rl, &thread, SPRITE_SHEET).unwrap_or_else(|err| {
+        eprintln!("failed to load sprite sheet: {}",err);
+        std::process::exit(1)
+    });
+    if let Err(err) = atlas().check_sheet(sheets.width, sheets.height) {
+        eprintln!("bad sprite atlas: {}",err);
+        std::process::exit(1)
+    }
+    let mut target = render_target(&mut rl, &thread, game.native_size());
     // All Sounds Assests
     let mut audio = RaylibAudio::init_audio_device();
-    let exp_sound = Sound::load_sound(EXP_SOUND).unwrap();
-    let bonus_sound = Sound::load_sound(BONUS_SOUND).unwrap();
-    let gameover = Sound::load_sound(GAMEOVER_SOUND).unwrap();
-    let upgrade_sound = Sound::load_sound(UPGRADE_SOUND).unwrap();
-    let win_sound = Sound::load_sound(WIN_SOUND).unwrap();
-    let burning_sound = Sound::load_sound(BURNING_SOUND).unwrap();
-    let punch = Sound::load_sound(PUNCH_SOUND).unwrap();
-
-    let sounds:GameSounds = (&exp_sound,&bonus_sound,&gameover,&upgrade_sound,&win_sound,&burning_sound,&punch);
-
-    while !rl.window_should_close() {
-        //rl.toggle_fullscreen();
+    if !audio.is_audio_device_ready() {
+        eprintln!("no audio device, playing without sound");
+    }
+    let exp_sound = assets::load_sound(&audio, EXP_SOUND);
+    let bonus_sound = assets::load_sound(&audio, BONUS_SOUND);
+    let gameover = assets::load_sound(&audio, GAMEOVER_SOUND);
+    let upgrade_sound = assets::load_sound(&audio, UPGRADE_SOUND);
+    let win_sound = assets::load_sound(&audio, WIN_SOUND);
+    let burning_sound = assets::load_sound(&audio, BURNING_SOUND);
+    let punch = assets::load_sound(&audio, PUNCH_SOUND);
+
+    let sounds:GameSounds = (exp_sound.as_ref(),bonus_sound.as_ref(),gameover.as_ref(),upgrade_sound.as_ref(),win_sound.as_ref(),burning_sound.as_ref(),punch.as_ref());
+
+    while !rl.window_should_close() && !game.quit {
         //UPDATE --> 
         let frame_time = rl.get_frame_time();
         game.update(&mut rl,&mut audio,sounds,frame_time);
     
         //Render Sound and Graphics -->
+        let (width,height) = game.native_size();
+        if (target.texture.width,target.texture.height) != (width,height) { // Battles have more HUD rows.
+            target = render_target(&mut rl, &thread, (width,height));
+        }
+        let screen = game.screen_rect(rl.get_screen_width(), rl.get_screen_height());
         let mut d = rl.begin_drawing(&thread);
-        game.draw(&mut d, &sheets ,frame_time);
+        {
+            let mut d = d.begin_texture_mode(&thread, &mut target);
+            game.draw(&mut d, &sheets ,frame_time);
+        }
+        d.clear_background(Color::BLACK); // The bars either side of the game when the window's shape doesn't match.
+        let source = Rectangle::new(0_f32, 0_f32, width as f32, -height as f32); // Render textures come out upside down.
+        d.draw_texture_pro(&target, source, screen, Vector2::default(), 0_f32, Color::WHITE);
     }        
+    game.save_recording();
+    if let Some(net) = &mut game.net {
+        net.leave();
+    }
 }
@@ -0,0 +1,293 @@
+use std::fs;
+use std::io;
+use rand::rngs::StdRng;
+use crate::cell::*;
+use crate::grid::Position;
+use crate::enemy::EnemyKind;
+use crate::bonus::BonusType;
+use crate::upgrade::UpgradeType;
+use crate::level::Drops;
+use crate::noise::*;
+use crate::fnv::Fnv;
+
+pub const MAP_SIZE:(usize,usize) = (32,18); // Tiles across and down of battle maps and most levels.
+pub const LARGE_MAP:(usize,usize) = (64,40);
+const MAX_SPAWNS:usize = 4;
+
+// Map file glyphs, one text line per row of tiles:
+//   #  block            +  breakable wall      .  open ground
+//   1-4  player spawns  X  exit under a breakable wall
+//   h c s g d  heart, cash, silver coin, gold coin and diamond lying on open ground
+//   k b p r    black, blue, purple and red bombs lying on open ground
+//   W C G E S  walker, chaser, ghost, bomb-eater and splitter standing on open ground
+// Breakable walls other than the exit get their hidden items from the level's drop table.
+const ITEM_GLYPHS:[(char,Item);9] = [
+    ('h',Item::Bonus(BonusType::Heart)),
+    ('c',Item::Bonus(BonusType::Cash)),
+    ('s',Item::Bonus(BonusType::SilverCoin)),
+    ('g',Item::Bonus(BonusType::GoldCoin)),
+    ('d',Item::Bonus(BonusType::Diamond)),
+    ('k',Item::Upgrade(UpgradeType::BlackBomb,2)),
+    ('b',Item::Upgrade(UpgradeType::BlueBomb,2)),
+    ('p',Item::Upgrade(UpgradeType::PurpleBomb,3)),
+    ('r',Item::Upgrade(UpgradeType::RedBomb,3)),
+];
+
+const ENEMY_GLYPHS:[(char,EnemyKind);5] = [
+    ('W',EnemyKind::Walker),
+    ('C',EnemyKind::Chaser),
+    ('G',EnemyKind::Ghost),
+    ('E',EnemyKind::BombEater),
+    ('S',EnemyKind::Splitter),
+];
+
+fn glyph_item(glyph:char) -> Option<Item> {
+    ITEM_GLYPHS.iter().find(|(g,_)| *g == glyph).map(|&(_,item)| item)
+}
+
+fn glyph_enemy(glyph:char) -> Option<EnemyKind> {
+    ENEMY_GLYPHS.iter().find(|(g,_)| *g == glyph).map(|&(_,kind)| kind)
+}
+
+// Tiles plus where players start and which enemies stand where, before any objects are made for it.
+#[derive(Clone,PartialEq,Debug)]
+pub struct Map {
+    pub cells:Vec<Vec<Cell>>,
+    pub spawns:Vec<Position>, // Player one first.
+    pub enemies:Vec<(EnemyKind,Position)>,
+}
+
+// Knock out the walls on and next to each spawn so nobody starts boxed in.
+fn clear_spawns(cells:&mut [Vec<Cell>],spawns:&[Position]){
+    for &(i,j) in spawns {
+        let di = if i == 1 {i+1} else {i-1};
+        let dj = if j == 1 {j+1} else {j-1};
+        for (x,y) in [(i,j),(di,j),(i,dj)] {
+            if cells[x][y].terrain == Terrain::Wall {
+                cells[x][y] = Cell::new(Terrain::Empty);
+            }
+        }
+    }
+}
+
+impl Map {
+    // Perlin noise map with a player in every corner, player one top left.
+    pub fn noise((width,height):(usize,usize),rng:&mut StdRng,drops:&Drops) -> Self {
+        let mut cells = noise(width, height, rng, drops);
+        let spawns = vec![(1,1),(width-2,height-2),(width-2,1),(1,height-2)];
+        clear_spawns(&mut cells, &spawns);
+        let enemies = vec![];
+        Self { cells, spawns, enemies }
+    }
+
+    // Blocks round the edge, open ground inside and player one in the top left.
+    pub fn blank(width:usize,height:usize) -> Self {
+        let mut cells = vec![vec![Cell::new(Terrain::Empty); height]; width];
+        for (i,rows) in cells.iter_mut().enumerate() {
+            for (j,cell) in rows.iter_mut().enumerate() {
+                if i == 0 || j == 0 || i == width-1 || j == height-1 {
+                    cell.terrain = Terrain::Block;
+                }
+            }
+        }
+        let spawns = vec![(1,1)];
+        let enemies = vec![];
+        Self { cells, spawns, enemies }
+    }
+
+    pub fn load(path:&str) -> io::Result<Self> {
+        let text = fs::read_to_string(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?;
+        Map::parse(path, &text)
+    }
+
+    // Errors read `name:line:column: what`, counting both from one, or `name: what` for the map as a whole.
+    pub fn parse(name:&str,text:&str) -> io::Result<Self> {
+        let fail = |msg:String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, msg));
+        let invalid = |line:usize,column:usize,msg:String| fail(format!("{}:{}: {}", line, column, msg));
+        let rows:Vec<Vec<char>> = text.trim_end().lines().map(|row| row.trim_end().chars().collect()).collect();
+        let width = rows.first().map_or(0, |row| row.len());
+        if rows.len() < 3 || width < 3 {
+            return Err(fail("a map needs at least 3 rows of 3 tiles".to_string()));
+        }
+        let mut cells = vec![vec![Cell::new(Terrain::Empty); rows.len()]; width];
+        let mut spawns:Vec<Option<Position>> = vec![None;MAX_SPAWNS];
+        let mut enemies = vec![];
+        let mut exit:Option<Position> = None;
+        for (j,row) in rows.iter().enumerate() {
+            if row.len() != width {
+                return Err(invalid(j+1, row.len().min(width)+1, format!("row is {} tiles wide, the first row is {}", row.len(), width)));
+            }
+            for (i,&glyph) in row.iter().enumerate() {
+                let (line,column) = (j+1,i+1);
+                let edge = i == 0 || j == 0 || i == width-1 || j == rows.len()-1;
+                if edge && glyph != '#' {
+                    return Err(invalid(line, column, format!("the border must be blocks `#`, found `{}`", glyph)));
+                }
+                let cell = &mut cells[i][j];
+                match glyph {
+                    '#' => {cell.terrain = Terrain::Block}
+                    '+' => {cell.terrain = Terrain::Wall}
+                    '.' => {}
+                    'X' => {
+                        if let Some((x,y)) = exit {
+                            return Err(invalid(line, column, format!("second exit, the first is at {}:{}", y+1, x+1)));
+                        }
+                        cell.terrain = Terrain::Wall;
+                        cell.item = Item::Exit;
+                        exit = Some((i,j));
+                    }
+                    '1'..='4' => {
+                        let slot = glyph as usize - '1' as usize;
+                        if let Some((x,y)) = spawns[slot] {
+                            return Err(invalid(line, column, format!("second spawn for player {}, the first is at {}:{}", glyph, y+1, x+1)));
+                        }
+                        spawns[slot] = Some((i,j));
+                    }
+                    _ => match (glyph_item(glyph),glyph_enemy(glyph)) {
+                        (Some(item),_) => {cell.item = item}
+                        (_,Some(kind)) => {enemies.push((kind,(i,j)))}
+                        _ => return Err(invalid(line, column, format!("unknown glyph `{}`", glyph))),
+                    },
+                }
+            }
+        }
+        if spawns[0].is_none() {
+            return Err(fail("no spawn for player 1, mark one with `1`".to_string()));
+        }
+        if let Some(slot) = (1..MAX_SPAWNS).find(|&slot| spawns[slot].is_some() && spawns[slot-1].is_none()) {
+            let (x,y) = spawns[slot].unwrap();
+            return Err(invalid(y+1, x+1, format!("spawn for player {} without one for player {}", slot+1, slot)));
+        }
+        let spawns = spawns.into_iter().flatten().collect();
+        Ok(Self { cells, spawns, enemies })
+    }
+
+    pub fn save(&self,path:&str) -> io::Result<()> {
+        fs::write(path, self.to_text()).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+    }
+
+    // Back to the glyphs `parse` reads. Items hidden under plain walls aren't written, the level's drop table hides them.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for j in 0..self.cells[0].len() {
+            for i in 0..self.cells.len() {
+                let spawn = self.spawns.iter().position(|&pos| pos == (i,j));
+                let enemy = self.enemies.iter().find(|&&(_,pos)| pos == (i,j)).map(|&(kind,_)| kind);
+                let cell = &self.cells[i][j];
+                let glyph = match (spawn,enemy,cell.terrain,cell.item) {
+                    (Some(slot),_,_,_) => char::from(b'1' + slot as u8),
+                    (_,Some(kind),_,_) => ENEMY_GLYPHS.iter().find(|(_,k)| *k == kind).map_or('.', |&(g,_)| g),
+                    (_,_,Terrain::Block,_) => '#',
+                    (_,_,Terrain::Wall,Item::Exit) => 'X',
+                    (_,_,Terrain::Wall,_) => '+',
+                    (_,_,Terrain::Empty,item) => ITEM_GLYPHS.iter().find(|(_,it)| *it == item).map_or('.', |&(g,_)| g),
+                };
+                text.push(glyph);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Fingerprint of the glyphs, replays keep it to check they play back on the map they were recorded on.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = Fnv::default();
+        hasher.write(self.to_text().as_bytes());
+        hasher.finish()
+    }
+
+    // Hide the drop table's items under the walls a map file left empty.
+    pub fn fill_walls(&mut self,rng:&mut StdRng,drops:&Drops){
+        for cell in self.cells.iter_mut().flatten() {
+            if cell.terrain == Terrain::Wall && cell.item == Item::Default {
+                cell.item = wall_item(rng, drops);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every glyph the format has.
+    const ALL_GLYPHS:&str = "\
+###########
+#1.+X.hcsg#
+#.#.#.#.#.#
+#dkbpr..WC#
+#GES.....2#
+###########
+";
+
+    fn error(text:&str) -> String {
+        Map::parse("test", text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn text_round_trips(){
+        for text in [ALL_GLYPHS,include_str!("../assets/maps/crossroads.map"),include_str!("../assets/maps/fortress.map")] {
+            let map = Map::parse("test", text).unwrap();
+            assert_eq!(map.to_text(), text);
+            assert_eq!(Map::parse("again", &map.to_text()).unwrap(), map);
+        }
+    }
+
+    #[test]
+    fn parses_every_glyph(){
+        let map = Map::parse("test", ALL_GLYPHS).unwrap();
+        assert_eq!(map.spawns, vec![(1,1),(9,4)]);
+        assert_eq!(map.cells[3][1].terrain, Terrain::Wall);
+        assert_eq!((map.cells[4][1].terrain,map.cells[4][1].item), (Terrain::Wall,Item::Exit));
+        assert_eq!(map.cells[6][1].item, Item::Bonus(BonusType::Heart));
+        assert_eq!(map.cells[4][3].item, Item::Upgrade(UpgradeType::PurpleBomb,3));
+        assert_eq!(map.enemies[0], (EnemyKind::Walker,(8,3)));
+        assert_eq!(map.enemies.len(), 5);
+    }
+
+    #[test]
+    fn blank_map_round_trips(){
+        let map = Map::blank(12, 7);
+        assert_eq!(Map::parse("test", &map.to_text()).unwrap(), map);
+    }
+
+    #[test]
+    fn rejects_tiny_maps(){
+        assert_eq!(error("###\n#1#\n"), "test: a map needs at least 3 rows of 3 tiles");
+    }
+
+    #[test]
+    fn rejects_ragged_rows(){
+        assert_eq!(error("#####\n#1.#\n#####\n"), "test: 2:5: row is 4 tiles wide, the first row is 5");
+    }
+
+    #[test]
+    fn rejects_an_open_border(){
+        assert_eq!(error("#####\n#1...\n#####\n"), "test: 2:5: the border must be blocks `#`, found `.`");
+    }
+
+    #[test]
+    fn rejects_a_second_exit(){
+        assert_eq!(error("######\n#1X.X#\n######\n"), "test: 2:5: second exit, the first is at 2:3");
+    }
+
+    #[test]
+    fn rejects_a_second_spawn(){
+        assert_eq!(error("######\n#1..1#\n######\n"), "test: 2:5: second spawn for player 1, the first is at 2:2");
+    }
+
+    #[test]
+    fn rejects_unknown_glyphs(){
+        assert_eq!(error("######\n#1.?.#\n######\n"), "test: 2:4: unknown glyph `?`");
+    }
+
+    #[test]
+    fn rejects_a_map_without_player_one(){
+        assert_eq!(error("######\n#..2.#\n######\n"), "test: no spawn for player 1, mark one with `1`");
+    }
+
+    #[test]
+    fn rejects_skipped_spawns(){
+        assert_eq!(error("######\n#1..3#\n######\n"), "test: 2:5: spawn for player 3 without one for player 2");
+    }
+}
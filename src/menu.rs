@@ -0,0 +1,132 @@
+use crate::bot::{Difficulty,DIFFICULTIES};
+use crate::sim::MAX_PLAYERS;
+
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum MenuItem {
+    Resume,
+    NewGame,
+    Continue,
+    Levels,
+    Battle,
+    Editor,
+    Options,
+    Credits,
+    Quit,
+}
+
+// Main menu rows top to bottom, Resume only shows over a game in progress.
+pub const MENU_ITEMS:[MenuItem;9] = [
+    MenuItem::Resume,MenuItem::NewGame,MenuItem::Continue,MenuItem::Levels,MenuItem::Battle,
+    MenuItem::Editor,MenuItem::Options,MenuItem::Credits,MenuItem::Quit,
+];
+
+impl MenuItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MenuItem::Resume => "RESUME",
+            MenuItem::NewGame => "NEW GAME",
+            MenuItem::Continue => "CONTINUE",
+            MenuItem::Levels => "LEVELS",
+            MenuItem::Battle => "BATTLE",
+            MenuItem::Editor => "LEVEL EDITOR",
+            MenuItem::Options => "OPTIONS",
+            MenuItem::Credits => "CREDITS",
+            MenuItem::Quit => "QUIT",
+        }
+    }
+}
+
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub enum OptionItem {
+    MasterVolume,
+    SfxVolume,
+    MusicVolume,
+    Fullscreen,
+    WindowSize,
+    PixelPerfect,
+    VSync,
+    FpsCap,
+    DangerOverlay,
+    Lives,
+    Controls,
+    Back,
+}
+
+pub const OPTION_ITEMS:[OptionItem;12] = [
+    OptionItem::MasterVolume,OptionItem::SfxVolume,OptionItem::MusicVolume,OptionItem::Fullscreen,
+    OptionItem::WindowSize,OptionItem::PixelPerfect,OptionItem::VSync,OptionItem::FpsCap,OptionItem::DangerOverlay,
+    OptionItem::Lives,OptionItem::Controls,OptionItem::Back,
+];
+
+impl OptionItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            OptionItem::MasterVolume => "MASTER VOLUME",
+            OptionItem::SfxVolume => "SFX VOLUME",
+            OptionItem::MusicVolume => "MUSIC VOLUME",
+            OptionItem::Fullscreen => "FULLSCREEN",
+            OptionItem::WindowSize => "WINDOW SIZE",
+            OptionItem::PixelPerfect => "INTEGER SCALING",
+            OptionItem::VSync => "VSYNC",
+            OptionItem::FpsCap => "FPS CAP",
+            OptionItem::DangerOverlay => "DANGER OVERLAY",
+            OptionItem::Lives => "STARTING LIVES",
+            OptionItem::Controls => "CONTROLS",
+            OptionItem::Back => "BACK",
+        }
+    }
+}
+
+pub const CREDITS:[&str;6] = [
+    "GAME, CODE AND PIXEL ART",
+    "THE BOMBER-MAN CONTRIBUTORS",
+    "",
+    "BUILT WITH",
+    "RUST, RAYLIB, NOISE-RS AND SERDE",
+    "",
+];
+
+// Battle screen rows: players, bots, bot level and start. Left and right change the values.
+pub const BATTLE_ROWS:usize = 4;
+
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub struct BattleSetup {
+    pub players:usize,
+    pub bots:usize, // The last player slots, player one is always a human.
+    pub level:usize, // Index into DIFFICULTIES.
+}
+
+impl BattleSetup {
+    pub fn new() -> Self {
+        let players = 2;
+        let bots = 1;
+        let level = Difficulty::Normal as usize;
+        Self { players, bots, level }
+    }
+
+    pub fn change(&mut self,row:usize,step:isize){
+        let wrap = |val:usize,min:usize,max:usize| (val as isize - min as isize + step).rem_euclid((max - min + 1) as isize) as usize + min;
+        match row {
+            0 => {
+                self.players = wrap(self.players, 2, MAX_PLAYERS);
+                self.bots = self.bots.min(self.players - 1);
+            }
+            1 => {self.bots = wrap(self.bots, 0, self.players - 1)}
+            2 => {self.level = wrap(self.level, 0, DIFFICULTIES.len() - 1)}
+            _ => {}
+        }
+    }
+
+    pub fn levels(&self) -> Vec<Difficulty> {
+        vec![DIFFICULTIES[self.level];self.bots]
+    }
+
+    pub fn rows(&self) -> [String;BATTLE_ROWS] {
+        [
+            format!("PLAYERS  < {} >",self.players),
+            format!("BOTS  < {} >",self.bots),
+            format!("BOT LEVEL  < {} >",DIFFICULTIES[self.level].name().to_uppercase()),
+            "START".to_string(),
+        ]
+    }
+}
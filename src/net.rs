@@ -0,0 +1,349 @@
+use std::io;
+use std::net::{SocketAddr,UdpSocket};
+use std::time::{Duration,Instant};
+use rand::{Rng,SeedableRng,rngs::StdRng};
+use crate::sim::Input;
+use crate::replay::{encode,decode};
+
+// Lockstep over UDP for two peers, the host is player one and the joiner player two.
+// Both sides only step a tick once they hold both inputs for it. Local input is scheduled
+// INPUT_DELAY ticks ahead so it is usually at the peer before it is needed.
+
+const MAGIC:&[u8;4] = b"BMNP";
+const VERSION:u8 = 2; // Bumped when the protocol or the desync hash changes, peers on different versions refuse each other.
+pub const NET_PLAYERS:usize = 2;
+const INPUT_DELAY:usize = 3;
+const MAX_RESEND:usize = 255; // Unacked inputs sent again in every packet, covers lost packets.
+const STALL_TIME:f32 = 1_f32; // Silence before the peer is shown as waiting.
+const GRACE_TIME:f32 = 10_f32; // Silence before the peer is given up on.
+const HANDSHAKE_TIME:f32 = 30_f32;
+const HELLO_INTERVAL:f32 = 0.25_f32;
+const MAX_PACKET:usize = 1024;
+
+const HELLO:u8 = 0;
+const WELCOME:u8 = 1;
+const INPUTS:u8 = 2;
+const BYE:u8 = 3;
+
+#[derive(PartialEq,Clone,Debug,Copy)]
+pub enum NetStatus {
+    Connected,
+    Waiting,
+    Lost,
+    Left,
+    Desync(usize),
+}
+
+// Delays and drops outgoing datagrams to try the protocol on loopback.
+pub struct Shim {
+    latency:Duration,
+    loss:f32,
+    queue:Vec<(Instant,Vec<u8>,SocketAddr)>,
+    rng:StdRng,
+}
+
+impl Shim {
+    pub fn new(latency_ms:u64,loss:f32) -> Self {
+        let latency = Duration::from_millis(latency_ms);
+        let queue = vec![];
+        let rng = StdRng::from_entropy();
+        Self { latency, loss, queue, rng }
+    }
+
+    pub fn send(&mut self,socket:&UdpSocket,bytes:Vec<u8>,addr:SocketAddr){
+        if self.loss > 0_f32 && self.rng.gen::<f32>() < self.loss {
+            return;
+        }
+        if self.latency.is_zero() {
+            let _ = socket.send_to(&bytes, addr);
+        }else{
+            self.queue.push((Instant::now() + self.latency, bytes, addr));
+        }
+    }
+
+    // Put every datagram whose delay is over on the wire.
+    pub fn flush(&mut self,socket:&UdpSocket){
+        let now = Instant::now();
+        self.queue.retain(|(due,bytes,addr)| {
+            if *due > now {
+                return true;
+            }
+            let _ = socket.send_to(bytes, *addr);
+            false
+        });
+    }
+}
+
+fn header(kind:u8,session:u32) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(kind);
+    bytes.extend_from_slice(&session.to_le_bytes());
+    bytes
+}
+
+fn read_u32(bytes:&[u8],at:usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at+4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes:&[u8],at:usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at+8)?.try_into().ok()?))
+}
+
+// Kind and session of a datagram, None for anything that isn't ours.
+fn parse_header(bytes:&[u8]) -> Option<(u8,u32)> {
+    if bytes.len() < 9 || &bytes[0..4] != MAGIC {
+        return None;
+    }
+    Some((bytes[4],read_u32(bytes, 5)?))
+}
+
+pub struct NetSession {
+    socket:UdpSocket,
+    peer:SocketAddr,
+    session:u32,
+    pub slot:usize,
+    pub seed:u64,
+    tick:usize,
+    local:Vec<u8>, // Input bits by tick, INPUT_DELAY ahead of `tick`.
+    remote:Vec<u8>, // Peer input bits by tick, contiguous from zero.
+    peer_ack:usize, // How many of our inputs the peer holds.
+    hashes:Vec<u64>, // Our state hash after each tick.
+    last_heard:Instant,
+    status:NetStatus,
+    shim:Shim,
+}
+
+impl NetSession {
+    fn new(socket:UdpSocket,peer:SocketAddr,session:u32,slot:usize,seed:u64,shim:Shim) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let tick = 0;
+        let local = vec![encode(&Input::default());INPUT_DELAY];
+        let remote = vec![encode(&Input::default());INPUT_DELAY];
+        let peer_ack = INPUT_DELAY;
+        let hashes = vec![];
+        let last_heard = Instant::now();
+        let status = NetStatus::Connected;
+        Ok(Self { socket, peer, session, slot, seed, tick, local, remote, peer_ack, hashes, last_heard, status, shim })
+    }
+
+    // Wait for someone to join on `port` and hand them the map seed.
+    pub fn host(port:u16,seed:u64,shim:Shim) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0",port))?;
+        eprintln!("waiting for a player on port {}",port);
+        NetSession::host_on(socket, seed, shim)
+    }
+
+    // The same on a socket that's already bound.
+    pub fn host_on(socket:UdpSocket,seed:u64,mut shim:Shim) -> io::Result<Self> {
+        socket.set_read_timeout(Some(Duration::from_secs_f32(HELLO_INTERVAL)))?;
+        let session = rand::random::<u32>() | 1;
+        let mut buf = [0_u8;MAX_PACKET];
+        loop {
+            shim.flush(&socket);
+            let (len,addr) = match socket.recv_from(&mut buf) {
+                Ok(got) => got,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => continue,
+                Err(err) => return Err(err),
+            };
+            if parse_header(&buf[..len]) == Some((HELLO,0)) && buf.get(9) == Some(&VERSION) {
+                let mut net = NetSession::new(socket, addr, session, 0, seed, shim)?;
+                net.send_welcome();
+                eprintln!("{} joined",addr);
+                return Ok(net);
+            }
+        }
+    }
+
+    // Knock on the host until it answers with the session and seed.
+    pub fn join(addr:&str,mut shim:Shim) -> io::Result<Self> {
+        let peer:SocketAddr = addr.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("bad address `{}`",addr)))?;
+        let socket = UdpSocket::bind(("0.0.0.0",0))?;
+        socket.set_read_timeout(Some(Duration::from_secs_f32(HELLO_INTERVAL)))?;
+        let start = Instant::now();
+        let mut buf = [0_u8;MAX_PACKET];
+        while start.elapsed().as_secs_f32() < HANDSHAKE_TIME {
+            let mut hello = header(HELLO, 0);
+            hello.push(VERSION);
+            shim.send(&socket, hello, peer);
+            shim.flush(&socket);
+            let len = match socket.recv_from(&mut buf) {
+                Ok((len,from)) if from == peer => len,
+                Ok(_) => continue,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => continue,
+                Err(err) => return Err(err),
+            };
+            if let (Some((WELCOME,session)),Some(seed)) = (parse_header(&buf[..len]),read_u64(&buf[..len], 9)) {
+                let slot = buf.get(17).copied().unwrap_or(1) as usize;
+                return NetSession::new(socket, peer, session, slot, seed, shim);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, format!("no answer from {}",addr)))
+    }
+
+    fn send_welcome(&mut self){
+        let mut bytes = header(WELCOME, self.session);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(1); // Slot for the joiner.
+        self.shim.send(&self.socket, bytes, self.peer);
+    }
+
+    // Our inputs the peer hasn't acked yet, what we hold of theirs and our latest hash.
+    fn send_inputs(&mut self){
+        let mut bytes = header(INPUTS, self.session);
+        let first = self.peer_ack.min(self.local.len());
+        let last = self.local.len().min(first + MAX_RESEND);
+        bytes.extend_from_slice(&(self.remote.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(first as u32).to_le_bytes());
+        bytes.push((last - first) as u8);
+        bytes.extend_from_slice(&self.local[first..last]);
+        let hash_tick = self.hashes.len().saturating_sub(1);
+        bytes.extend_from_slice(&(hash_tick as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.hashes.last().copied().unwrap_or(0).to_le_bytes());
+        self.shim.send(&self.socket, bytes, self.peer);
+    }
+
+    fn recv_inputs(&mut self,bytes:&[u8]){
+        let (Some(ack),Some(first)) = (read_u32(bytes, 9),read_u32(bytes, 13)) else { return };
+        let count = bytes.get(17).copied().unwrap_or(0) as usize;
+        let Some(inputs) = bytes.get(18..18+count) else { return };
+        self.peer_ack = self.peer_ack.max(ack as usize);
+        for (n,&bits) in inputs.iter().enumerate() {
+            if first as usize + n == self.remote.len() {
+                self.remote.push(bits);
+            }
+        }
+        let at = 18 + count;
+        if let (Some(hash_tick),Some(hash)) = (read_u32(bytes, at),read_u64(bytes, at+4)) {
+            let hash_tick = hash_tick as usize;
+            if hash != 0 && hash_tick < self.hashes.len() && self.hashes[hash_tick] != hash {
+                self.status = NetStatus::Desync(hash_tick);
+            }
+        }
+    }
+
+    // Once per frame: read whatever arrived, send our side and keep the peer's pulse.
+    pub fn poll(&mut self){
+        let mut buf = [0_u8;MAX_PACKET];
+        while let Ok((len,addr)) = self.socket.recv_from(&mut buf) {
+            let bytes = &buf[..len];
+            match parse_header(bytes) {
+                Some((HELLO,0)) if self.slot == 0 && addr == self.peer => {self.send_welcome()} // Our welcome got lost.
+                Some((INPUTS,session)) if session == self.session => {
+                    self.peer = addr; // Follow the peer if its address changed.
+                    self.last_heard = Instant::now();
+                    if !self.is_down() {self.status = NetStatus::Connected}
+                    self.recv_inputs(bytes);
+                }
+                Some((BYE,session)) if session == self.session => {self.status = NetStatus::Left}
+                _ => {}
+            }
+        }
+        if self.status == NetStatus::Lost || self.status == NetStatus::Left {
+            return;
+        }
+        let silence = self.last_heard.elapsed().as_secs_f32();
+        if !self.is_down() { // A desynced side keeps sending its hashes so the peer finds out too.
+            if silence > GRACE_TIME {
+                self.status = NetStatus::Lost;
+            }else if silence > STALL_TIME {
+                self.status = NetStatus::Waiting;
+            }
+        }
+        self.send_inputs();
+        self.shim.flush(&self.socket);
+    }
+
+    // Both players' input for the next tick, in slot order, once it has arrived.
+    pub fn inputs(&self) -> Option<Vec<Input>> {
+        if self.is_down() || self.tick >= self.remote.len() || self.tick >= self.local.len() {
+            return None;
+        }
+        let (local,remote) = (decode(self.local[self.tick]),decode(self.remote[self.tick]));
+        Some(if self.slot == 0 {vec![local,remote]} else {vec![remote,local]})
+    }
+
+    // The tick was stepped, queue our next input and remember what the state came out as.
+    pub fn advance(&mut self,input:&Input,hash:u64){
+        self.local.push(encode(input));
+        self.hashes.push(hash);
+        self.tick += 1;
+    }
+
+    pub fn leave(&mut self){
+        let bye = header(BYE, self.session);
+        let _ = self.socket.send_to(&bye, self.peer);
+    }
+
+    // Desynced or gone for good, the game can't go on.
+    pub fn is_down(&self) -> bool {
+        matches!(self.status, NetStatus::Lost | NetStatus::Left | NetStatus::Desync(_))
+    }
+
+    pub fn status_message(&self) -> Option<String> {
+        let peer = 2 - self.slot;
+        match self.status {
+            NetStatus::Connected => None,
+            NetStatus::Waiting => Some(format!("WAITING FOR PLAYER {}",peer)),
+            NetStatus::Lost => Some(format!("PLAYER {} DISCONNECTED",peer)),
+            NetStatus::Left => Some(format!("PLAYER {} LEFT",peer)),
+            NetStatus::Desync(tick) => Some(format!("DESYNC AT TICK {}",tick)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::sim::{Sim,TICK,LIFES};
+
+    const TICKS:usize = 240;
+    const LATENCY_MS:u64 = 15;
+    const LOSS:f32 = 0.2;
+
+    // Each side walks its own way and plants now and then, so the players and the map both change.
+    fn scripted(slot:usize,tick:usize) -> Input {
+        let side = (tick / 30 + slot * 2) % 4;
+        Input { right: side == 0, down: side == 1, left: side == 2, up: side == 3, bomb: tick.is_multiple_of(70), ..Input::default() }
+    }
+
+    // Plays TICKS ticks in lockstep and hands back the hash after each one.
+    fn play(mut net:NetSession) -> Vec<u64> {
+        let mut sim = Sim::new(net.seed, NET_PLAYERS, LIFES);
+        let start = Instant::now();
+        while net.tick < TICKS {
+            assert!(!net.is_down(), "{:?}", net.status_message());
+            assert!(start.elapsed() < Duration::from_secs(60), "stuck at tick {}", net.tick);
+            net.poll();
+            match net.inputs() {
+                Some(inputs) => {
+                    sim.step(&inputs, TICK);
+                    net.advance(&scripted(net.slot, net.tick + INPUT_DELAY), sim.hash());
+                }
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        for _ in 0..50 { // Let the last hashes reach the peer.
+            net.poll();
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(!net.is_down(), "{:?}", net.status_message());
+        net.hashes.clone()
+    }
+
+    #[test]
+    fn host_and_joiner_stay_in_sync_over_a_bad_link(){
+        let socket = UdpSocket::bind(("127.0.0.1",0)).unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let host = thread::spawn(move || {
+            let net = NetSession::host_on(socket, 99, Shim::new(LATENCY_MS, LOSS)).unwrap();
+            play(net)
+        });
+        let joiner = NetSession::join(&addr, Shim::new(LATENCY_MS, LOSS)).unwrap();
+        assert_eq!((joiner.slot,joiner.seed), (1,99));
+        let joined = play(joiner);
+        let hosted = host.join().unwrap();
+        assert_eq!(hosted.len(), TICKS);
+        assert_eq!(hosted, joined);
+    }
+}
@@ -1,15 +1,14 @@
 use raylib::{prelude::*};
-use rand::Rng;
-use crate::bomb::*;
-use crate::grid::{SCALED_TILE,TILE_SIZE,MAX_RAND_FRAME,FRAMES,O,ANIM_DURATION};
-
-const EMPTY_Y:f32 = 32_f32;
-const GRASS_Y:f32 = 112_f32;
-const BLOCK_Y:f32 = 16_f32;
+use rand::{Rng, rngs::StdRng};
+use serde::{Serialize,Deserialize};
+use crate::save::RectangleDef;
+use crate::cell::*;
+use crate::atlas::atlas;
+use crate::grid::{TILE_SIZE,MAX_RAND_FRAME,O,ANIM_DURATION};
 
 pub const MAX_WALL_FRAMES:usize = 7;
 
-#[derive(PartialEq,Clone,Debug,Copy)]
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
 pub enum State {
     IDEAL,
     EXPLOADING,
@@ -19,26 +18,31 @@ pub enum State {
 #[macro_export]
 macro_rules! static_obj {
     ($name:ident) => {
-        #[derive(PartialEq,Clone,Debug,Copy)]
+        #[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
         pub struct $name{
+           #[serde(with = "RectangleDef")]
            pub rec:Rectangle,
+           #[serde(with = "RectangleDef")]
            pub rec2:Rectangle,
         }
     };
 }
 
+// Rolls past the last frame of the clip land on the `$common` one, so that variant turns up most.
+fn first(_frames:usize) -> usize {0}
+fn last(frames:usize) -> usize {frames - 1}
+
 macro_rules! impl_rand_obj {
-    ($name:ident,$max:literal,$min:literal,$y:expr) => {
+    ($name:ident,$clip:literal,$common:ident) => {
         impl $name{
-            pub fn new(i:usize,j:usize,scale:f32) -> $name {
-                let scaled_tile = TILE_SIZE*scale;
-                let x = (i as f32) * scaled_tile;
-                let y = (j as f32) * scaled_tile;
-                let rec2 =  Rectangle::new(x,y,SCALED_TILE,SCALED_TILE);
-                let mut rng = rand::thread_rng();
+            pub fn new(i:usize,j:usize,rng:&mut StdRng) -> $name {
+                let x = (i as f32) * TILE_SIZE;
+                let y = (j as f32) * TILE_SIZE;
+                let rec2 =  Rectangle::new(x,y,TILE_SIZE,TILE_SIZE);
+                let frames = atlas().clip($clip).frames.len();
                 let mut i = rng.gen_range(0..MAX_RAND_FRAME) as usize;
-                if i >= $max{i = $min;}
-                let rec = Rectangle::new(FRAMES[i],$y,TILE_SIZE,TILE_SIZE);
+                if i >= frames{i = $common(frames);}
+                let rec = atlas().frame($clip, i);
                 Self { rec, rec2}
               }
         }
@@ -48,11 +52,11 @@ macro_rules! impl_rand_obj {
 
 #[macro_export]
 macro_rules! impl_set_position {
-    ($name:ident,$fn:ident,$vec_field:ident,$scale:expr) => {
+    ($name:ident,$fn:ident,$vec_field:ident,$tile:expr) => {
         impl $name {
           pub fn $fn(&mut self, i:usize, j: usize) {
-                self.$vec_field.x = i as f32 * $scale;
-                self.$vec_field.y = j as f32 * $scale;
+                self.$vec_field.x = i as f32 * $tile;
+                self.$vec_field.y = j as f32 * $tile;
             }
         }
     };
@@ -62,7 +66,7 @@ macro_rules! impl_set_position {
 macro_rules! impl_static_draw {
     ($name:ident) => {
         impl $name {
-           pub fn draw(&mut self,sheets:&Texture2D,d:&mut RaylibDrawHandle){
+           pub fn draw(&mut self,sheets:&Texture2D,d:&mut impl RaylibDraw){
                 d.draw_texture_pro(sheets, self.rec, self.rec2,Vector2::default(),O, Color::WHITE);
             }
         }
@@ -70,7 +74,7 @@ macro_rules! impl_static_draw {
 
     ($name:ident,$prop:ident,$prop2:ident) => {
         impl $name {
-           pub fn draw2(&mut self,sheets:&Texture2D,d:&mut RaylibDrawHandle){
+           pub fn draw2(&mut self,sheets:&Texture2D,d:&mut impl RaylibDraw){
                 d.draw_texture_pro(sheets, self.$prop, self.$prop2,Vector2::default(),O, Color::WHITE);
             }
         }
@@ -81,13 +85,13 @@ macro_rules! impl_static_draw {
 macro_rules! impl_exp {
     ($obj:ident,$name:ident) => {
         impl $obj {
-            pub fn $name(&mut self,i:usize,j:usize,cells:&Vec<Vec<i8>>){
+            pub fn $name(&mut self,i:usize,j:usize,cells:&[Vec<Cell>]){
                 if self.state == State::IDEAL{
-                for &(x, y, mid) in [(-1, 0, FLAME_MID_RIGHT),(1, 0, FLAME_MID_LEFT),(0, -1, FLAME_MID_DOWN),(0, 1, FLAME_MID_TOP)].iter() {
+                for &(x, y, mid) in [(-1, 0, Flame::MidRight),(1, 0, Flame::MidLeft),(0, -1, Flame::MidDown),(0, 1, Flame::MidTop)].iter() {
                         let row = (i as isize + x as isize) as usize;
                         let col = (j as isize + y as isize) as usize;
-                        let cell = cells[row][col];
-                        if cell == EXPLOSION || cell == mid{
+                        let flame = cells[row][col].flame;
+                        if flame == Flame::Explosion || flame == mid{
                             self.state = State::EXPLOADING;
                             break;
                         }
@@ -98,9 +102,11 @@ macro_rules! impl_exp {
     };
 }
 
-#[derive(PartialEq,Clone,Debug,Copy)]
+#[derive(PartialEq,Clone,Debug,Copy,Serialize,Deserialize)]
 pub struct Wall {
+    #[serde(with = "RectangleDef")]
     pub rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec:Rectangle,
     pub frame:usize,
     pub time:f32,
@@ -108,12 +114,11 @@ pub struct Wall {
 }
 
 impl Wall {
-    pub fn new(i:usize,j:usize,scale:f32) -> Self {
-      let scaled_tile = TILE_SIZE*scale;
-      let x = (i as f32) * scaled_tile;
-      let y = (j as f32) * scaled_tile;
-      let rec2 =  Rectangle::new(x, y, scaled_tile , scaled_tile);
-      let rec =  Rectangle::new(O, O, TILE_SIZE , TILE_SIZE);
+    pub fn new(i:usize,j:usize) -> Self {
+      let x = (i as f32) * TILE_SIZE;
+      let y = (j as f32) * TILE_SIZE;
+      let rec2 =  Rectangle::new(x, y, TILE_SIZE, TILE_SIZE);
+      let rec = atlas().frame("wall", 0);
       let frame = 0;
       let time = O;
       let state = State::IDEAL;
@@ -142,8 +147,8 @@ impl Wall {
             self.frame += 1;
         }
         self.time += frame_time;
-        self.rec.x = FRAMES[self.frame];
         self.frame = self.frame  % MAX_WALL_FRAMES;
+        self.rec = atlas().frame("wall", self.frame);
     }
 }
 
@@ -154,11 +159,11 @@ static_obj!(Empty);
 static_obj!(Block);
 static_obj!(Grass);
 
-impl_set_position!(Empty,set_position,rec2,SCALED_TILE);
+impl_set_position!(Empty,set_position,rec2,TILE_SIZE);
 
-impl_rand_obj!(Block,4,0,BLOCK_Y);
-impl_rand_obj!(Grass,8,7,GRASS_Y);
-impl_rand_obj!(Empty,4,0,EMPTY_Y);
+impl_rand_obj!(Block,"block",first);
+impl_rand_obj!(Grass,"grass",last);
+impl_rand_obj!(Empty,"floor",first);
 
 impl_static_draw!(Grass);
 impl_static_draw!(Empty);
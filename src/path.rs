@@ -0,0 +1,197 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap,VecDeque};
+use crate::cell::*;
+use crate::grid::Position;
+
+// Path search over the cell grid for enemies and CPU players.
+// A cost function gives the price of stepping onto a cell, None when it can't be entered.
+
+const UNSEEN:u32 = u32::MAX;
+const STEPS:[(isize,isize);4] = [(0,-1),(0,1),(-1,0),(1,0)];
+
+// Open ground only, bombs and flames block.
+pub fn walker_cost(cell:&Cell) -> Option<u32> {
+    if cell.is_solid() || cell.bomb || cell.is_flame() {None} else {Some(1)}
+}
+
+// Players walk over bombs, only walls, blocks and flames stop them.
+pub fn bomber_cost(cell:&Cell) -> Option<u32> {
+    if cell.is_solid() || cell.is_flame() {None} else {Some(1)}
+}
+
+// Breakable walls are no obstacle.
+pub fn ghost_cost(cell:&Cell) -> Option<u32> {
+    if cell.terrain == Terrain::Block || cell.bomb || cell.is_flame() {None} else {Some(1)}
+}
+
+fn neighbours(cells:&[Vec<Cell>],position:Position) -> impl Iterator<Item = Position> + '_ {
+    let (i,j) = position;
+    STEPS.iter().filter_map(move |&(di,dj)| {
+        let ni = i.checked_add_signed(di)?;
+        let nj = j.checked_add_signed(dj)?;
+        cells.get(ni)?.get(nj)?;
+        Some((ni,nj))
+    })
+}
+
+fn manhattan(a:Position,b:Position) -> u32 {
+    (a.0.abs_diff(b.0) + a.1.abs_diff(b.1)) as u32
+}
+
+// Cheapest way from `from` to `to`, both ends included. The start cell is never costed
+// so an agent standing on its own bomb can still walk off it.
+pub fn find_path<F:Fn(&Cell) -> Option<u32>>(cells:&[Vec<Cell>],from:Position,to:Position,cost:F) -> Option<Vec<Position>> {
+    let cols = cells[0].len();
+    let index = |(i,j):Position| i * cols + j;
+    let mut best = vec![UNSEEN;cells.len() * cols];
+    let mut parent = vec![usize::MAX;cells.len() * cols];
+    let mut open = BinaryHeap::new();
+    best[index(from)] = 0;
+    open.push(Reverse((manhattan(from, to),0,from)));
+    while let Some(Reverse((_,spent,here))) = open.pop() {
+        if here == to {
+            let mut path = vec![to];
+            let mut at = index(to);
+            while parent[at] != usize::MAX {
+                at = parent[at];
+                path.push((at / cols, at % cols));
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if spent > best[index(here)] {
+            continue; // Stale entry, a cheaper way here was already expanded.
+        }
+        for next in neighbours(cells, here) {
+            let Some(step) = cost(&cells[next.0][next.1]) else { continue };
+            let total = spent + step;
+            if total < best[index(next)] {
+                best[index(next)] = total;
+                parent[index(next)] = index(here);
+                open.push(Reverse((total + manhattan(next, to),total,next)));
+            }
+        }
+    }
+    None
+}
+
+// Step counts from one cell to every cell it can reach.
+pub struct FloodFill {
+    cols:usize,
+    dist:Vec<u32>,
+    parent:Vec<usize>,
+    order:Vec<Position>, // Every reached cell, nearest first.
+}
+
+impl FloodFill {
+    // Breadth first, so only whether a cell can be entered matters, not its cost.
+    pub fn new<F:Fn(&Cell) -> Option<u32>>(cells:&[Vec<Cell>],from:Position,cost:F) -> Self {
+        FloodFill::search(cells, from, |(i,j),_| cost(&cells[i][j]).is_some())
+    }
+
+    // Like `new` but `enter` also sees where the cell is and after how many steps it would be
+    // reached, for searches where cells open and close over time.
+    pub fn search<F:Fn(Position,u32) -> bool>(cells:&[Vec<Cell>],from:Position,enter:F) -> Self {
+        let cols = cells[0].len();
+        let index = |(i,j):Position| i * cols + j;
+        let mut dist = vec![UNSEEN;cells.len() * cols];
+        let mut parent = vec![usize::MAX;cells.len() * cols];
+        let mut queue = VecDeque::new();
+        dist[index(from)] = 0;
+        queue.push_back(from);
+        let mut order = vec![];
+        while let Some(here) = queue.pop_front() {
+            order.push(here);
+            for next in neighbours(cells, here) {
+                if dist[index(next)] != UNSEEN || !enter(next, dist[index(here)] + 1) {
+                    continue;
+                }
+                dist[index(next)] = dist[index(here)] + 1;
+                parent[index(next)] = index(here);
+                queue.push_back(next);
+            }
+        }
+        Self { cols, dist, parent, order }
+    }
+
+    pub fn reached(&self) -> &[Position] {
+        &self.order
+    }
+
+    pub fn distance(&self,(i,j):Position) -> Option<u32> {
+        let dist = *self.dist.get(i * self.cols + j)?;
+        if dist == UNSEEN {None} else {Some(dist)}
+    }
+
+    pub fn path_to(&self,to:Position) -> Option<Vec<Position>> {
+        self.distance(to)?;
+        let mut path = vec![to];
+        let mut at = to.0 * self.cols + to.1;
+        while self.parent[at] != usize::MAX {
+            at = self.parent[at];
+            path.push((at / self.cols, at % self.cols));
+        }
+        path.reverse();
+        return Some(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+
+    // A wall of breakable walls down the middle with one gap at the bottom.
+    const MAP:&str = "#######\n#1.+..#\n#..+..#\n#..+..#\n#.....#\n#######\n";
+
+    fn cells() -> Vec<Vec<Cell>> {
+        Map::parse("test", MAP).unwrap().cells
+    }
+
+    fn is_walk(path:&[Position]) -> bool {
+        path.windows(2).all(|step| manhattan(step[0], step[1]) == 1)
+    }
+
+    #[test]
+    fn walkers_route_around_walls(){
+        let cells = cells();
+        let path = find_path(&cells, (1,1), (5,1), walker_cost).unwrap();
+        assert_eq!((path[0],path[path.len()-1]), ((1,1),(5,1)));
+        assert!(is_walk(&path));
+        assert!(path.iter().all(|&(i,j)| walker_cost(&cells[i][j]).is_some()));
+        assert_eq!(path.len(), 11, "down to the gap, across and back up");
+    }
+
+    #[test]
+    fn ghosts_pass_through_walls(){
+        let path = find_path(&cells(), (1,1), (5,1), ghost_cost).unwrap();
+        assert_eq!(path.len(), 5, "straight across the wall");
+        assert!(is_walk(&path));
+    }
+
+    #[test]
+    fn blocked_goal_has_no_path(){
+        let mut cells = cells();
+        cells[3][4].terrain = Terrain::Block; // Close the gap.
+        assert_eq!(find_path(&cells, (1,1), (5,1), walker_cost), None);
+        assert_eq!(find_path(&cells, (1,1), (3,1), walker_cost), None, "the goal itself is a wall");
+        cells[2][1].bomb = true;
+        assert_eq!(find_path(&cells, (1,1), (2,2), walker_cost).map(|path| path.len()), Some(3), "around the bomb");
+    }
+
+    #[test]
+    fn flood_fill_counts_steps_and_stops_at_walls(){
+        let mut cells = cells();
+        let fill = FloodFill::new(&cells, (1,1), walker_cost);
+        assert_eq!(fill.reached()[0], (1,1));
+        assert_eq!(fill.distance((5,1)), Some(10));
+        assert_eq!(fill.distance((3,1)), None);
+        assert_eq!(fill.path_to((5,1)).map(|path| path.len()), Some(11));
+        cells[3][4].terrain = Terrain::Block;
+        let fill = FloodFill::new(&cells, (1,1), walker_cost);
+        assert_eq!(fill.distance((5,1)), None);
+        assert_eq!(fill.path_to((5,1)), None);
+        let ghost = FloodFill::new(&cells, (1,1), ghost_cost);
+        assert_eq!(ghost.distance((5,1)), Some(4));
+    }
+}
@@ -2,31 +2,31 @@ use raylib::prelude::*;
 use crate::bonus::*;
 use crate::grid::*;
 use crate::bomb::*;
-use crate::noise::*;
+use crate::cell::*;
 use crate::upgrade::*;
+use crate::sim::*;
+use serde::{Serialize,Deserialize};
+use crate::save::{RectangleDef,ColorDef};
+use crate::atlas::atlas;
 
-pub type PlayerSounds<'a> = (&'a Sound, &'a Sound);
-
-const SPEED:f32 = 30_f32 * SCALE;
-const MAX_PLAYER_FRAME:usize = 4;
+pub const SPEED:f32 = 30_f32;
+const STEER_SLACK:f32 = 0.5_f32; // How close to a tile counts as on it when walking a path, one tick of movement.
+pub const MAX_PLAYER_FRAME:usize = 4; // Frames in each player clip, dying and spawning end on the last.
 const P_COLORS:&[Color;3] = &[Color::RED,Color::YELLOW,Color::WHITE];
-const MARGIN:f32 = 0.7*SCALE;
+pub const PLAYER_COLORS:[Color;4] = [Color::WHITE,Color::SKYBLUE,Color::LIME,Color::PINK]; // Tint per player slot.
+const MARGIN:f32 = 0.7;
 const BOMB_RELOAD_TIME:f32 = 1_f32;
+pub const MAX_LIVE_BOMBS:usize = 3; // Bombs a player can have down at once.
+const WEAPONS:[UpgradeType;5] = [UpgradeType::Default,UpgradeType::BlackBomb,UpgradeType::BlueBomb,UpgradeType::PurpleBomb,UpgradeType::RedBomb];
 
-const LD_Y:f32 = 48_f32; // Left Down
-const RT_Y:f32 = 64_f32; // Right Top
-const DS_Y:f32 = 80_f32; // Death Spawn
-
-const LRD_FRAMES:&[f32;4] = &[0_f32,16_f32,32_f32,48_f32];
-const TDS_FRAMES:&[f32;4] = &[64_f32,80_f32,96_f32,112_f32];
 const STAND_FRAMES:&[usize;2] = &[0,3];
 // Collison shape const for player
-const COLL_MARGIN_X:f32 = 3_f32*SCALE;
-const COLL_MARGIN_Y:f32 = 2_f32*SCALE;
-const PLAYER_HEIGHT:f32 = 13_f32*SCALE;
-const PLAYER_WIDTH:f32 = 9_f32*SCALE;
+const COLL_MARGIN_X:f32 = 3_f32;
+const COLL_MARGIN_Y:f32 = 2_f32;
+const PLAYER_HEIGHT:f32 = 13_f32;
+const PLAYER_WIDTH:f32 = 9_f32;
 
-#[derive(Clone,PartialEq,Debug,Copy)]
+#[derive(Clone,PartialEq,Debug,Copy,Serialize,Deserialize)]
 pub enum DIR {
     Up,
     Down,
@@ -53,7 +53,7 @@ impl DIR{
     }
 }
 
-#[derive(Clone,PartialEq,Debug)]
+#[derive(Clone,PartialEq,Debug,Serialize,Deserialize)]
 pub enum State2 {
    SPAWN,
    SPAWNING,
@@ -62,8 +62,11 @@ pub enum State2 {
    DEAD,
 }
 
-#[derive(Clone,PartialEq,Debug)]
+#[derive(Clone,PartialEq,Debug,Serialize,Deserialize)]
 pub struct Player {
+    pub slot:usize,
+    #[serde(with = "ColorDef")]
+    pub color:Color,
     pub dir:DIR,
     pub weapon:UpgradeType,
     pub lifes:usize,
@@ -79,14 +82,25 @@ pub struct Player {
     pub temp_score:usize,
     pub score:usize,
     pub moving:bool,
+    #[serde(with = "ColorDef")]
     pub tint:Color,
+    #[serde(with = "RectangleDef")]
     pub rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
+    pub prev_rec2:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_up:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_down:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_right:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_left:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_spawn:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_death:Rectangle,
+    #[serde(with = "RectangleDef")]
     pub rec_shadow:Rectangle,
     pub state:State2,
     pub frames:usize,
@@ -98,8 +112,8 @@ pub struct Player {
 macro_rules! impl_dir_draw {
     ($name:ident,$fn_name:ident,$prop:ident) => {
         impl $name {
-            fn $fn_name(&mut self,player_texture:&Texture2D,d:&mut RaylibDrawHandle){
-                d.draw_texture_pro(player_texture, self.$prop, self.rec2,Vector2::default(),O, self.tint);
+            fn $fn_name(&mut self,player_texture:&Texture2D,d:&mut impl RaylibDraw,dest:Rectangle){
+                d.draw_texture_pro(player_texture, self.$prop, dest,Vector2::default(),O, self.tint);
             }
         }
     };
@@ -113,11 +127,22 @@ impl_dir_draw!(Player,draw_spawn,rec_spawn);
 impl_dir_draw!(Player,draw_death,rec_death);
 impl_dir_draw!(Player,draw_shadow,rec_shadow);
 
+// Flame length of a bomb type.
+pub fn bomb_power(weapon:UpgradeType) -> usize {
+    match weapon {
+      UpgradeType::BlackBomb => 2,
+      UpgradeType::BlueBomb => 3,
+      UpgradeType::PurpleBomb => 4,
+      UpgradeType::RedBomb => 5,
+      UpgradeType::Default => 1,
+    }
+}
+
 impl Player{
-   pub fn new() -> Self {
+   pub fn new(slot:usize,spawn:Position,lifes:usize) -> Self {
+     let color = PLAYER_COLORS[slot % PLAYER_COLORS.len()];
      let dir = DIR::Down;
      let weapon = UpgradeType::Default;
-     let lifes = 3;
      let cash = 0;
      let silver_coin = 0;
      let gold_coin = 0;
@@ -130,21 +155,30 @@ impl Player{
      let temp_score = 0;
      let score = 0;
      let moving = false;
-     let tint = P_COLORS[2];
+     let tint = color;
      let frames = 0;
-     let rec2 =  Rectangle::new(SCALED_TILE, SCALED_TILE, SCALED_TILE, SCALED_TILE);
-     let rec_up = Rectangle::new(LRD_FRAMES[frames], RT_Y, TILE_SIZE, TILE_SIZE);
-     let rec_down = Rectangle::new(TDS_FRAMES[frames], LD_Y, TILE_SIZE, TILE_SIZE); 
-     let rec_right = Rectangle::new(LRD_FRAMES[frames], RT_Y, TILE_SIZE, TILE_SIZE);
-     let rec_left = Rectangle::new(TDS_FRAMES[frames], LD_Y, TILE_SIZE, TILE_SIZE);
-     let rec_spawn = Rectangle::new(TDS_FRAMES[frames], DS_Y, TILE_SIZE, TILE_SIZE);
-     let rec_death = Rectangle::new(LRD_FRAMES[frames], DS_Y, TILE_SIZE, TILE_SIZE);
-     let rec_shadow = Rectangle::new(FRAMES[5],32_f32,TILE_SIZE,TILE_SIZE);
+     let rec2 =  Rectangle::new(spawn.0 as f32 * TILE_SIZE, spawn.1 as f32 * TILE_SIZE, TILE_SIZE, TILE_SIZE);
+     let prev_rec2 = rec2;
+     let rec_up = atlas().frame("player_up", frames);
+     let rec_down = atlas().frame("player_down", frames);
+     let rec_right = atlas().frame("player_right", frames);
+     let rec_left = atlas().frame("player_left", frames);
+     let rec_spawn = atlas().frame("player_spawn", frames);
+     let rec_death = atlas().frame("player_death", frames);
+     let rec_shadow = atlas().frame("shadow", 0);
      let state = State2::SPAWN;
      let time = 0_f32;
      let delay = 0.11_f32;
      let bomb_reload_time = BOMB_RELOAD_TIME;
-     Self{dir,weapon,lifes,cash,silver_coin,gold_coin,diamond,black_bombs,blue_bombs,purple_bombs,red_bombs,delay_bool,temp_score,score ,moving,tint, rec2 , rec_up, rec_down, rec_right, rec_left,rec_spawn,rec_death,rec_shadow, state, frames, time,delay,bomb_reload_time}
+     Self{slot,color,dir,weapon,lifes,cash,silver_coin,gold_coin,diamond,black_bombs,blue_bombs,purple_bombs,red_bombs,delay_bool,temp_score,score ,moving,tint, rec2 ,prev_rec2, rec_up, rec_down, rec_right, rec_left,rec_spawn,rec_death,rec_shadow, state, frames, time,delay,bomb_reload_time}
+    }
+
+    // The same player freshly spawned on the next level, keeping lives, score, coins and bombs.
+    pub fn carry_over(&self,spawn:Position) -> Self {
+      let score = self.score + self.temp_score;
+      Self { weapon: self.weapon, cash: self.cash, silver_coin: self.silver_coin, gold_coin: self.gold_coin, diamond: self.diamond,
+        black_bombs: self.black_bombs, blue_bombs: self.blue_bombs, purple_bombs: self.purple_bombs, red_bombs: self.red_bombs,
+        score, ..Player::new(self.slot, spawn, self.lifes) }
     }
 
     pub fn get_coll_shape(&self) -> Rectangle {
@@ -155,8 +189,8 @@ impl Player{
      }
    
      pub fn get_position(&self) -> Position {
-       let i = ((self.rec2.x + MARGIN_POS)/ SCALED_TILE) as usize;
-       let j =  ((self.rec2.y + MARGIN_POS)/ SCALED_TILE) as usize;
+       let i = ((self.rec2.x + MARGIN_POS)/ TILE_SIZE) as usize;
+       let j =  ((self.rec2.y + MARGIN_POS)/ TILE_SIZE) as usize;
        return (i,j);
    }
 
@@ -183,41 +217,112 @@ impl Player{
    pub fn plant_bomb(&mut self,grid:&mut Grid){
      let position = self.get_position();
      let (i,j) = position;
-    if grid.cells[i][j] != EMPTY || self.bomb_reload_time < BOMB_RELOAD_TIME{
+    if !grid.cells[i][j].is_empty() || !self.bomb_ready(grid){
         return;
-      } else if self.bomb_reload_time >= BOMB_RELOAD_TIME{
+      } else {
          let mut new_bomb = Bomb::new();
          new_bomb.set_position(i,j);
+         new_bomb.power = self.use_weapon();
+         new_bomb.owner = self.slot;
          self.bomb_reload_time = 0_f32;
-         grid.cells[i][j] = BOMB;
+         grid.cells[i][j].bomb = true;
          grid.game_objs[i][j] = GameObjs::Bomb(new_bomb);
       }
    }
 
-   pub fn control(&mut self,rl:&mut RaylibHandle,frame_time:f32,grid:&mut Grid){
+   pub fn get_bombs(&self,weapon:UpgradeType) -> usize {
+     match weapon {
+       UpgradeType::BlackBomb => self.black_bombs,
+       UpgradeType::BlueBomb => self.blue_bombs,
+       UpgradeType::PurpleBomb => self.purple_bombs,
+       UpgradeType::RedBomb => self.red_bombs,
+       UpgradeType::Default => 0,
+     }
+   }
+
+   pub fn cycle_weapon(&mut self){ // Step to the next bomb type in stock, the plain bomb is always there.
+     let current = WEAPONS.iter().position(|&weapon| weapon == self.weapon).unwrap_or(0);
+     for k in 1..=WEAPONS.len() {
+       let weapon = WEAPONS[(current + k) % WEAPONS.len()];
+       if weapon == UpgradeType::Default || self.get_bombs(weapon) > 0 {
+         self.weapon = weapon;
+         return;
+       }
+     }
+   }
+
+   fn use_weapon(&mut self) -> usize { // Spend one bomb of the selected type and give back its power.
+     match self.weapon {
+       UpgradeType::BlackBomb => {self.black_bombs -= 1}
+       UpgradeType::BlueBomb => {self.blue_bombs -= 1}
+       UpgradeType::PurpleBomb => {self.purple_bombs -= 1}
+       UpgradeType::RedBomb => {self.red_bombs -= 1}
+       UpgradeType::Default => {}
+     }
+     let power = bomb_power(self.weapon);
+     if self.get_bombs(self.weapon) == 0 {
+       self.weapon = UpgradeType::Default;
+     }
+     return power;
+   }
+
+   // Most powerful bomb type in stock.
+   pub fn strongest_weapon(&self) -> UpgradeType {
+     *WEAPONS.iter().rev().find(|&&weapon| weapon == UpgradeType::Default || self.get_bombs(weapon) > 0).unwrap_or(&UpgradeType::Default)
+   }
+
+   pub fn bomb_ready(&self,grid:&Grid) -> bool {
+     self.bomb_reload_time >= BOMB_RELOAD_TIME && grid.live_bombs(self.slot) < MAX_LIVE_BOMBS
+   }
+
+   // Arrow keys that walk towards tile `to`, None once the player stands on it.
+   pub fn steer(&self,to:Position) -> Option<Input> {
+     let dx = to.0 as f32 * TILE_SIZE - self.rec2.x;
+     let dy = to.1 as f32 * TILE_SIZE - self.rec2.y;
+     if dx.abs() <= STEER_SLACK && dy.abs() <= STEER_SLACK {
+       return None;
+     }
+     let horizontal = dx.abs() > dy.abs(); // Close the bigger gap first, that lines up for the turn.
+     Some(Input { up: !horizontal && dy < 0_f32, down: !horizontal && dy > 0_f32, left: horizontal && dx < 0_f32, right: horizontal && dx > 0_f32, ..Input::default() })
+   }
+
+   // Whether the player can walk straight from tile `from` into the neighbouring `to`, that is
+   // it is already lined up with the row or column the step runs along.
+   pub fn lined_up(&self,from:Position,to:Position) -> bool {
+     if from.0 == to.0 {
+       (from.0 as f32 * TILE_SIZE - self.rec2.x).abs() <= STEER_SLACK
+     }else{
+       (from.1 as f32 * TILE_SIZE - self.rec2.y).abs() <= STEER_SLACK
+     }
+   }
+
+   pub fn control(&mut self,input:&Input,frame_time:f32,grid:&mut Grid){
     let obj_rec = self.get_coll_shape();
     let position = self.get_position();
     let neutral_coll = grid.get_collisions(position, obj_rec).1;
 
     match self.state { 
     State2::ALIVE => {
-     if rl.is_key_down(KeyboardKey::KEY_UP) && self.dir != DIR::NotUp{//Set direction and start movement.
+     if input.cycle {
+        self.cycle_weapon();
+     }
+     if input.up && self.dir != DIR::NotUp{//Set direction and start movement.
         self.dir = DIR::Up;
         self.moving = true;
         self.go(neutral_coll,frame_time);
-    }else if rl.is_key_down(KeyboardKey::KEY_DOWN) && self.dir != DIR::NotDown{
+    }else if input.down && self.dir != DIR::NotDown{
         self.dir = DIR::Down;
         self.moving = true;
         self.go(neutral_coll,frame_time);
-    }else if rl.is_key_down(KeyboardKey::KEY_LEFT) && self.dir != DIR::NotLeft{
+    }else if input.left && self.dir != DIR::NotLeft{
         self.dir = DIR::Left;
         self.moving = true;
         self.go(neutral_coll,frame_time);
-    }else if rl.is_key_down(KeyboardKey::KEY_RIGHT) && self.dir != DIR::NotRight{
+    }else if input.right && self.dir != DIR::NotRight{
         self.dir = DIR::Right;
         self.moving = true;
         self.go(neutral_coll,frame_time);
-    }else if rl.is_key_pressed(KeyboardKey::KEY_B) {
+    }else if input.bomb {
         self.moving = false;
         self.plant_bomb(grid);
     }else{
@@ -234,64 +339,61 @@ impl Player{
       }
   }
 
-  pub fn take(&mut self,grid:&mut Grid,audio:&mut RaylibAudio,sounds:PlayerSounds){
-      let (bonus_sound,upgrade_sound) = sounds;
-      let p_pos = self.get_position();
-
-      for i in 0..grid.upgrade_vec.len(){
-          let upgrade = &mut grid.upgrade_vec[i];
-          let u_pos = upgrade.get_position();
-
-          if p_pos == u_pos {
-            match upgrade.up_type {
-             UpgradeType::BlackBomb => { self.black_bombs += upgrade.val;  upgrade.play_audio(audio, upgrade_sound);},
-             UpgradeType::BlueBomb => { self.blue_bombs += upgrade.val;  upgrade.play_audio(audio, upgrade_sound);},
-             UpgradeType::PurpleBomb => { self.purple_bombs += upgrade.val;  upgrade.play_audio(audio, upgrade_sound);},
-             UpgradeType::RedBomb => { self.red_bombs += upgrade.val;  upgrade.play_audio(audio, upgrade_sound);},
+  pub fn take(&mut self,grid:&mut Grid){
+      let (i,j) = self.get_position();
+      let cell = grid.cells[i][j];
+      if cell.terrain != Terrain::Empty {
+        return;
+      }
+      match cell.item {
+        Item::Upgrade(up_type,val) => {
+          match up_type {
+             UpgradeType::BlackBomb => { self.black_bombs += val;},
+             UpgradeType::BlueBomb => { self.blue_bombs += val;},
+             UpgradeType::PurpleBomb => { self.purple_bombs += val;},
+             UpgradeType::RedBomb => { self.red_bombs += val;},
              _ => {}
-            }
-            grid.rm_upgrade_obj(i);
+          }
+          grid.events.push(SoundEvent::Upgrade);
         }
-      }
-
-      for i in 0..grid.bonus_vec.len(){
-          let bonus = &mut grid.bonus_vec[i];
-          let b_pos = bonus.get_position();
-          if p_pos == b_pos {
-             match bonus.bonus_type {
-              BonusType::Heart => {self.lifes += bonus.val; bonus.play_audio(audio, bonus_sound)},
-              BonusType::Cash => {self.cash += 1; self.temp_score += bonus.val;bonus.play_audio(audio, bonus_sound)},
-              BonusType::SilverCoin => {self.silver_coin += 1; self.temp_score += bonus.val;bonus.play_audio(audio, bonus_sound)},
-              BonusType::GoldCoin => {self.gold_coin += 1; self.temp_score += bonus.val;bonus.play_audio(audio, bonus_sound)},
-              BonusType::Diamond => {self.diamond += 1; self.temp_score += bonus.val;bonus.play_audio(audio, bonus_sound)},
+        Item::Bonus(bonus_type) => {
+          let val = Bonus::get_val(bonus_type);
+          match bonus_type {
+              BonusType::Heart => {self.lifes += val},
+              BonusType::Cash => {self.cash += 1; self.temp_score += val},
+              BonusType::SilverCoin => {self.silver_coin += 1; self.temp_score += val},
+              BonusType::GoldCoin => {self.gold_coin += 1; self.temp_score += val},
+              BonusType::Diamond => {self.diamond += 1; self.temp_score += val},
                _ => {}
-             }
-             grid.rm_bonus_obj(i);
           }
+          grid.events.push(SoundEvent::Bonus);
+        }
+        Item::Default | Item::Exit => {return}
       }
+      grid.rm_item(i, j);
   }
 
   pub fn animate(&mut self,frame_time:f32){ 
     let mut local_rec = &mut self.rec_down;//Default rectangle down as per direction.
-    let mut local_frames = TDS_FRAMES;
+    let mut clip = "player_down";
 
     match self.dir{//Check player direction and set default rectangle.
-        DIR::Up => {local_rec = &mut self.rec_up; local_frames = TDS_FRAMES;}
-        DIR::Right => {local_rec = &mut self.rec_right; local_frames = LRD_FRAMES;}
-        DIR::Left => {local_rec = &mut self.rec_left; local_frames = LRD_FRAMES}
+        DIR::Up => {local_rec = &mut self.rec_up; clip = "player_up";}
+        DIR::Right => {local_rec = &mut self.rec_right; clip = "player_right";}
+        DIR::Left => {local_rec = &mut self.rec_left; clip = "player_left"}
         _ => {}
      }
 
     match self.state {
         State2::DYING => { 
-            local_frames = &LRD_FRAMES; 
+            clip = "player_death";
             local_rec = &mut self.rec_death;
-            local_rec.x = local_frames[self.frames];
+            *local_rec = atlas().frame(clip, self.frames);
             self.tint = P_COLORS[self.frames % (P_COLORS.len()-1)];}
         State2::SPAWNING => {
-            local_frames = &TDS_FRAMES; self.tint = P_COLORS[2];
+            clip = "player_spawn"; self.tint = self.color;
             local_rec = &mut self.rec_spawn;
-            local_rec.x = local_frames[self.frames];
+            *local_rec = atlas().frame(clip, self.frames);
          }
         _ => {}
      }
@@ -304,10 +406,10 @@ impl Player{
     self.frames %= MAX_PLAYER_FRAME;
     
     if self.moving && self.state == State2::ALIVE{//Animate the player when moving true and and death false.
-    local_rec.x = local_frames[self.frames]; 
+    *local_rec = atlas().frame(clip, self.frames);
     }else if !self.moving && self.state == State2::ALIVE {  //Animate the player when moving false and and death false.
         self.frames = STAND_FRAMES[self.frames%STAND_FRAMES.len()];
-        local_rec.x = local_frames[self.frames];
+        *local_rec = atlas().frame(clip, self.frames);
     }
   }
 
@@ -319,19 +421,29 @@ impl Player{
       self.delay_bool = !self.delay_bool;
   }
   
-  pub fn update_state(&mut self,grid:&mut Grid,frame_time:f32){
+  // Out of SPAWN and into the spawn animation, the sim decides when.
+  pub fn spawn(&mut self){
+      self.delay = 0.11;
+      self.state = State2::SPAWNING;
+  }
+
+  // Lose a life and start dying, flames and enemies both end up here.
+  pub fn kill(&mut self){
+      if self.state != State2::ALIVE {
+        return;
+      }
+      self.lifes -= 1;
+      self.state = State2::DYING;
+      self.delay = 0.11;
+      self.frames = 0;
+  }
+
+  pub fn update_state(&mut self,grid:&mut Grid){
       let position = self.get_position();
       let obj_rec = self.get_coll_shape();
       let fatal_coll = grid.get_collisions(position, obj_rec).0;
 
       match self.state {
-        State2::SPAWN => {
-          self.delay += frame_time;
-          if self.delay > 2_f32 {
-            self.delay = 0.11;
-            self.state = State2::SPAWNING;
-          }
-        }
         State2::SPAWNING => {
             if self.frames >= MAX_FRAME{
                 self.state = State2::ALIVE;
@@ -342,21 +454,18 @@ impl Player{
         }
         State2::ALIVE => {
           if fatal_coll{
-             self.lifes -= 1;
-             self.state = State2::DYING;
-             self.delay = 0.11;
-             self.frames = 0;
+             self.kill();
           }
         }
         State2::DYING => {
           if self.frames >= MAX_FRAME{
-            if self.lifes <= 0 {
+            if self.lifes == 0 {
              self.state = State2::DEAD;
             }else{
              self.frames = 0;
              self.delay = 0_f32;
              self.state = State2::SPAWN;
-             self.tint = Color::WHITE;
+             self.tint = self.color;
             }  
           }
         }
@@ -364,30 +473,38 @@ impl Player{
       }
   }
 
-  pub fn draw(&mut self,d:&mut RaylibDrawHandle,player_texture:&Texture2D){    // Draw and update function.
+  pub fn get_render_rec(&self,alpha:f32) -> Rectangle { // Blend the last two ticks so movement stays smooth between them.
+    let x = self.prev_rec2.x + (self.rec2.x - self.prev_rec2.x) * alpha;
+    let y = self.prev_rec2.y + (self.rec2.y - self.prev_rec2.y) * alpha;
+    Rectangle::new(x, y, self.rec2.width, self.rec2.height)
+  }
+
+  pub fn draw(&mut self,d:&mut impl RaylibDraw,player_texture:&Texture2D,alpha:f32){    // Draw and update function.
+    let dest = self.get_render_rec(alpha);
     match self.state {
       State2::ALIVE => 
        match self.dir {   //Draw the player on screen.
-         DIR::Down => { self.draw_shadow(player_texture, d);self.draw_down(player_texture, d)}
-         DIR::Up => {self.draw_shadow(player_texture, d);self.draw_up(player_texture,d)}
-         DIR::Right => {self.draw_shadow(player_texture, d);self.draw_right(player_texture, d)}
-         DIR::Left => {self.draw_shadow(player_texture, d);self.draw_left(player_texture, d)}
-         DIR::NotDown => {self.draw_shadow(player_texture, d);self.draw_down(player_texture, d)}
-         DIR::NotUp => {self.draw_shadow(player_texture, d);self.draw_up(player_texture,d)}
-         DIR::NotRight => {self.draw_shadow(player_texture, d);self.draw_right(player_texture, d)}
-         DIR::NotLeft => {self.draw_shadow(player_texture, d);self.draw_left(player_texture, d)}
+         DIR::Down => { self.draw_shadow(player_texture, d,dest);self.draw_down(player_texture, d,dest)}
+         DIR::Up => {self.draw_shadow(player_texture, d,dest);self.draw_up(player_texture,d,dest)}
+         DIR::Right => {self.draw_shadow(player_texture, d,dest);self.draw_right(player_texture, d,dest)}
+         DIR::Left => {self.draw_shadow(player_texture, d,dest);self.draw_left(player_texture, d,dest)}
+         DIR::NotDown => {self.draw_shadow(player_texture, d,dest);self.draw_down(player_texture, d,dest)}
+         DIR::NotUp => {self.draw_shadow(player_texture, d,dest);self.draw_up(player_texture,d,dest)}
+         DIR::NotRight => {self.draw_shadow(player_texture, d,dest);self.draw_right(player_texture, d,dest)}
+         DIR::NotLeft => {self.draw_shadow(player_texture, d,dest);self.draw_left(player_texture, d,dest)}
        }
-       State2::SPAWNING => {self.draw_spawn(player_texture, d);}
-       State2::DYING => {self.draw_death(player_texture, d);}
+       State2::SPAWNING => {self.draw_spawn(player_texture, d,dest);}
+       State2::DYING => {self.draw_death(player_texture, d,dest);}
      _ => {}   
     }  
  }
 
-  pub fn update(&mut self,rl:&mut RaylibHandle,grid:&mut Grid,audio:&mut RaylibAudio,sounds:PlayerSounds,frame_time:f32){
-    self.take(grid, audio, sounds);
+  pub fn update(&mut self,input:&Input,grid:&mut Grid,frame_time:f32){
+    self.prev_rec2 = self.rec2;
+    self.take(grid);
     self.update_score();
-    self.update_state(grid,frame_time);
-    self.control(rl, frame_time, grid);
+    self.update_state(grid);
+    self.control(input, frame_time, grid);
     self.bomb_reload(frame_time);
     self.animate(frame_time);
   }
@@ -0,0 +1,272 @@
+use std::fs;
+use std::io;
+use crate::sim::{Input,MAX_PLAYERS};
+use crate::map::Map;
+
+const MAGIC:&[u8;4] = b"BMRP";
+const VERSION:u8 = 10; // Bumped when map generation, the simulation or the layout changes, older replays would play out differently.
+const HEADER_LEN:usize = 31;
+const NO_MAP:u64 = 0; // The level's own map rather than one from `--map`.
+
+const UP_BIT:u8 = 1;
+const DOWN_BIT:u8 = 2;
+const LEFT_BIT:u8 = 4;
+const RIGHT_BIT:u8 = 8;
+const BOMB_BIT:u8 = 16;
+const PAUSE_BIT:u8 = 32;
+const CYCLE_BIT:u8 = 64;
+
+pub fn encode(input:&Input) -> u8 {
+    let mut bits = 0;
+    for &(on,bit) in [(input.up,UP_BIT),(input.down,DOWN_BIT),(input.left,LEFT_BIT),
+                      (input.right,RIGHT_BIT),(input.bomb,BOMB_BIT),(input.pause,PAUSE_BIT),(input.cycle,CYCLE_BIT)].iter() {
+        if on {
+            bits |= bit;
+        }
+    }
+    return bits;
+}
+
+pub fn decode(bits:u8) -> Input {
+    Input {
+        up: bits & UP_BIT != 0,
+        down: bits & DOWN_BIT != 0,
+        left: bits & LEFT_BIT != 0,
+        right: bits & RIGHT_BIT != 0,
+        bomb: bits & BOMB_BIT != 0,
+        cycle: bits & CYCLE_BIT != 0,
+        pause: bits & PAUSE_BIT != 0,
+    }
+}
+
+// Map seed, level, starting lives and the `--map` played on plus every tick input, run length encoded
+// since held keys repeat for many ticks.
+// File layout: "BMRP", version byte, seed as u64 LE, level as u64 LE, player count byte, lives byte,
+// map hash as u64 LE, then runs of (one input bits byte per player, u16 LE count).
+#[derive(Clone,Debug,PartialEq)]
+pub struct Replay {
+    pub seed:u64,
+    pub level:usize,
+    pub players:usize,
+    pub lives:usize,
+    pub map:u64, // `Map::hash` of the custom map, NO_MAP for the level's own.
+    pub runs:Vec<(Vec<u8>,u16)>,
+}
+
+impl Replay {
+    pub fn new(seed:u64,level:usize,players:usize,lives:usize,map:Option<&Map>) -> Self {
+        let map = map.map_or(NO_MAP, Map::hash);
+        let runs = vec![];
+        Self { seed, level, players, lives, map, runs }
+    }
+
+    // Inputs only play out the same on the map they were recorded on.
+    pub fn check_map(&self,name:&str,map:Option<&Map>) -> io::Result<()> {
+        let msg = match map {
+            None if self.map != NO_MAP => "recorded on a custom map, play it with the same --map",
+            Some(_) if self.map == NO_MAP => "recorded on the level's own map, play it without --map",
+            Some(map) if self.map != map.hash() => "recorded on a different map than the one given with --map",
+            _ => return Ok(()),
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", name, msg)))
+    }
+
+    pub fn push(&mut self,inputs:&[Input]){
+        let bits:Vec<u8> = inputs.iter().map(encode).collect();
+        match self.runs.last_mut() {
+            Some((last,count)) if *last == bits && *count < u16::MAX => {*count += 1}
+            _ => {self.runs.push((bits,1))}
+        }
+    }
+
+    pub fn save(&self,path:&str) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path:&str) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Replay::from_bytes(path, &bytes)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.level as u64).to_le_bytes());
+        bytes.push(self.players as u8);
+        bytes.push(self.lives as u8);
+        bytes.extend_from_slice(&self.map.to_le_bytes());
+        for (bits,count) in &self.runs {
+            bytes.extend_from_slice(bits);
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+
+    // Errors read `name: what`.
+    pub fn from_bytes(name:&str,bytes:&[u8]) -> io::Result<Self> {
+        let invalid = |msg:&str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, msg));
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        if bytes[4] != VERSION {
+            return Err(invalid("unsupported replay version"));
+        }
+        let mut seed_bytes = [0_u8;8];
+        seed_bytes.copy_from_slice(&bytes[5..13]);
+        let seed = u64::from_le_bytes(seed_bytes);
+        let mut level_bytes = [0_u8;8];
+        level_bytes.copy_from_slice(&bytes[13..21]);
+        let level = match usize::try_from(u64::from_le_bytes(level_bytes)) {
+            Ok(level) if level > 0 => level,
+            _ => return Err(invalid("bad level")),
+        };
+        let players = bytes[21] as usize;
+        if players == 0 || players > MAX_PLAYERS {
+            return Err(invalid("bad player count"));
+        }
+        let lives = bytes[22] as usize;
+        if lives == 0 {
+            return Err(invalid("bad lives"));
+        }
+        let mut map_bytes = [0_u8;8];
+        map_bytes.copy_from_slice(&bytes[23..31]);
+        let map = u64::from_le_bytes(map_bytes);
+        let body = &bytes[HEADER_LEN..];
+        let run_len = players + 2;
+        if !body.len().is_multiple_of(run_len) {
+            return Err(invalid("truncated input data"));
+        }
+        let runs = body.chunks(run_len).map(|run| (run[..players].to_vec(), u16::from_le_bytes([run[players],run[players+1]]))).collect();
+        Ok(Self { seed, level, players, lives, map, runs })
+    }
+}
+
+// Hands a recorded replay back one tick at a time.
+#[derive(Clone,Debug)]
+pub struct Playback {
+    pub replay:Replay,
+    run:usize,
+    count:u16,
+}
+
+impl Playback {
+    pub fn new(replay:Replay) -> Self {
+        let run = 0;
+        let count = 0;
+        Self { replay, run, count }
+    }
+
+    pub fn next(&mut self) -> Option<Vec<Input>> {
+        let (bits,count) = self.replay.runs.get(self.run)?;
+        let inputs = bits.iter().map(|&bits| decode(bits)).collect();
+        self.count += 1;
+        if self.count >= *count {
+            self.run += 1;
+            self.count = 0;
+        }
+        Some(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Sim,TICK};
+
+    const TICKS:usize = 900;
+
+    // Walks a square and drops a bomb now and then, enough to move, burn and change the map.
+    fn scripted(tick:usize) -> Input {
+        let side = tick / 45 % 4;
+        Input { right: side == 0, down: side == 1, left: side == 2, up: side == 3, bomb: tick.is_multiple_of(150), ..Input::default() }
+    }
+
+    fn recorded() -> (Replay,Vec<Input>,u64) {
+        let mut sim = Sim::new(42, 1, 3);
+        let mut replay = Replay::new(sim.seed, sim.level, 1, 3, None);
+        let mut inputs = vec![];
+        for tick in 0..TICKS {
+            let input = [scripted(tick)];
+            replay.push(&input);
+            sim.step(&input, TICK);
+            inputs.push(input[0]);
+        }
+        (replay,inputs,sim.hash())
+    }
+
+    #[test]
+    fn decoded_replay_plays_out_the_same(){
+        let (replay,inputs,hash) = recorded();
+        let decoded = Replay::from_bytes("test", &replay.to_bytes()).unwrap();
+        assert_eq!(decoded, replay);
+        let mut sim = Sim::at_level(decoded.seed, decoded.level, decoded.players, decoded.lives);
+        let mut playback = Playback::new(decoded);
+        let mut played = vec![];
+        while let Some(input) = playback.next() {
+            sim.step(&input, TICK);
+            played.push(input[0]);
+        }
+        assert_eq!(played, inputs);
+        assert_eq!(sim.hash(), hash);
+    }
+
+    #[test]
+    fn every_input_survives_its_bits(){
+        for bits in 0..64 {
+            assert_eq!(encode(&decode(bits)), bits);
+        }
+    }
+
+    fn rejected(bytes:&[u8]) -> String {
+        Replay::from_bytes("test", bytes).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn rejects_wrong_magic(){
+        let mut bytes = recorded().0.to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(rejected(&bytes), "test: not a replay file");
+    }
+
+    #[test]
+    fn rejects_wrong_version(){
+        let mut bytes = recorded().0.to_bytes();
+        bytes[4] = VERSION + 1;
+        assert_eq!(rejected(&bytes), "test: unsupported replay version");
+    }
+
+    #[test]
+    fn rejects_truncated_data(){
+        let bytes = recorded().0.to_bytes();
+        assert_eq!(rejected(&bytes[..bytes.len()-1]), "test: truncated input data");
+        assert_eq!(rejected(&bytes[..HEADER_LEN-1]), "test: not a replay file");
+    }
+
+    #[test]
+    fn levels_past_a_byte_read_back(){
+        let mut replay = Replay::new(42, 300, 2, 3, None);
+        replay.push(&[Input::default();2]);
+        assert_eq!(Replay::from_bytes("test", &replay.to_bytes()).unwrap(), replay);
+    }
+
+    #[test]
+    fn rejects_level_zero(){
+        let bytes = Replay::new(42, 0, 1, 3, None).to_bytes();
+        assert_eq!(rejected(&bytes), "test: bad level");
+    }
+
+    #[test]
+    fn refuses_another_map(){
+        let map = Map::parse("test", "#######\n#1....#\n#.....#\n#######\n").unwrap();
+        let other = Map::parse("test", "#######\n#1..+.#\n#.....#\n#######\n").unwrap();
+        let replay = Replay::new(42, 1, 1, 3, Some(&map));
+        let decoded = Replay::from_bytes("test", &replay.to_bytes()).unwrap();
+        assert!(decoded.check_map("test", Some(&map)).is_ok());
+        assert_eq!(decoded.check_map("test", Some(&other)).unwrap_err().to_string(), "test: recorded on a different map than the one given with --map");
+        assert_eq!(decoded.check_map("test", None).unwrap_err().to_string(), "test: recorded on a custom map, play it with the same --map");
+        let own = Replay::new(42, 1, 1, 3, None);
+        assert!(own.check_map("test", None).is_ok());
+        assert_eq!(own.check_map("test", Some(&map)).unwrap_err().to_string(), "test: recorded on the level's own map, play it without --map");
+    }
+}
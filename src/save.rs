@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use raylib::prelude::*;
+use serde::{Serialize,Deserialize};
+use crate::sim::Sim;
+use crate::bot::Difficulty;
+use crate::config::config_path;
+
+pub const SAVE_VERSION:u32 = 10; // Bump whenever Sim, anything it holds or the saved bots change shape.
+pub const SAVE_FILE:&str = "bomber_man.sav"; // In the user config directory with the settings.
+
+// Mirrors of the raylib types so sim objects holding them can derive serde.
+#[derive(Serialize,Deserialize)]
+#[serde(remote = "Rectangle")]
+pub struct RectangleDef {
+    pub x:f32,
+    pub y:f32,
+    pub width:f32,
+    pub height:f32,
+}
+
+#[derive(Serialize,Deserialize)]
+#[serde(remote = "Color")]
+pub struct ColorDef {
+    pub r:u8,
+    pub g:u8,
+    pub b:u8,
+    pub a:u8,
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version:u32,
+}
+
+#[derive(Deserialize)]
+struct SaveGame {
+    sim:Sim,
+    bots:Vec<Difficulty>,
+}
+
+#[derive(Serialize)]
+struct SaveGameRef<'a> {
+    version:u32,
+    sim:&'a Sim,
+    bots:&'a [Difficulty], // The last player slots are the bots', as `Game::set_bots` hands them out.
+}
+
+pub fn save_exists() -> bool {
+    config_path(SAVE_FILE).exists()
+}
+
+pub fn save_game(sim:&Sim,bots:&[Difficulty]) -> io::Result<()> {
+    let path = config_path(SAVE_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, to_json(sim, bots)?)
+}
+
+pub fn load_game() -> io::Result<(Sim,Vec<Difficulty>)> {
+    let json = fs::read_to_string(config_path(SAVE_FILE))?;
+    from_json(&json)
+}
+
+fn to_json(sim:&Sim,bots:&[Difficulty]) -> io::Result<String> {
+    let save = SaveGameRef { version: SAVE_VERSION, sim, bots };
+    Ok(serde_json::to_string(&save)?)
+}
+
+fn from_json(json:&str) -> io::Result<(Sim,Vec<Difficulty>)> {
+    // Read the version on its own first so an old save is refused instead of half parsed.
+    let header:SaveHeader = serde_json::from_str(json)?;
+    if header.version != SAVE_VERSION {
+        let msg = format!("save version {} is not supported, expected {}", header.version, SAVE_VERSION);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+    let save:SaveGame = serde_json::from_str(json)?;
+    if save.sim.level == 0 { // Levels count from one.
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "save has no level 0"));
+    }
+    Ok((save.sim,save.bots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Input,TICK,LIFES};
+
+    #[test]
+    fn saved_game_loads_back(){
+        let mut sim = Sim::new(3, 1, LIFES);
+        for _ in 0..200 {
+            sim.step(&[Input { right: true, ..Input::default() }], TICK);
+        }
+        let (loaded,bots) = from_json(&to_json(&sim, &[]).unwrap()).unwrap();
+        assert_eq!(loaded.hash(), sim.hash());
+        assert!(bots.is_empty());
+    }
+
+    #[test]
+    fn saved_battle_keeps_its_bots(){
+        let mut sim = Sim::new(3, 3, LIFES);
+        for _ in 0..200 {
+            sim.step(&[Input::default();3], TICK);
+        }
+        let bots = [Difficulty::Hard,Difficulty::Easy];
+        let (loaded,loaded_bots) = from_json(&to_json(&sim, &bots).unwrap()).unwrap();
+        assert_eq!(loaded.hash(), sim.hash());
+        assert_eq!(loaded_bots, bots);
+    }
+
+    #[test]
+    fn rejects_another_save_version(){
+        let json = to_json(&Sim::new(3, 1, LIFES), &[]).unwrap();
+        let other = json.replacen(&format!("\"version\":{}", SAVE_VERSION), &format!("\"version\":{}", SAVE_VERSION + 1), 1);
+        assert_ne!(other, json);
+        let err = from_json(&other).err().expect("another save version is refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), format!("save version {} is not supported, expected {}", SAVE_VERSION + 1, SAVE_VERSION));
+    }
+
+    #[test]
+    fn rejects_level_zero(){
+        let json = to_json(&Sim::new(3, 1, LIFES), &[]).unwrap();
+        let zero = json.replacen("\"level\":1", "\"level\":0", 1);
+        assert_ne!(zero, json);
+        let err = from_json(&zero).err().expect("level 0 is refused");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::config::*;
+use crate::menu::OptionItem;
+use crate::sim::LIFES;
+
+pub const SETTINGS_FILE:&str = "settings.cfg";
+
+const VOLUME_STEP:u32 = 10; // Percent per press on the options screen.
+pub const WINDOW_SCALES:[u32;6] = [1,2,3,4,5,6]; // Multiples of the native 16 px tile resolution.
+pub const FPS_CAPS:[u32;5] = [30,60,120,144,0]; // 0 leaves the frame rate uncapped.
+const MAX_LIVES:usize = 9;
+
+#[derive(PartialEq,Clone,Copy,Debug)]
+pub struct Settings {
+    pub master_volume:u32, // Percent, like the other volumes.
+    pub sfx_volume:u32,
+    pub music_volume:u32,
+    pub fullscreen:bool,
+    pub window_scale:usize, // Index into WINDOW_SCALES.
+    pub pixel_perfect:bool, // Whole multiples of the native resolution only, otherwise stretch as far as fits.
+    pub vsync:bool,
+    pub fps_cap:usize, // Index into FPS_CAPS.
+    pub danger_overlay:bool,
+    pub lives:usize, // Lives at the start of a solo game, battles are always one.
+}
+
+fn on_off(on:bool) -> String {
+    if on {"ON".to_string()} else {"OFF".to_string()}
+}
+
+fn parse_bool(val:&str) -> Option<bool> {
+    match val {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Steps through `len` choices without wrapping.
+fn step_index(index:usize,step:isize,len:usize) -> usize {
+    (index as isize + step).clamp(0, len as isize - 1) as usize
+}
+
+fn step_volume(volume:u32,step:isize) -> u32 {
+    (volume as isize + step * VOLUME_STEP as isize).clamp(0, 100) as u32
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        let master_volume = 100;
+        let sfx_volume = 100;
+        let music_volume = 100;
+        let fullscreen = false;
+        let window_scale = WINDOW_SCALES.iter().position(|&scale| scale == 3).unwrap();
+        let pixel_perfect = true;
+        let vsync = true;
+        let fps_cap = FPS_CAPS.iter().position(|&fps| fps == 0).unwrap();
+        let danger_overlay = false;
+        let lives = LIFES;
+        Self { master_volume, sfx_volume, music_volume, fullscreen, window_scale, pixel_perfect, vsync, fps_cap, danger_overlay, lives }
+    }
+
+    pub fn load() -> Self {
+        match read_config(SETTINGS_FILE) {
+            Ok(pairs) => Settings::from_pairs(&pairs),
+            Err(_) => Settings::new(),
+        }
+    }
+
+    // Defaults overridden by the config lines, bad lines are reported and skipped.
+    fn from_pairs(pairs:&[(usize,String,String)]) -> Self {
+        let mut settings = Settings::new();
+        for (line,key,val) in pairs {
+            let percent = val.parse::<u32>().ok().filter(|&volume| volume <= 100);
+            let ok = match key.as_str() {
+                "master_volume" => percent.map(|volume| settings.master_volume = volume),
+                "sfx_volume" => percent.map(|volume| settings.sfx_volume = volume),
+                "music_volume" => percent.map(|volume| settings.music_volume = volume),
+                "fullscreen" => parse_bool(val).map(|on| settings.fullscreen = on),
+                "window_scale" => val.parse().ok().and_then(|scale:u32| WINDOW_SCALES.iter().position(|&s| s == scale)).map(|n| settings.window_scale = n),
+                "pixel_perfect" => parse_bool(val).map(|on| settings.pixel_perfect = on),
+                "vsync" => parse_bool(val).map(|on| settings.vsync = on),
+                "fps_cap" => val.parse().ok().and_then(|fps:u32| FPS_CAPS.iter().position(|&f| f == fps)).map(|n| settings.fps_cap = n),
+                "danger_overlay" => parse_bool(val).map(|on| settings.danger_overlay = on),
+                "lives" => val.parse::<usize>().ok().filter(|lives| (1..=MAX_LIVES).contains(lives)).map(|lives| settings.lives = lives),
+                _ => {eprintln!("{}:{}: unknown setting `{}`",SETTINGS_FILE,line,key); continue}
+            };
+            if ok.is_none() {
+                eprintln!("{}:{}: bad value `{}` for `{}`",SETTINGS_FILE,line,val,key);
+            }
+        }
+        settings
+    }
+
+    fn to_pairs(self) -> Vec<(String,String)> {
+        let pairs = [
+            ("master_volume",self.master_volume.to_string()),
+            ("sfx_volume",self.sfx_volume.to_string()),
+            ("music_volume",self.music_volume.to_string()),
+            ("fullscreen",self.fullscreen.to_string()),
+            ("window_scale",WINDOW_SCALES[self.window_scale].to_string()),
+            ("pixel_perfect",self.pixel_perfect.to_string()),
+            ("vsync",self.vsync.to_string()),
+            ("fps_cap",FPS_CAPS[self.fps_cap].to_string()),
+            ("danger_overlay",self.danger_overlay.to_string()),
+            ("lives",self.lives.to_string()),
+        ];
+        pairs.iter().map(|(key,val)| (key.to_string(),val.clone())).collect()
+    }
+
+    pub fn save(&self){
+        if let Err(err) = write_config(SETTINGS_FILE, &self.to_pairs()) {
+            eprintln!("failed to save settings: {}",err);
+        }
+    }
+
+    // Left and right on an options row, switches flip either way.
+    pub fn change(&mut self,item:OptionItem,step:isize){
+        match item {
+            OptionItem::MasterVolume => self.master_volume = step_volume(self.master_volume, step),
+            OptionItem::SfxVolume => self.sfx_volume = step_volume(self.sfx_volume, step),
+            OptionItem::MusicVolume => self.music_volume = step_volume(self.music_volume, step),
+            OptionItem::Fullscreen => self.fullscreen = !self.fullscreen,
+            OptionItem::WindowSize => self.window_scale = step_index(self.window_scale, step, WINDOW_SCALES.len()),
+            OptionItem::PixelPerfect => self.pixel_perfect = !self.pixel_perfect,
+            OptionItem::VSync => self.vsync = !self.vsync,
+            OptionItem::FpsCap => self.fps_cap = step_index(self.fps_cap, step, FPS_CAPS.len()),
+            OptionItem::DangerOverlay => self.danger_overlay = !self.danger_overlay,
+            OptionItem::Lives => self.lives = (self.lives as isize + step).clamp(1, MAX_LIVES as isize) as usize,
+            OptionItem::Controls | OptionItem::Back => {}
+        }
+    }
+
+    // What an options row shows after its label, None for rows that only open something.
+    pub fn value(&self,item:OptionItem) -> Option<String> {
+        let value = match item {
+            OptionItem::MasterVolume => format!("{}%",self.master_volume),
+            OptionItem::SfxVolume => format!("{}%",self.sfx_volume),
+            OptionItem::MusicVolume => format!("{}%",self.music_volume),
+            OptionItem::Fullscreen => on_off(self.fullscreen),
+            OptionItem::WindowSize => format!("{}X",WINDOW_SCALES[self.window_scale]),
+            OptionItem::PixelPerfect => on_off(self.pixel_perfect),
+            OptionItem::VSync => on_off(self.vsync),
+            OptionItem::FpsCap => match FPS_CAPS[self.fps_cap] {
+                0 => "OFF".to_string(),
+                fps => fps.to_string(),
+            },
+            OptionItem::DangerOverlay => on_off(self.danger_overlay),
+            OptionItem::Lives => self.lives.to_string(),
+            OptionItem::Controls | OptionItem::Back => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Config lines the way `read_config` hands them back.
+    fn lines(pairs:&[(&str,&str)]) -> Vec<(usize,String,String)> {
+        pairs.iter().enumerate().map(|(n,(key,val))| (n+1,key.to_string(),val.to_string())).collect()
+    }
+
+    #[test]
+    fn saved_settings_read_back(){
+        let mut settings = Settings::new();
+        settings.master_volume = 40;
+        settings.sfx_volume = 0;
+        settings.fullscreen = true;
+        settings.window_scale = 0;
+        settings.pixel_perfect = false;
+        settings.vsync = false;
+        settings.fps_cap = 3;
+        settings.danger_overlay = true;
+        settings.lives = MAX_LIVES;
+        let pairs:Vec<(usize,String,String)> = settings.to_pairs().into_iter().enumerate().map(|(n,(key,val))| (n+1,key,val)).collect();
+        assert_eq!(Settings::from_pairs(&pairs), settings);
+    }
+
+    #[test]
+    fn bad_values_and_unknown_keys_keep_the_defaults(){
+        let loaded = Settings::from_pairs(&lines(&[
+            ("master_volume","101"),
+            ("music_volume","loud"),
+            ("lives","0"),
+            ("window_scale","90"),
+            ("fps_cap","75"),
+            ("vsync","yes"),
+            ("difficulty","hard"),
+        ]));
+        assert_eq!(loaded, Settings::new());
+        assert_eq!(Settings::from_pairs(&lines(&[("lives",&(MAX_LIVES + 1).to_string())])), Settings::new());
+        let edges = Settings::from_pairs(&lines(&[("sfx_volume","100"),("music_volume","0"),("lives","1")]));
+        assert_eq!((edges.sfx_volume,edges.music_volume,edges.lives), (100,0,1));
+    }
+
+    #[test]
+    fn changes_stay_in_range(){
+        let mut settings = Settings::new();
+        settings.change(OptionItem::MasterVolume, 1);
+        assert_eq!(settings.master_volume, 100);
+        for _ in 0..20 {
+            settings.change(OptionItem::SfxVolume, -1);
+            settings.change(OptionItem::Lives, 1);
+            settings.change(OptionItem::WindowSize, 1);
+            settings.change(OptionItem::FpsCap, -1);
+        }
+        assert_eq!(settings.sfx_volume, 0);
+        assert_eq!(settings.lives, MAX_LIVES);
+        assert_eq!(settings.window_scale, WINDOW_SCALES.len() - 1, "the last size doesn't wrap round to the first");
+        assert_eq!(settings.fps_cap, 0);
+        for _ in 0..20 {
+            settings.change(OptionItem::Lives, -1);
+        }
+        assert_eq!(settings.lives, 1);
+        settings.change(OptionItem::MusicVolume, -1);
+        assert_eq!(settings.music_volume, 100 - VOLUME_STEP);
+    }
+}
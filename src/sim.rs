@@ -0,0 +1,655 @@
+use crate::grid::*;
+use crate::player::*;
+use crate::enemy::*;
+use crate::cell::*;
+use crate::level::*;
+use crate::map::{Map,MAP_SIZE};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Serialize,Deserialize};
+use crate::fnv::Fnv;
+
+pub const TICK:f32 = 1_f32/60_f32; // Fixed simulation step, 60 Hz.
+pub const MAX_FRAME_TIME:f32 = 0.25_f32; // Clamp long frames so a stall can't queue up endless ticks.
+pub const MAX_PLAYERS:usize = 4;
+pub const LIFES:usize = 3;
+const BATTLE_LIFES:usize = 1; // A battle is one knockout per player.
+const KILL_SCORE:usize = 500; // Points for blowing up another player in a battle.
+const ENEMY_SPAWN_DIST:usize = 8; // Tiles kept clear of enemies around the player's corner.
+const ENEMY_SEED:u64 = 0x656e656d79; // Mixed into the map seed so enemy placement doesn't mirror the map rng.
+const EXIT_SCORE:usize = 1000; // Points scored in one level that open the exit even with enemies left.
+pub const COUNTDOWN_TICKS:usize = 84; // The world holds still this long at the start of a level, 1.4 seconds.
+
+// Per tick input for one player, filled from the keyboard by the game or by anything else in headless runs.
+#[derive(PartialEq,Clone,Debug,Copy,Default)]
+pub struct Input {
+    pub up:bool,
+    pub down:bool,
+    pub left:bool,
+    pub right:bool,
+    pub bomb:bool,
+    pub cycle:bool,
+    pub pause:bool,
+}
+
+// Things the simulation wants the presentation layer to play.
+#[derive(PartialEq,Clone,Debug,Copy)]
+pub enum SoundEvent {
+    Explosion,
+    Bonus,
+    Upgrade,
+    EnemyDeath,
+    Punch,
+    Win,
+    GameOver, // Queued by the game, the sim itself never ends a game.
+}
+
+// Pure game logic, no window or audio device needed to step it.
+#[derive(Serialize,Deserialize)]
+pub struct Sim {
+    pub seed:u64,
+    pub level:usize,
+    pub grid:Grid,
+    pub players:Vec<Player>,
+    pub enemies:Vec<Enemy>,
+    pub start_score:usize, // Player one's score when the level began.
+    pub time_left:Option<f32>, // Campaign levels only.
+    pub cleared:bool,
+    pub countdown:usize, // Ticks left before the players spawn in.
+}
+
+// The enemies a map places where it wants them, then one per roster entry on random open
+// tiles well away from the player's spawn.
+pub fn spawn_enemies(grid:&Grid,seed:u64,placed:&[(EnemyKind,Position)],roster:&[EnemyKind]) -> Vec<Enemy> {
+    let mut rng = StdRng::seed_from_u64(seed ^ ENEMY_SEED);
+    let mut enemies = vec![];
+    for &(kind,(i,j)) in placed {
+        enemies.push(Enemy::new(kind, i, j, &mut rng));
+    }
+    let (si,sj) = grid.spawns[0];
+    let mut open = vec![];
+    for (i,rows) in grid.cells.iter().enumerate() {
+        for (j,cell) in rows.iter().enumerate() {
+            let taken = placed.iter().any(|&(_,at)| at == (i,j));
+            if cell.terrain == Terrain::Empty && !taken && i.abs_diff(si) + j.abs_diff(sj) >= ENEMY_SPAWN_DIST {
+                open.push((i,j));
+            }
+        }
+    }
+    for &kind in roster {
+        if open.is_empty() {
+            break;
+        }
+        let (i,j) = open.swap_remove(rng.gen_range(0..open.len()));
+        enemies.push(Enemy::new(kind, i, j, &mut rng));
+    }
+    return enemies;
+}
+
+impl Sim {
+    // One player is the normal game, two to four is a battle. `lives` is what a solo player
+    // starts with, in a battle everyone has one.
+    pub fn new(seed:u64,players:usize,lives:usize) -> Self {
+        Self::at_level(seed, 1, players, lives)
+    }
+
+    pub fn at_level(seed:u64,level:usize,players:usize,lives:usize) -> Self {
+        Self::on_map(seed, level, players, lives, None)
+    }
+
+    // Solo games play the campaign level, battles a noise map with no enemies, exit or clock.
+    // Every level gets its own map seed, the first one is the run's seed itself. A `custom` map
+    // stands in for the level's own and brings only the enemies placed on it.
+    pub fn on_map(seed:u64,level:usize,players:usize,lives:usize,custom:Option<&Map>) -> Self {
+        let battle = players > 1;
+        let def = campaign_level(level);
+        let map_seed = seed.wrapping_add(level.saturating_sub(1) as u64);
+        let map_seed = if battle || custom.is_some() {map_seed} else {def.map.seed(map_seed)};
+        let drops = if battle {&DEFAULT_DROPS} else {&def.drops};
+        let mut rng = StdRng::seed_from_u64(map_seed); // One rng for the map and its tile art so a seed replays the same map.
+        let map = match custom {
+            Some(custom) => {
+                let mut map = custom.clone();
+                map.fill_walls(&mut rng, drops);
+                map
+            }
+            None if battle => Map::noise(MAP_SIZE, &mut rng, drops),
+            None => def.map.build(def.size, &mut rng, drops).unwrap_or_else(|err| {
+                eprintln!("failed to load level {}, playing a noise map: {}",def.name,err);
+                Map::noise(def.size, &mut rng, drops)
+            }),
+        };
+        let mut grid = Grid::from_map(&map, &mut rng);
+        let lifes = if battle {BATTLE_LIFES} else {lives};
+        let (enemies,time_left) = if battle {
+            (vec![],None)
+        }else{
+            if grid.exit.is_none() {
+                grid.hide_exit(map_seed);
+            }
+            let roster = if custom.is_some() {&[]} else {def.roster};
+            (spawn_enemies(&grid, map_seed, &map.enemies, roster),Some(def.time_limit))
+        };
+        let spawns = &grid.spawns;
+        let players = (0..players.clamp(1,MAX_PLAYERS)).map(|slot| Player::new(slot, spawns[slot % spawns.len()], lifes)).collect();
+        let start_score = 0;
+        let cleared = false;
+        let countdown = COUNTDOWN_TICKS;
+        Self { seed, level, grid, players, enemies, start_score, time_left, cleared, countdown }
+    }
+
+    // Name of the campaign level, None in a battle.
+    pub fn level_name(&self) -> Option<&'static str> {
+        if self.is_battle() {None} else {Some(campaign_level(self.level).name)}
+    }
+
+    // The level after a cleared one, the player brings along what they have.
+    pub fn next_level(&self,custom:Option<&Map>) -> Self {
+        let mut sim = Self::on_map(self.seed, self.level + 1, self.players.len(), self.players[0].lifes, custom);
+        let spawns = &sim.grid.spawns;
+        sim.players = self.players.iter().map(|player| player.carry_over(spawns[player.slot % spawns.len()])).collect();
+        sim.start_score = sim.players[0].score;
+        return sim;
+    }
+
+    // `inputs` holds one entry per player, in slot order.
+    pub fn step(&mut self,inputs:&[Input],frame_time:f32){
+        if self.cleared { // Nothing moves once the player is through the exit.
+            return;
+        }
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return;
+        }
+        for player in self.players.iter_mut().filter(|player| player.state == State2::SPAWN) {
+            player.spawn(); // After the countdown, and straight after losing a life.
+        }
+        let alive:Vec<bool> = self.players.iter().map(|player| player.state == State2::ALIVE).collect();
+        for (player,input) in self.players.iter_mut().zip(inputs) {
+            player.update(input, &mut self.grid, frame_time);
+        }
+        self.credit_kills(&alive);
+        let targets:Vec<Position> = self.players.iter().filter(|player| player.state == State2::ALIVE).map(|player| player.get_position()).collect();
+        let mut pieces = vec![];
+        for enemy in self.enemies.iter_mut() {
+            if enemy.update(&mut self.grid, &targets, frame_time) {
+                self.players[0].temp_score += enemy.archetype().score; // Enemies only show up in solo games.
+                self.grid.events.push(SoundEvent::EnemyDeath);
+                pieces.extend(enemy.split());
+            }
+        }
+        self.enemies.extend(pieces);
+        self.touch_enemies();
+        self.run_clock(frame_time);
+        self.grid.update(frame_time);
+        if !self.grid.exit_open && !self.is_battle() && self.exit_earned() {
+            self.grid.exit_open = true;
+        }
+        self.reach_exit();
+    }
+
+    // Points player one scored in this level, counting the ones still being tallied.
+    pub fn level_score(&self) -> usize {
+        let player = &self.players[0];
+        (player.score + player.temp_score).saturating_sub(self.start_score)
+    }
+
+    // The exit opens once every enemy is dead or enough points were scored in the level.
+    fn exit_earned(&self) -> bool {
+        self.enemies.iter().all(|enemy| enemy.state != EnemyState::ALIVE) || self.level_score() >= EXIT_SCORE
+    }
+
+    // Stepping onto the open exit clears the level.
+    fn reach_exit(&mut self){
+        let player = &self.players[0];
+        if self.cleared || player.state != State2::ALIVE {
+            return;
+        }
+        if self.grid.get_collisions(player.get_position(), player.get_coll_shape()).5 {
+            self.cleared = true;
+            self.grid.events.push(SoundEvent::Win);
+        }
+    }
+
+    // Whoever planted the bomb that burned another player this tick scores for it.
+    fn credit_kills(&mut self,alive:&[bool]){
+        let burned:Vec<(usize,usize)> = self.players.iter()
+            .filter(|player| alive[player.slot] && player.state == State2::DYING)
+            .filter_map(|player| self.grid.burned_by(player.get_position(), player.get_coll_shape()).map(|owner| (owner,player.slot)))
+            .collect();
+        for (owner,slot) in burned {
+            if owner != slot && owner < self.players.len() {
+                self.players[owner].temp_score += KILL_SCORE;
+            }
+        }
+    }
+
+    // The level clock runs while the player is alive, when it runs out they lose a life and it starts over.
+    fn run_clock(&mut self,frame_time:f32){
+        let player = &mut self.players[0];
+        let time_left = match self.time_left.as_mut() {
+            Some(time_left) if player.state == State2::ALIVE => time_left,
+            _ => return,
+        };
+        *time_left -= frame_time;
+        if *time_left <= 0_f32 {
+            *time_left = campaign_level(self.level).time_limit;
+            player.kill();
+            self.grid.events.push(SoundEvent::Punch);
+        }
+    }
+
+    // Walking into a live enemy kills the player.
+    fn touch_enemies(&mut self){
+        for player in self.players.iter_mut().filter(|player| player.state == State2::ALIVE) {
+            let rec = player.get_coll_shape();
+            if self.enemies.iter().any(|enemy| enemy.state == EnemyState::ALIVE && overlaps(&enemy.get_coll_shape(), &rec)) {
+                player.kill();
+                self.grid.events.push(SoundEvent::Punch);
+            }
+        }
+    }
+
+    pub fn is_battle(&self) -> bool {
+        self.players.len() > 1
+    }
+
+    fn alive(&self) -> usize {
+        self.players.iter().filter(|player| player.state != State2::DEAD).count()
+    }
+
+    // Solo games end when the player is out of lives, battles when one player or nobody is left.
+    pub fn is_over(&self) -> bool {
+        if self.is_battle() {self.alive() <= 1} else {self.alive() == 0}
+    }
+
+    // Slot of the last player standing, None while the battle goes on or when it ended in a draw.
+    pub fn winner(&self) -> Option<usize> {
+        if !self.is_battle() || !self.is_over() {
+            return None;
+        }
+        self.players.iter().position(|player| player.state != State2::DEAD)
+    }
+
+    // Fingerprint of the cells, players and enemies, peers compare it every tick to catch a desync.
+    // Built from explicit little-endian bytes so peers built with different toolchains agree.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = Fnv::default();
+        hasher.write(&(self.countdown as u64).to_le_bytes());
+        for cell in self.grid.cells.iter().flatten() {
+            hasher.write(&cell.to_bytes());
+        }
+        for player in &self.players {
+            hasher.write(&player.rec2.x.to_le_bytes());
+            hasher.write(&player.rec2.y.to_le_bytes());
+            hasher.write(&[player.state.clone() as u8]);
+        }
+        for enemy in &self.enemies {
+            hasher.write(&enemy.rec2.x.to_le_bytes());
+            hasher.write(&enemy.rec2.y.to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    pub fn drain_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.grid.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bomb::*;
+    use crate::bonus::{Bonus,BonusType};
+    use crate::objects::{Block,State,Wall};
+    use crate::upgrade::UpgradeType;
+    use crate::path::{find_path,walker_cost};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    const SEED:u64 = 7;
+
+    // Blocks all over but for open ground `width` by `height` tiles in the top left, border included,
+    // with the countdown already over.
+    fn room(width:usize,height:usize) -> Sim {
+        battle_room(width, height, 1)
+    }
+
+    // The same room with everyone after player one standing where `put_player` puts them.
+    fn battle_room(width:usize,height:usize,players:usize) -> Sim {
+        let mut sim = Sim::new(SEED, players, LIFES);
+        sim.countdown = 0;
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let grid = &mut sim.grid;
+        for (i,rows) in grid.cells.iter_mut().enumerate() {
+            for (j,cell) in rows.iter_mut().enumerate() {
+                let open = i > 0 && j > 0 && i < width-1 && j < height-1;
+                *cell = Cell::new(if open {Terrain::Empty} else {Terrain::Block});
+                grid.game_objs[i][j] = if open {GameObjs::Default} else {GameObjs::Block(Block::new(i,j,&mut rng))};
+            }
+        }
+        grid.bonus_vec.clear();
+        grid.upgrade_vec.clear();
+        sim.enemies.clear();
+        sim
+    }
+
+    fn put(sim:&mut Sim,(i,j):Position,terrain:Terrain){
+        sim.grid.cells[i][j] = Cell::new(terrain);
+        sim.grid.game_objs[i][j] = match terrain {
+            Terrain::Wall => GameObjs::Wall(Wall::new(i,j)),
+            Terrain::Block => GameObjs::Block(Block::new(i,j,&mut StdRng::seed_from_u64(SEED))),
+            Terrain::Empty => GameObjs::Default,
+        };
+    }
+
+    fn put_player(sim:&mut Sim,slot:usize,(i,j):Position){
+        let player = &mut sim.players[slot];
+        player.rec2.x = i as f32 * TILE_SIZE;
+        player.rec2.y = j as f32 * TILE_SIZE;
+        player.prev_rec2 = player.rec2;
+    }
+
+    // Player one plays `input`, everyone else stands still.
+    fn run(sim:&mut Sim,input:Input,ticks:usize){
+        let mut inputs = vec![Input::default();sim.players.len()];
+        inputs[0] = input;
+        for _ in 0..ticks {
+            sim.step(&inputs, TICK);
+        }
+    }
+
+    // Steps through the spawn animation until every player can move.
+    fn spawn_in(sim:&mut Sim){
+        for _ in 0..600 {
+            if sim.players.iter().all(|player| player.state == State2::ALIVE) {
+                return;
+            }
+            run(sim, Input::default(), 1);
+        }
+        panic!("the players never spawned");
+    }
+
+    // A bomb the way player one plants it, going off after `fuse` seconds.
+    fn place_bomb(sim:&mut Sim,at:Position,power:usize,fuse:f32){
+        place_bomb_of(sim, 0, at, power, fuse);
+    }
+
+    fn place_bomb_of(sim:&mut Sim,owner:usize,(i,j):Position,power:usize,fuse:f32){
+        let mut bomb = Bomb::new();
+        bomb.set_position(i, j);
+        bomb.owner = owner;
+        bomb.power = power;
+        bomb.exploading_time = fuse;
+        sim.grid.cells[i][j].bomb = true;
+        sim.grid.game_objs[i][j] = GameObjs::Bomb(bomb);
+    }
+
+    #[test]
+    fn flames_stop_at_walls_and_blocks(){
+        let mut sim = room(9, 7);
+        put(&mut sim, (3,3), Terrain::Wall);
+        put(&mut sim, (5,3), Terrain::Block);
+        place_bomb(&mut sim, (4,3), 3, TICK);
+        run(&mut sim, Input::default(), 10);
+        let cells = &sim.grid.cells;
+        assert_eq!(cells[4][3].flame, Flame::Explosion);
+        assert!(cells[4][2].is_flame() && cells[4][1].is_flame(), "flame runs up to the border");
+        assert!(cells[4][4].is_flame() && cells[4][5].is_flame(), "flame runs down to the border");
+        assert!(!cells[3][3].is_flame() && !cells[2][3].is_flame(), "the breakable wall stops the flame");
+        assert!(!cells[5][3].is_flame() && !cells[6][3].is_flame(), "the block stops the flame");
+        assert!(matches!(sim.grid.game_objs[3][3], GameObjs::Wall(wall) if wall.state == State::EXPLOADING), "the wall next to the blast breaks");
+    }
+
+    #[test]
+    fn flames_set_off_the_next_bomb(){
+        let mut sim = room(9, 6);
+        let fuse = Bomb::new().exploading_time;
+        place_bomb(&mut sim, (2,3), 2, TICK);
+        place_bomb(&mut sim, (4,3), 1, fuse);
+        run(&mut sim, Input::default(), 10);
+        match sim.grid.game_objs[4][3] {
+            GameObjs::Bomb(bomb) => {
+                assert_eq!(bomb.state, State::EXPLOADING);
+                assert!(bomb.exploading_time > 0_f32, "went off well before its own fuse");
+            }
+            obj => panic!("expected the second bomb, found {:?}", obj),
+        }
+        assert_eq!(sim.grid.cells[5][3].flame, Flame::EndRight, "the second bomb throws its own flames");
+    }
+
+    #[test]
+    fn standing_on_a_bomb_costs_a_life(){
+        let mut sim = room(7, 4);
+        let lifes = sim.players[0].lifes;
+        spawn_in(&mut sim);
+        run(&mut sim, Input { bomb: true, ..Input::default() }, 1);
+        assert!(sim.grid.cells[1][1].bomb);
+        run(&mut sim, Input::default(), (Bomb::new().exploading_time / TICK) as usize + 10);
+        assert_eq!(sim.players[0].state, State2::DYING);
+        assert_eq!(sim.players[0].lifes, lifes - 1);
+    }
+
+    #[test]
+    fn walking_over_pickups_takes_them(){
+        let mut sim = room(7, 4);
+        sim.grid.cells[2][1].item = Item::Bonus(BonusType::GoldCoin);
+        sim.grid.cells[3][1].item = Item::Upgrade(UpgradeType::BlackBomb, 2);
+        spawn_in(&mut sim);
+        let right = Input { right: true, ..Input::default() };
+        for _ in 0..300 {
+            if sim.players[0].get_position() == (4,1) {
+                break;
+            }
+            run(&mut sim, right, 1);
+        }
+        run(&mut sim, Input::default(), 1);
+        let player = &sim.players[0];
+        assert_eq!(player.get_position(), (4,1));
+        assert_eq!(player.gold_coin, 1);
+        assert_eq!(player.score + player.temp_score, Bonus::get_val(BonusType::GoldCoin));
+        assert_eq!(player.black_bombs, 2);
+        assert_eq!(sim.grid.cells[2][1].item, Item::Default);
+        assert_eq!(sim.grid.cells[3][1].item, Item::Default);
+        assert!(sim.drain_events().contains(&SoundEvent::Upgrade));
+    }
+
+    // Walks and plants bombs on a fixed script so two runs get the same inputs.
+    fn scripted(tick:usize) -> Input {
+        let right = (tick / 60).is_multiple_of(2);
+        Input { right, down: !right, bomb: tick.is_multiple_of(90), ..Input::default() }
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_hash_the_same(){
+        let play = |script:fn(usize) -> Input| {
+            let mut sim = Sim::new(SEED, 1, LIFES);
+            for tick in 0..600 {
+                sim.step(&[script(tick)], TICK);
+            }
+            sim.hash()
+        };
+        assert_eq!(play(scripted), play(scripted));
+        assert_ne!(play(scripted), play(|_| Input::default()), "other inputs end up somewhere else");
+    }
+
+    #[test]
+    fn a_seed_always_generates_the_same_map(){
+        let cells = |seed:u64| Sim::new(seed, 1, LIFES).grid.cells;
+        assert_eq!(cells(SEED), cells(SEED));
+        assert_ne!(cells(SEED), cells(SEED + 1));
+    }
+
+    #[test]
+    fn burning_another_player_scores_for_the_bomb_owner(){
+        let mut sim = battle_room(7, 4, 2);
+        put_player(&mut sim, 1, (3,1));
+        spawn_in(&mut sim);
+        place_bomb_of(&mut sim, 0, (2,1), 1, TICK);
+        run(&mut sim, Input::default(), 10);
+        assert!(sim.players.iter().all(|player| player.state == State2::DYING));
+        let points = |slot:usize| sim.players[slot].score + sim.players[slot].temp_score;
+        assert_eq!(points(0), KILL_SCORE, "the owner scores for the other player, not for themselves");
+        assert_eq!(points(1), 0);
+    }
+
+    // Half a tile over from the bottom row into a flame, the border right under it is checked too.
+    #[test]
+    fn kills_next_to_the_border_are_credited(){
+        let mut sim = battle_room(7, 4, 2);
+        put_player(&mut sim, 1, (4,2));
+        spawn_in(&mut sim);
+        sim.players[1].rec2.x += TILE_SIZE / 2_f32;
+        place_bomb_of(&mut sim, 0, (5,1), 1, TICK);
+        run(&mut sim, Input::default(), 10);
+        assert_eq!(sim.players[1].state, State2::DYING);
+        assert_eq!(sim.players[0].score + sim.players[0].temp_score, KILL_SCORE);
+    }
+
+    #[test]
+    fn live_bombs_are_capped_per_player(){
+        let mut sim = room(9, 5);
+        spawn_in(&mut sim);
+        let (bomb,fuse) = (Input { bomb: true, ..Input::default() },Bomb::new().exploading_time);
+        place_bomb(&mut sim, (5,3), 1, fuse);
+        place_bomb(&mut sim, (7,3), 1, fuse);
+        place_bomb_of(&mut sim, 1, (3,3), 1, fuse); // Somebody else's doesn't count.
+        run(&mut sim, bomb, 1);
+        assert!(sim.grid.cells[1][1].bomb, "the third bomb goes down");
+        sim.grid.rm_game_obj(1, 1);
+        place_bomb(&mut sim, (6,2), 1, fuse);
+        run(&mut sim, bomb, 1);
+        assert_eq!(sim.grid.live_bombs(0), MAX_LIVE_BOMBS);
+        assert!(!sim.grid.cells[1][1].bomb, "no fourth bomb");
+    }
+
+    // Only moves when map generation or the hashed state changes, never with the toolchain.
+    #[test]
+    fn hash_is_pinned_for_a_seed(){
+        assert_eq!(Sim::new(SEED, 1, LIFES).hash(), 0x70e2f1a66ea2b577);
+    }
+
+    #[test]
+    fn chasers_follow_the_shortest_path_to_the_player(){
+        let mut sim = room(9, 5);
+        for i in 1..7 {
+            put(&mut sim, (i,2), Terrain::Block);
+        }
+        spawn_in(&mut sim);
+        let mut rng = StdRng::seed_from_u64(SEED);
+        sim.enemies = vec![Enemy::new(EnemyKind::Chaser, 1, 3, &mut rng)];
+        let route = find_path(&sim.grid.cells, (1,3), (1,1), walker_cost).unwrap();
+        for _ in 0..1200 {
+            if sim.players[0].state != State2::ALIVE {
+                break;
+            }
+            run(&mut sim, Input::default(), 1);
+            assert!(route.contains(&sim.enemies[0].target), "left the route for {:?}", sim.enemies[0].target);
+        }
+        assert_eq!(sim.players[0].state, State2::DYING, "the chaser catches the player");
+    }
+
+    #[test]
+    fn touching_an_enemy_costs_a_life(){
+        let mut sim = room(7, 4);
+        spawn_in(&mut sim);
+        let mut rng = StdRng::seed_from_u64(SEED);
+        sim.enemies = vec![Enemy::new(EnemyKind::Walker, 1, 1, &mut rng)];
+        sim.drain_events();
+        run(&mut sim, Input::default(), 1);
+        assert_eq!(sim.players[0].state, State2::DYING);
+        assert_eq!(sim.players[0].lifes, LIFES - 1);
+        assert!(sim.drain_events().contains(&SoundEvent::Punch));
+    }
+
+    // Player one boxed in along the top row, the enemy of `kind` below a row of blocks.
+    fn enemy_room(height:usize,kind:EnemyKind,at:Position) -> Sim {
+        let mut sim = room(7, height);
+        for i in 1..6 {
+            put(&mut sim, (i,2), Terrain::Block);
+        }
+        sim.enemies = vec![Enemy::new(kind, at.0, at.1, &mut StdRng::seed_from_u64(SEED))];
+        sim
+    }
+
+    #[test]
+    fn ghosts_walk_through_breakable_walls(){
+        let mut sim = enemy_room(7, EnemyKind::Ghost, (2,4));
+        for i in 1..6 {
+            for j in 3..6 {
+                if (i,j) != (2,4) {
+                    put(&mut sim, (i,j), Terrain::Wall);
+                }
+            }
+        }
+        let mut on_wall = false;
+        for _ in 0..600 {
+            run(&mut sim, Input::default(), 1);
+            let (i,j) = sim.enemies[0].get_position();
+            assert_ne!(sim.grid.cells[i][j].terrain, Terrain::Block, "blocks still stop a ghost");
+            on_wall |= sim.grid.cells[i][j].terrain == Terrain::Wall;
+        }
+        assert!(on_wall, "the ghost never left its open tile");
+    }
+
+    #[test]
+    fn bomb_eaters_swallow_bombs(){
+        let mut sim = enemy_room(5, EnemyKind::BombEater, (1,3));
+        place_bomb(&mut sim, (2,3), 1, Bomb::new().exploading_time);
+        run(&mut sim, Input::default(), 120);
+        assert!(!sim.grid.cells[2][3].bomb);
+        assert!(!matches!(sim.grid.game_objs[2][3], GameObjs::Bomb(_)));
+        assert!(!sim.drain_events().contains(&SoundEvent::Explosion), "eaten, not set off");
+    }
+
+    #[test]
+    fn splitters_break_into_splitlings(){
+        let mut sim = enemy_room(5, EnemyKind::Splitter, (3,3));
+        place_bomb(&mut sim, (3,3), 1, TICK);
+        run(&mut sim, Input::default(), 10);
+        assert_eq!(sim.enemies[0].state, EnemyState::DYING);
+        let pieces = &sim.enemies[1..];
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces.iter().all(|piece| piece.kind == EnemyKind::Splitling && piece.state == EnemyState::ALIVE), "the blast that split it spares the pieces");
+    }
+
+    #[test]
+    fn the_exit_opens_once_the_enemies_are_dead(){
+        let mut sim = enemy_room(6, EnemyKind::Walker, (3,4));
+        sim.grid.cells[3][1].item = Item::Exit; // As if its wall was blown open.
+        sim.grid.exit = Some((3,1));
+        spawn_in(&mut sim);
+        let right = Input { right: true, ..Input::default() };
+        for _ in 0..300 {
+            if sim.players[0].get_position() == (3,1) {
+                break;
+            }
+            run(&mut sim, right, 1);
+        }
+        run(&mut sim, Input::default(), 30);
+        assert_eq!(sim.players[0].get_position(), (3,1));
+        assert!(!sim.grid.exit_open && !sim.cleared, "closed while the walker lives");
+        sim.enemies[0].state = EnemyState::DEAD;
+        run(&mut sim, Input::default(), 1);
+        assert!(sim.grid.exit_open && sim.cleared);
+        assert!(sim.drain_events().contains(&SoundEvent::Win));
+        let next = sim.next_level(None);
+        assert_eq!(next.level, 2);
+        assert_eq!(next.players[0].lifes, sim.players[0].lifes);
+    }
+
+    #[test]
+    fn the_exit_hides_under_a_wall(){
+        let sim = Sim::new(SEED, 1, LIFES);
+        let (i,j) = sim.grid.exit.expect("the level has an exit");
+        assert_eq!(sim.grid.cells[i][j].terrain, Terrain::Wall);
+        assert_eq!(sim.grid.cells[i][j].item, Item::Exit);
+        assert_eq!(Sim::new(SEED, 1, LIFES).grid.exit, Some((i,j)), "the seed picks the same wall");
+    }
+
+    #[test]
+    fn solo_players_start_with_the_lives_asked_for(){
+        assert_eq!(Sim::new(SEED, 1, 5).players[0].lifes, 5);
+        assert!(Sim::new(SEED, 3, 5).players.iter().all(|player| player.lifes == BATTLE_LIFES));
+        let sim = Sim::new(SEED, 1, 2);
+        assert_eq!(sim.next_level(None).players[0].lifes, 2, "the next level brings along what's left");
+    }
+}